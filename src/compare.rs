@@ -0,0 +1,80 @@
+use std::{
+    env,
+    io::{Read, Write, stdin},
+    process::{Command, Stdio},
+    thread,
+};
+
+use eyre::{Result, eyre};
+
+use crate::Args;
+
+const BACKENDS: &[&str] = &["o0", "o1", "o2", "llvm"];
+
+/// Run the program on every backend with the same flags and input, diffing against o0
+pub fn main(args: Args, f: &[u8]) -> Result<()> {
+    // stdin is read up front so every backend sees the same bytes
+    let mut input = Vec::new();
+    if args.random_input.is_none() && f.contains(&b',') {
+        stdin().read_to_end(&mut input)?;
+    }
+    let exe = env::current_exe()?;
+    let forwarded: Vec<String> = env::args()
+        .skip(1)
+        .filter(|arg| arg != "--compare-backends")
+        .collect();
+    let mut reference: Option<Vec<u8>> = None;
+    let mut diverged = Vec::new();
+    for backend in BACKENDS {
+        if *backend == "llvm" && Command::new("clang").arg("--version").output().is_err() {
+            eprintln!("llvm: skipped, clang not found");
+            continue;
+        }
+        let mut child = Command::new(&exe)
+            .arg(format!("--{backend}"))
+            .args(&forwarded)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let bytes = input.clone();
+        // a program that stops reading early breaks the pipe, which is fine
+        let feeder = thread::spawn(move || stdin.write_all(&bytes));
+        let output = child.wait_with_output()?;
+        let _ = feeder.join();
+        if !output.status.success() {
+            eprintln!("{backend}: failed with {}", output.status);
+            diverged.push(*backend);
+            continue;
+        }
+        let output = output.stdout;
+        let Some(expected) = &reference else {
+            eprintln!("{backend}: reference, {} bytes", output.len());
+            reference = Some(output);
+            continue;
+        };
+        match expected.iter().zip(&output).position(|(a, b)| a != b) {
+            None if expected.len() == output.len() => eprintln!("{backend}: agrees"),
+            Some(i) => {
+                eprintln!(
+                    "{backend}: diverges at byte {i}, expected {:#04x}, got {:#04x}",
+                    expected[i], output[i]
+                );
+                diverged.push(*backend);
+            }
+            None => {
+                let i = expected.len().min(output.len());
+                eprintln!(
+                    "{backend}: diverges at byte {i}, expected {} bytes, got {}",
+                    expected.len(),
+                    output.len()
+                );
+                diverged.push(*backend);
+            }
+        }
+    }
+    if !diverged.is_empty() {
+        Err(eyre!("backends diverged: {}", diverged.join(", ")))?;
+    }
+    Ok(())
+}
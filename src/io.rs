@@ -0,0 +1,345 @@
+use clap::ValueEnum;
+use eyre::{Result, eyre};
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    io::{self, Bytes, Read, StdinLock, StdoutLock, Write, stdin, stdout},
+};
+
+use crate::Args;
+
+/// I/O executed by an interpreter run
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoCounts {
+    /// Bytes written, `:` writes one per digit
+    pub outputs: usize,
+    pub inputs: usize,
+    /// Inputs that got a byte rather than EOF
+    pub input_bytes: usize,
+}
+
+impl IoCounts {
+    pub fn input(&mut self, byte: Option<u8>) -> u8 {
+        self.inputs += 1;
+        if byte.is_some() {
+            self.input_bytes += 1;
+        }
+        byte.unwrap_or(0)
+    }
+
+    /// How many of `len` more output bytes `--limit-output` leaves room for
+    pub fn room(&self, limit: Option<usize>, len: usize) -> usize {
+        limit.map_or(len, |limit| limit - self.outputs).min(len)
+    }
+}
+
+impl Display for IoCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "output: {} bytes; input: {} ops, {} bytes",
+            self.outputs, self.inputs, self.input_bytes
+        )
+    }
+}
+
+/// Byte source for `,`, `None` is EOF
+#[derive(Debug)]
+pub enum Input {
+    Stdin(Bytes<StdinLock<'static>>),
+    /// Stdin as pairs of hex digits, whitespace between them is skipped (`--hex-input`)
+    Hex(Bytes<StdinLock<'static>>),
+    Random(XorShift),
+}
+
+impl Input {
+    pub fn new(random_seed: Option<u64>, hex: bool) -> Self {
+        match random_seed {
+            Some(seed) => Input::Random(XorShift::new(seed)),
+            None if hex => Input::Hex(stdin().lock().bytes()),
+            None => Input::Stdin(stdin().lock().bytes()),
+        }
+    }
+}
+
+/// One byte per call, for readers generic over `Read`. Errors reading stdin are EOF here
+/// too
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(slot) = buf.first_mut() else {
+            return Ok(0);
+        };
+        match self.next() {
+            Some(byte) => {
+                *slot = byte;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// Next hex digit, `None` at EOF or on anything that is neither a digit nor whitespace
+fn hex_digit(bytes: &mut Bytes<StdinLock<'static>>) -> Option<u8> {
+    let c = bytes
+        .map_while(Result::ok)
+        .find(|c| !c.is_ascii_whitespace())?;
+    let digit = (c as char).to_digit(16);
+    if digit.is_none() {
+        eprintln!(
+            "warning: {:?} in hex input is not a hex digit, treating it as EOF",
+            c as char
+        );
+    }
+    digit.map(|digit| digit as u8)
+}
+
+impl Iterator for Input {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        match self {
+            Input::Stdin(bytes) => bytes.next().and_then(Result::ok),
+            Input::Hex(bytes) => Some(hex_digit(bytes)? << 4 | hex_digit(bytes)?),
+            Input::Random(rng) => rng.next(),
+        }
+    }
+}
+
+/// When `--flush-on` flushes output to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FlushOn {
+    /// After every `\n`, for terminals
+    Newline,
+    /// After every byte
+    Byte,
+    /// Only when a large buffer fills up and at exit
+    Never,
+}
+
+impl FlushOn {
+    /// Whether writing `bytes` flushes, so a writer holding output back should pass it on
+    pub fn flushes(self, bytes: &[u8]) -> bool {
+        match self {
+            FlushOn::Newline => bytes.contains(&b'\n'),
+            FlushOn::Byte => !bytes.is_empty(),
+            FlushOn::Never => false,
+        }
+    }
+}
+
+/// Bytes `--flush-on never` holds before writing them out
+const HELD: usize = 1 << 16;
+
+/// Stdout for `.` with the `--flush-on` policy, if any. Without one stdout keeps its own
+/// line buffering
+#[derive(Debug)]
+pub struct Output {
+    sink: Sink,
+    flush_on: Option<FlushOn>,
+    /// `--flush-on never`: bytes not yet given to stdout, which flushes at every newline
+    held: Vec<u8>,
+}
+
+/// `--hex-output` writes every byte as two hex digits
+#[derive(Debug)]
+enum Sink {
+    /// `--expect`: compared with the file instead of written
+    Expect(Expect),
+    Raw(StdoutLock<'static>),
+    Hex(StdoutLock<'static>),
+    /// `write(2)` on fd 1 for every write, bypassing the buffered stdout (`--raw-io`)
+    #[cfg(feature = "raw-io")]
+    Syscall,
+}
+
+impl Output {
+    /// `raw` is only honored with the `raw-io` feature, main rejects it otherwise
+    pub fn new(hex: bool, raw: bool, flush_on: Option<FlushOn>) -> Self {
+        let sink = Sink::new(hex, raw);
+        Self {
+            sink,
+            flush_on,
+            held: Vec::new(),
+        }
+    }
+
+    /// Stdout as the output flags say, or the `--expect` file to compare with
+    pub fn for_args(args: &Args) -> Result<Self> {
+        let mut output = Self::new(args.hex_output, args.raw_io, args.flush_on);
+        if let Some(path) = &args.expect {
+            let expected = fs::read(path)
+                .map_err(|e| eyre!("cannot read --expect file {}: {e}", path.display()))?;
+            output.sink = Sink::Expect(Expect {
+                path: path.display().to_string(),
+                expected,
+                matched: 0,
+            });
+        }
+        Ok(output)
+    }
+
+    /// Flush once the program is done, and with `--expect` fail if it printed less than
+    /// the file holds
+    pub fn finish(&mut self) -> Result<()> {
+        self.flush()?;
+        if let Sink::Expect(expect) = &self.sink
+            && expect.matched < expect.expected.len()
+        {
+            Err(eyre!(
+                "output ended at byte {}, {} has {}",
+                expect.matched,
+                expect.path,
+                expect.expected.len()
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) -> io::Result<()> {
+        self.sink.write_all(&self.held)?;
+        self.held.clear();
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.flush_on == Some(FlushOn::Never) {
+            self.held.extend_from_slice(buf);
+            if self.held.len() >= HELD {
+                self.release()?;
+            }
+            return Ok(buf.len());
+        }
+        self.sink.write_all(buf)?;
+        if self.flush_on.is_some_and(|flush_on| flush_on.flushes(buf)) {
+            self.sink.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.release()?;
+        self.sink.flush()
+    }
+}
+
+/// Held bytes are written even on an early return with an error
+impl Drop for Output {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl Sink {
+    fn new(hex: bool, raw: bool) -> Self {
+        #[cfg(feature = "raw-io")]
+        if raw {
+            return Sink::Syscall;
+        }
+        let _ = raw;
+        if hex {
+            Sink::Hex(stdout().lock())
+        } else {
+            Sink::Raw(stdout().lock())
+        }
+    }
+}
+
+/// Output checked against `expected` as it is written, so a run stops at the first byte
+/// that differs
+#[derive(Debug)]
+struct Expect {
+    path: String,
+    expected: Vec<u8>,
+    /// Bytes written so far, all of them as expected
+    matched: usize,
+}
+
+impl Write for Expect {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let rest = &self.expected[self.matched..];
+        let diverged = buf.iter().zip(rest).position(|(got, want)| got != want);
+        let offset = self.matched + diverged.unwrap_or(rest.len());
+        let msg = match diverged {
+            Some(i) => format!(
+                "output differs from {} at byte {offset}, expected {:#04x}, got {:#04x}",
+                self.path, rest[i], buf[i]
+            ),
+            None if buf.len() > rest.len() => format!(
+                "output goes on past the {} bytes of {}",
+                self.expected.len(),
+                self.path
+            ),
+            None => {
+                self.matched += buf.len();
+                return Ok(buf.len());
+            }
+        };
+        Err(io::Error::other(msg))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Expect(expect) => expect.write(buf),
+            Sink::Raw(out) => out.write(buf),
+            Sink::Hex(out) => {
+                for byte in buf {
+                    write!(out, "{byte:02x}")?;
+                }
+                Ok(buf.len())
+            }
+            #[cfg(feature = "raw-io")]
+            Sink::Syscall => {
+                let written = unsafe { libc::write(1, buf.as_ptr().cast(), buf.len()) };
+                if written < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(written as usize)
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Expect(expect) => expect.flush(),
+            Sink::Raw(out) | Sink::Hex(out) => out.flush(),
+            #[cfg(feature = "raw-io")]
+            Sink::Syscall => Ok(()),
+        }
+    }
+}
+
+/// Endless deterministic byte stream (xorshift64)
+#[derive(Debug, Clone)]
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // one splitmix64 step so nearby seeds diverge immediately
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        // xorshift gets stuck on a zero state
+        Self((z ^ (z >> 31)).max(1))
+    }
+}
+
+impl Iterator for XorShift {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        Some((self.0 >> 56) as u8)
+    }
+}
@@ -1,9 +1,9 @@
-use std::fs::File;
+use std::io::Read;
 
 use eyre::Result;
 
 use crate::Args;
 
-pub fn main(_args: Args, _f: File) -> Result<()> {
+pub fn main(_args: Args, _f: impl Read) -> Result<()> {
     todo!()
 }
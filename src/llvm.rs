@@ -1,128 +1,1836 @@
-use std::{fs::File, io::Write, path::PathBuf, process::Command, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::{self, File},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{Read, Write, stdout},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    thread,
+};
 
-use eyre::{OptionExt, Result};
+use clap::ValueEnum;
+use eyre::{OptionExt, Result, eyre};
 use inkwell::{
-    AddressSpace, IntPredicate,
+    AddressSpace, IntPredicate, OptimizationLevel,
     attributes::{Attribute, AttributeLoc},
+    basic_block::BasicBlock,
     builder::Builder,
     context::Context,
+    intrinsics::Intrinsic,
+    memory_buffer::MemoryBuffer,
     module::{Linkage, Module},
-    targets::TargetTriple,
-    values::{FunctionValue, IntValue, PointerValue},
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+    },
+    types::BasicMetadataTypeEnum,
+    values::{BasicMetadataValueEnum, FunctionValue, GlobalValue, IntValue, PointerValue},
 };
 
 use crate::{
-    Args, o1,
+    Args,
+    io::{FlushOn, Input, Output},
     o2::{self, Stmt},
+    rust,
 };
 
+/// Where the compiled program gets its tape and I/O from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Runtime {
+    /// `calloc` the tape, `putchar`/`getchar` for I/O
+    #[default]
+    Libc,
+    /// Static tape, imported `read`/`write` (fd, buf, len) for I/O
+    Freestanding,
+}
+
+/// What the LLVM backend produces
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Emit {
+    /// Executable built by clang, then run
+    #[default]
+    Exe,
+    /// Target assembly from LLVM's code generator, `<input>.s`
+    Asm,
+    /// Object file from LLVM's code generator, `<input>.o`
+    Obj,
+    /// The optimized o2 statements as JSON on stdout, without LLVM (needs the serde feature)
+    JsonAst,
+    /// Rust source with `pub fn run(input, output)` running the program, `<input>.rs`
+    Rust,
+    /// Pseudo-SSA listing of the pointer and cell values the compiler threads through the
+    /// program, simpler than the IR of --text, `<input>.ssa`
+    SsaIr,
+    /// Every command in the source with its byte offset on stdout, one per line, before
+    /// any run is coalesced
+    Tokens,
+}
+
+/// Canary cells on each side of a `--tape-guard` tape
+const TAPE_GUARD: u32 = 16;
+const CANARY: u64 = 0xa5;
+
+/// Bytes `--buffered-runtime` writes and reads per call at most
+const IO_BUFFER: u64 = 4096;
+
+#[derive(Debug)]
+enum Io<'ctx> {
+    Libc {
+        putchar: FunctionValue<'ctx>,
+        getchar: FunctionValue<'ctx>,
+    },
+    Freestanding {
+        read: FunctionValue<'ctx>,
+        write: FunctionValue<'ctx>,
+    },
+    /// `--buffered-runtime`, `void putc(i8)` and `i8 getc()` on buffers that `flush`
+    /// writes out and `getc` fills in blocks
+    Buffered {
+        putc: FunctionValue<'ctx>,
+        getc: FunctionValue<'ctx>,
+        flush: FunctionValue<'ctx>,
+    },
+}
+
+/// `--emit ssa-ir`: what `compile_rec` does with the values it threads, written as it
+/// goes. Every pointer update is a new `%ptr` version and every loaded or computed cell a
+/// new `%v`, memory is addressed as `[%ptr + offset]` and control flow as labelled blocks
+/// joined by phis, like the IR without its types and element pointers
+#[derive(Debug, Default)]
+struct Ssa {
+    lines: Vec<String>,
+    /// Version the pointer is at
+    ptr: usize,
+    ptrs: usize,
+    vals: usize,
+    blocks: usize,
+    /// Listings of outlined loops, printed after the function that calls them
+    functions: Vec<String>,
+}
+
+/// A loop or if whose header has been logged, waiting for the end of its body
+#[derive(Debug)]
+struct SsaBlock {
+    label: String,
+    /// Pointer version on entry
+    entry: usize,
+    /// Version of the header's phi, and the line it goes on once the back edge is known
+    phi: (usize, usize),
+}
+
+impl Ssa {
+    /// Listing of a function, which starts with the pointer as `%ptr0`
+    fn new(header: String) -> Self {
+        Self {
+            lines: vec![header],
+            ..Self::default()
+        }
+    }
+
+    fn inst(&mut self, inst: String) {
+        self.lines.push(format!("  {inst}"));
+    }
+
+    fn val(&mut self) -> String {
+        self.vals += 1;
+        format!("%v{}", self.vals)
+    }
+
+    fn ptr(&self) -> String {
+        format!("%ptr{}", self.ptr)
+    }
+
+    fn next_ptr(&mut self) -> String {
+        self.ptrs += 1;
+        self.ptr = self.ptrs;
+        self.ptr()
+    }
+
+    fn cell(&self, offset: i32) -> String {
+        match offset {
+            0 => format!("[{}]", self.ptr()),
+            1.. => format!("[{} + {offset}]", self.ptr()),
+            _ => format!("[{} - {}]", self.ptr(), offset.unsigned_abs()),
+        }
+    }
+
+    fn load(&mut self, offset: i32) -> String {
+        let val = self.val();
+        let cell = self.cell(offset);
+        self.inst(format!("{val} = load {cell}"));
+        val
+    }
+
+    fn add_at(&mut self, offset: i32, n: impl Display) {
+        let old = self.load(offset);
+        let new = self.val();
+        self.inst(format!("{new} = add {old}, {n}"));
+        let cell = self.cell(offset);
+        self.inst(format!("store {cell}, {new}"));
+    }
+
+    /// Straight-line statements, the loop builders log control flow
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::PtrInc(n) => {
+                let old = self.ptr();
+                let new = self.next_ptr();
+                self.inst(format!("{new} = add {old}, {n}"));
+            }
+            Stmt::ValInc(n) => self.add_at(0, *n as i8),
+            Stmt::ValIncAt(offset, n) => self.add_at(*offset, *n as i8),
+            Stmt::Set(n) => self.inst(format!("store {}, {}", self.cell(0), *n as i8)),
+            Stmt::SetAt(offset, n) => {
+                self.inst(format!("store {}, {}", self.cell(*offset), *n as i8))
+            }
+            // logged with the rest of its run by `add_muls`
+            Stmt::AddMul(..) => {}
+            Stmt::Output | Stmt::DebugCell | Stmt::OutputNum => {
+                let val = self.load(0);
+                let op = match stmt {
+                    Stmt::Output => "out",
+                    Stmt::DebugCell => "dbg",
+                    _ => "outnum",
+                };
+                self.inst(format!("{op} {val}"));
+            }
+            // the cell is kept when the divisor is 0
+            Stmt::Mod => {
+                let val = self.load(0);
+                let divisor = self.load(1);
+                let rem = self.val();
+                self.inst(format!("{rem} = mod {val}, {divisor}"));
+                self.inst(format!("store {}, {rem}", self.cell(0)));
+            }
+            Stmt::OutputConst(bytes) => self.inst(format!("out \"{}\"", bytes.escape_ascii())),
+            Stmt::Input => {
+                let val = self.val();
+                self.inst(format!("{val} = in"));
+                self.inst(format!("store {}, {val}", self.cell(0)));
+            }
+            _ => {}
+        }
+    }
+
+    /// A run of `AddMul`s sharing one load of the guard
+    fn add_muls(&mut self, muls: &[(i32, i32)]) {
+        let guard = self.load(0);
+        for (offset, factor) in muls {
+            let product = self.val();
+            self.inst(format!("{product} = mul {guard}, {}", *factor as i8));
+            self.add_at(*offset, product);
+        }
+    }
+
+    fn label(&mut self, kind: &str) -> String {
+        self.blocks += 1;
+        format!("{kind}{}", self.blocks)
+    }
+
+    /// Header of a loop testing its guard at the top, or with `test` false of a rotated
+    /// one, which falls straight into its body
+    fn loop_header(&mut self, kind: &str, test: bool) -> SsaBlock {
+        let label = self.label(kind);
+        let entry = self.ptr;
+        self.lines.push(format!("{label}:"));
+        let line = self.lines.len();
+        self.lines.push(String::new());
+        self.next_ptr();
+        if test {
+            let guard = self.load(0);
+            self.inst(format!("br {guard}, {label}.body, {label}.end"));
+            self.lines.push(format!("{label}.body:"));
+        }
+        SsaBlock {
+            label,
+            entry,
+            phi: (self.ptr, line),
+        }
+    }
+
+    /// Back edge of a loop, after which the pointer is the header's phi or, in a rotated
+    /// loop, the one the test at the bottom saw
+    fn loop_latch(&mut self, block: SsaBlock, test: bool) {
+        let SsaBlock { label, entry, phi } = block;
+        let back = self.ptr();
+        self.lines[phi.1] = format!("  %ptr{} = phi %ptr{entry}, {back}", phi.0);
+        if test {
+            self.inst(format!("br {label}"));
+            self.ptr = phi.0;
+        } else {
+            let guard = self.load(0);
+            self.inst(format!("br {guard}, {label}, {label}.end"));
+        }
+        self.lines.push(format!("{label}.end:"));
+    }
+
+    fn if_header(&mut self) -> SsaBlock {
+        let label = self.label("if");
+        let guard = self.load(0);
+        self.inst(format!("br {guard}, {label}.then, {label}.end"));
+        self.lines.push(format!("{label}.then:"));
+        SsaBlock {
+            label,
+            entry: self.ptr,
+            phi: (0, 0),
+        }
+    }
+
+    fn if_end(&mut self, block: SsaBlock) {
+        let then = self.ptr();
+        self.inst(format!("br {}.end", block.label));
+        self.lines.push(format!("{}.end:", block.label));
+        let ptr = self.next_ptr();
+        self.inst(format!("{ptr} = phi %ptr{}, {then}", block.entry));
+    }
+
+    /// The listing, with the outlined loops after it
+    fn listing(&self) -> String {
+        let mut listing = self.lines.join("\n") + "\n";
+        for function in &self.functions {
+            listing.push('\n');
+            listing.push_str(function);
+        }
+        listing
+    }
+}
+
+/// `--grow`: the tape lives behind globals so `grow` can realloc it past a cell
+#[derive(Debug, Clone, Copy)]
+struct Grow<'ctx> {
+    mem: GlobalValue<'ctx>,
+    /// Cells in `mem`, `i64`
+    cap: GlobalValue<'ctx>,
+    /// `void (i32 index)`, exits with a message if the tape cannot grow to `index`
+    function: FunctionValue<'ctx>,
+}
+
+/// `--safe` or `--wrap`: every pointer move is checked against, or wrapped around, a tape
+/// of `len` cells
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    len: u32,
+    wrap: bool,
+}
+
 #[derive(Debug)]
 pub struct Compiler<'ctx> {
     context: &'ctx Context,
     builder: Builder<'ctx>,
     module: Module<'ctx>,
-    main: FunctionValue<'ctx>,
+    /// Function being built, `main` or an outlined loop
+    function: FunctionValue<'ctx>,
     ptr: IntValue<'ctx>,
     mem: PointerValue<'ctx>,
-    putchar: FunctionValue<'ctx>,
-    getchar: FunctionValue<'ctx>,
+    /// Element pointer of `mem[ptr]`, reusable within the block it was built in
+    cell: Option<(BasicBlock<'ctx>, IntValue<'ctx>, PointerValue<'ctx>)>,
+    io: Io<'ctx>,
+    arch_native: bool,
+    outline_loops: bool,
+    outlined: usize,
+    /// Outlined functions by loop, identical loops share one. Only ever looked up, so the
+    /// functions are emitted in program order whatever the hash order
+    loops: HashMap<Stmt, FunctionValue<'ctx>>,
+    lib: bool,
+    signed_cells: bool,
+    /// Static tape wrapped in canaries, checked before `main` returns
+    guard: Option<GlobalValue<'ctx>>,
+    /// Loops entered so far with `--canary`, which checks the guard after each of them
+    canary: Option<usize>,
+    /// Growable tape, every access is checked against its capacity
+    grow: Option<Grow<'ctx>>,
+    /// Every pointer add is checked for overflowing `i32`
+    halt_on_overflow: bool,
+    bounds: Option<Bounds>,
+    ssa: Option<Ssa>,
 }
 
 impl<'ctx> Compiler<'ctx> {
-    fn new(context: &'ctx Context) -> Result<Self> {
+    fn new(context: &'ctx Context, args: &Args) -> Result<Self> {
+        let tape_len: u32 = args
+            .max_mem
+            .try_into()
+            .map_err(|_| eyre!("tape of {} cells is too large", args.max_mem))?;
+        if args.tape_guard && (args.runtime != Runtime::Freestanding || args.lib) {
+            Err(eyre!(
+                "--tape-guard needs the static tape of --runtime freestanding"
+            ))?;
+        }
+        if args.grow && (args.runtime != Runtime::Libc || args.lib) {
+            Err(eyre!("--grow needs the calloc'd tape of --runtime libc"))?;
+        }
+        if args.halt_on_overflow && args.runtime != Runtime::Libc {
+            Err(eyre!(
+                "--halt-on-overflow needs --runtime libc to report it"
+            ))?;
+        }
+        if args.safe && args.runtime != Runtime::Libc {
+            Err(eyre!("--safe needs --runtime libc to report it"))?;
+        }
+        if args.buffered_runtime && args.runtime != Runtime::Libc {
+            Err(eyre!("--buffered-runtime needs --runtime libc"))?;
+        }
+        if args.flush_on.is_some() && (args.runtime != Runtime::Libc || args.lib) {
+            Err(eyre!(
+                "--flush-on sets the buffering of libc's stdout, which needs --runtime libc \
+                 and a main of its own"
+            ))?;
+        }
+
         let builder = context.create_builder();
         let module = context.create_module("main");
         #[cfg(target_arch = "x86_64")]
         module.set_triple(&TargetTriple::create("x86_64-pc-linux-gnu"));
+        if let Some(layout) = &args.datalayout {
+            // let the IR parser validate it, it reports errors instead of aborting
+            let stub = format!("target datalayout = \"{layout}\"\n");
+            let buffer = MemoryBuffer::create_from_memory_range_copy(stub.as_bytes(), "datalayout");
+            let stub = context
+                .create_module_from_ir(buffer)
+                .map_err(|e| eyre!("invalid datalayout '{layout}': {}", e.to_string_lossy()))?;
+            module.set_data_layout(&stub.get_data_layout());
+        }
 
         let i32_type = context.i32_type();
-        let main_type = i32_type.fn_type(&[], false);
-        let main = module.add_function("main", main_type, None);
-
         let ptr_type = context.ptr_type(AddressSpace::default());
         let i64_type = context.i64_type();
-        let calloc_type = ptr_type.fn_type(&[i64_type.into()], false);
-        let calloc = module.add_function("calloc", calloc_type, Some(Linkage::External));
-        let noalias_kind_id = Attribute::get_named_enum_kind_id("noalias");
-        let noalias = context.create_enum_attribute(noalias_kind_id, 0);
-        calloc.add_attribute(AttributeLoc::Return, noalias);
-        let putchar_type = i32_type.fn_type(&[i32_type.into()], false);
-        let putchar = module.add_function("putchar", putchar_type, Some(Linkage::External));
-        let getchar_type = i32_type.fn_type(&[], false);
-        let getchar = module.add_function("getchar", getchar_type, Some(Linkage::External));
+        // --lib: `i32 bf_run(ptr tape)` returning the final pointer
+        let (main_type, name) = if args.lib {
+            (i32_type.fn_type(&[ptr_type.into()], false), "bf_run")
+        } else {
+            (i32_type.fn_type(&[], false), "main")
+        };
+        let name = args.entry.as_deref().unwrap_or(name);
+        let main = module.add_function(name, main_type, None);
+        if args.arch_native {
+            tune_for_host(context, main);
+        }
 
         let entry = context.append_basic_block(main, "entry");
         builder.position_at_end(entry);
         let ptr = i32_type.const_zero();
-        let mem = builder
+
+        let tape_arg = args
+            .lib
+            .then(|| main.get_nth_param(0).unwrap().into_pointer_value());
+        let mut guard = None;
+        let (mem, io) = match args.runtime {
+            Runtime::Libc => {
+                let putchar_type = i32_type.fn_type(&[i32_type.into()], false);
+                let putchar = module.add_function("putchar", putchar_type, Some(Linkage::External));
+                let getchar_type = i32_type.fn_type(&[], false);
+                let getchar = module.add_function("getchar", getchar_type, Some(Linkage::External));
+                if let Some(flush_on) = args.flush_on {
+                    // before any output, as setvbuf requires
+                    let stdout = module.add_global(ptr_type, None, "stdout");
+                    stdout.set_linkage(Linkage::External);
+                    let stdout = builder.build_load(ptr_type, stdout.as_pointer_value(), "")?;
+                    let setvbuf_type = i32_type.fn_type(
+                        &[
+                            ptr_type.into(),
+                            ptr_type.into(),
+                            i32_type.into(),
+                            i64_type.into(),
+                        ],
+                        false,
+                    );
+                    let setvbuf =
+                        module.add_function("setvbuf", setvbuf_type, Some(Linkage::External));
+                    // _IOLBF, _IONBF and _IOFBF
+                    let mode = match flush_on {
+                        FlushOn::Newline => 1,
+                        FlushOn::Byte => 2,
+                        FlushOn::Never => 0,
+                    };
+                    builder.build_direct_call(
+                        setvbuf,
+                        &[
+                            stdout.into(),
+                            ptr_type.const_null().into(),
+                            i32_type.const_int(mode, false).into(),
+                            i64_type.const_zero().into(),
+                        ],
+                        "",
+                    )?;
+                }
+
+                let mem = match tape_arg {
+                    Some(tape) => tape,
+                    None => {
+                        let calloc_type =
+                            ptr_type.fn_type(&[i64_type.into(), i64_type.into()], false);
+                        let calloc =
+                            module.add_function("calloc", calloc_type, Some(Linkage::External));
+                        let noalias_kind_id = Attribute::get_named_enum_kind_id("noalias");
+                        let noalias = context.create_enum_attribute(noalias_kind_id, 0);
+                        calloc.add_attribute(AttributeLoc::Return, noalias);
+                        builder
+                            .build_direct_call(
+                                calloc,
+                                &[
+                                    i64_type.const_int(tape_len as u64, false).into(),
+                                    i64_type.const_int(1, false).into(),
+                                ],
+                                "mem",
+                            )?
+                            .try_as_basic_value()
+                            .left()
+                            .unwrap()
+                            .into_pointer_value()
+                    }
+                };
+                (mem, Io::Libc { putchar, getchar })
+            }
+            Runtime::Freestanding => {
+                let io_type =
+                    i64_type.fn_type(&[i32_type.into(), ptr_type.into(), i64_type.into()], false);
+                let read = module.add_function("read", io_type, Some(Linkage::External));
+                let write = module.add_function("write", io_type, Some(Linkage::External));
+
+                let i8_type = context.i8_type();
+                let mem = match tape_arg {
+                    Some(tape) => tape,
+                    None if args.tape_guard => {
+                        let canaries =
+                            context.const_string(&[CANARY as u8; TAPE_GUARD as usize], false);
+                        let cells_type = i8_type.array_type(tape_len);
+                        let canaries_type = canaries.get_type();
+                        let tape_type = context.struct_type(
+                            &[
+                                canaries_type.into(),
+                                cells_type.into(),
+                                canaries_type.into(),
+                            ],
+                            true,
+                        );
+                        let tape = module.add_global(tape_type, None, "tape");
+                        tape.set_linkage(Linkage::Internal);
+                        tape.set_initializer(&context.const_struct(
+                            &[
+                                canaries.into(),
+                                cells_type.const_zero().into(),
+                                canaries.into(),
+                            ],
+                            true,
+                        ));
+                        guard = Some(tape);
+                        let zero = i32_type.const_zero();
+                        let cells = i32_type.const_int(1, false);
+                        unsafe {
+                            builder.build_in_bounds_gep(
+                                tape_type,
+                                tape.as_pointer_value(),
+                                &[zero, cells, zero],
+                                "mem",
+                            )
+                        }?
+                    }
+                    None => {
+                        let tape_type = i8_type.array_type(tape_len);
+                        let tape = module.add_global(tape_type, None, "tape");
+                        tape.set_linkage(Linkage::Internal);
+                        tape.set_initializer(&tape_type.const_zero());
+                        tape.as_pointer_value()
+                    }
+                };
+                (mem, Io::Freestanding { read, write })
+            }
+        };
+
+        let mut compiler = Compiler {
+            context,
+            builder,
+            module,
+            function: main,
+            ptr,
+            mem,
+            cell: None,
+            io,
+            arch_native: args.arch_native,
+            outline_loops: args.outline_loops,
+            outlined: 0,
+            loops: HashMap::new(),
+            lib: args.lib,
+            signed_cells: args.signed_cells,
+            guard,
+            canary: args.canary.then_some(0),
+            grow: None,
+            halt_on_overflow: args.halt_on_overflow,
+            bounds: (args.safe || args.wrap).then_some(Bounds {
+                len: tape_len,
+                wrap: args.wrap,
+            }),
+            ssa: (args.emit == Emit::SsaIr).then(|| {
+                let mut ssa = Ssa::new(format!("{name}:"));
+                ssa.inst("%ptr0 = 0".to_owned());
+                ssa
+            }),
+        };
+        // before the grow function, which flushes on failure
+        if args.buffered_runtime {
+            compiler.io = compiler.build_buffered_io()?;
+        }
+        if args.grow {
+            compiler.grow = Some(compiler.build_grow(tape_len)?);
+        }
+        Ok(compiler)
+    }
+
+    /// The `--buffered-runtime` functions over internal buffers, which batch output into
+    /// `write` calls of up to [`IO_BUFFER`] bytes and read input as blocks of as much
+    fn build_buffered_io(&mut self) -> Result<Io<'ctx>> {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let void_type = self.context.void_type();
+        let io_type = i64_type.fn_type(&[i32_type.into(), ptr_type.into(), i64_type.into()], false);
+        let [read, write] = ["read", "write"].map(|name| {
+            self.module.get_function(name).unwrap_or_else(|| {
+                self.module
+                    .add_function(name, io_type, Some(Linkage::External))
+            })
+        });
+        let buf_type = i8_type.array_type(IO_BUFFER as u32);
+        let [out_buf, in_buf] = ["io.out", "io.in"].map(|name| {
+            let buf = self.module.add_global(buf_type, None, name);
+            buf.set_linkage(Linkage::Internal);
+            buf.set_initializer(&buf_type.const_zero());
+            buf.as_pointer_value()
+        });
+        // bytes in io.out, and the next byte of io.in and the end of what it holds
+        let [out_len, in_pos, in_len] = ["io.out.len", "io.in.pos", "io.in.len"].map(|name| {
+            let counter = self.module.add_global(i64_type, None, name);
+            counter.set_linkage(Linkage::Internal);
+            counter.set_initializer(&i64_type.const_zero());
+            counter.as_pointer_value()
+        });
+        let zero = i64_type.const_zero();
+        let one = i64_type.const_int(1, false);
+        let caller_bb = self.builder.get_insert_block().unwrap();
+
+        // void io.flush(), writes until io.out is empty or write fails
+        let flush = self.module.add_function(
+            "io.flush",
+            void_type.fn_type(&[], false),
+            Some(Linkage::Internal),
+        );
+        let entry = self.context.append_basic_block(flush, "entry");
+        let loop_bb = self.context.append_basic_block(flush, "loop");
+        let write_bb = self.context.append_basic_block(flush, "write");
+        let done_bb = self.context.append_basic_block(flush, "done");
+        self.builder.position_at_end(entry);
+        let len = self
+            .builder
+            .build_load(i64_type, out_len, "len")?
+            .into_int_value();
+        self.builder.build_unconditional_branch(loop_bb)?;
+        self.builder.position_at_end(loop_bb);
+        let written = self.builder.build_phi(i64_type, "written")?;
+        let written_val = written.as_basic_value().into_int_value();
+        let more = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, written_val, len, "")?;
+        self.builder
+            .build_conditional_branch(more, write_bb, done_bb)?;
+        self.builder.position_at_end(write_bb);
+        let buf = unsafe { self.builder.build_gep(i8_type, out_buf, &[written_val], "") }?;
+        let rest = self.builder.build_int_sub(len, written_val, "")?;
+        let n = self
+            .builder
+            .build_direct_call(
+                write,
+                &[i32_type.const_int(1, false).into(), buf.into(), rest.into()],
+                "",
+            )?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let next = self.builder.build_int_add(written_val, n, "")?;
+        let ok = self
+            .builder
+            .build_int_compare(IntPredicate::SGT, n, zero, "")?;
+        self.builder
+            .build_conditional_branch(ok, loop_bb, done_bb)?;
+        written.add_incoming(&[(&zero, entry), (&next, write_bb)]);
+        self.builder.position_at_end(done_bb);
+        self.builder.build_store(out_len, zero)?;
+        self.builder.build_return(None)?;
+
+        // void io.putc(i8), flushes a full buffer first
+        let putc = self.module.add_function(
+            "io.putc",
+            void_type.fn_type(&[i8_type.into()], false),
+            Some(Linkage::Internal),
+        );
+        let entry = self.context.append_basic_block(putc, "entry");
+        let flush_bb = self.context.append_basic_block(putc, "flush");
+        let store_bb = self.context.append_basic_block(putc, "store");
+        self.builder.position_at_end(entry);
+        let len = self
+            .builder
+            .build_load(i64_type, out_len, "len")?
+            .into_int_value();
+        let full = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            len,
+            i64_type.const_int(IO_BUFFER, false),
+            "",
+        )?;
+        self.builder
+            .build_conditional_branch(full, flush_bb, store_bb)?;
+        self.builder.position_at_end(flush_bb);
+        self.builder.build_direct_call(flush, &[], "")?;
+        self.builder.build_unconditional_branch(store_bb)?;
+        self.builder.position_at_end(store_bb);
+        let len_phi = self.builder.build_phi(i64_type, "len")?;
+        len_phi.add_incoming(&[(&len, entry), (&zero, flush_bb)]);
+        let len = len_phi.as_basic_value().into_int_value();
+        let byte_ptr = unsafe { self.builder.build_gep(i8_type, out_buf, &[len], "") }?;
+        self.builder
+            .build_store(byte_ptr, putc.get_nth_param(0).unwrap())?;
+        let len = self.builder.build_int_add(len, one, "")?;
+        self.builder.build_store(out_len, len)?;
+        self.builder.build_return(None)?;
+
+        // i8 io.getc(), refills io.in when it runs out, EOF reads as 0 like the interpreters
+        let getc = self.module.add_function(
+            "io.getc",
+            i8_type.fn_type(&[], false),
+            Some(Linkage::Internal),
+        );
+        let entry = self.context.append_basic_block(getc, "entry");
+        let fill_bb = self.context.append_basic_block(getc, "fill");
+        let eof_bb = self.context.append_basic_block(getc, "eof");
+        let filled_bb = self.context.append_basic_block(getc, "filled");
+        let take_bb = self.context.append_basic_block(getc, "take");
+        self.builder.position_at_end(entry);
+        let pos = self
+            .builder
+            .build_load(i64_type, in_pos, "pos")?
+            .into_int_value();
+        let len = self
+            .builder
+            .build_load(i64_type, in_len, "len")?
+            .into_int_value();
+        let empty = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, pos, len, "")?;
+        self.builder
+            .build_conditional_branch(empty, fill_bb, take_bb)?;
+        self.builder.position_at_end(fill_bb);
+        // a prompt must be visible before blocking on the read
+        self.builder.build_direct_call(flush, &[], "")?;
+        let n = self
+            .builder
             .build_direct_call(
-                calloc,
+                read,
                 &[
-                    i64_type.const_int(30000, false).into(),
-                    i64_type.const_int(1, false).into(),
+                    i32_type.const_zero().into(),
+                    in_buf.into(),
+                    i64_type.const_int(IO_BUFFER, false).into(),
                 ],
-                "mem",
+                "",
             )?
             .try_as_basic_value()
             .left()
             .unwrap()
+            .into_int_value();
+        let eof = self
+            .builder
+            .build_int_compare(IntPredicate::SLE, n, zero, "eof")?;
+        self.builder
+            .build_conditional_branch(eof, eof_bb, filled_bb)?;
+        self.builder.position_at_end(eof_bb);
+        self.builder.build_return(Some(&i8_type.const_zero()))?;
+        self.builder.position_at_end(filled_bb);
+        self.builder.build_store(in_len, n)?;
+        self.builder.build_unconditional_branch(take_bb)?;
+        self.builder.position_at_end(take_bb);
+        let pos_phi = self.builder.build_phi(i64_type, "pos")?;
+        pos_phi.add_incoming(&[(&pos, entry), (&zero, filled_bb)]);
+        let pos = pos_phi.as_basic_value().into_int_value();
+        let byte_ptr = unsafe { self.builder.build_gep(i8_type, in_buf, &[pos], "") }?;
+        let byte = self.builder.build_load(i8_type, byte_ptr, "")?;
+        let pos = self.builder.build_int_add(pos, one, "")?;
+        self.builder.build_store(in_pos, pos)?;
+        self.builder.build_return(Some(&byte))?;
+
+        self.builder.position_at_end(caller_bb);
+        Ok(Io::Buffered { putc, getc, flush })
+    }
+
+    /// Write out what `--buffered-runtime` holds back, before returning or exiting
+    fn build_flush(&self) -> Result<()> {
+        if let Io::Buffered { flush, .. } = self.io {
+            self.builder.build_direct_call(flush, &[], "")?;
+        }
+        Ok(())
+    }
+
+    /// Move the calloc'd tape behind the `--grow` globals and build the function that
+    /// reallocs it, doubling the capacity or more and zeroing the new cells
+    fn build_grow(&mut self, tape_len: u32) -> Result<Grow<'ctx>> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let mem = self.module.add_global(ptr_type, None, "tape.mem");
+        mem.set_linkage(Linkage::Internal);
+        mem.set_initializer(&ptr_type.const_null());
+        let cap = self.module.add_global(i64_type, None, "tape.cap");
+        cap.set_linkage(Linkage::Internal);
+        cap.set_initializer(&i64_type.const_int(tape_len as u64, false));
+        self.builder.build_store(mem.as_pointer_value(), self.mem)?;
+
+        let realloc_type = ptr_type.fn_type(&[ptr_type.into(), i64_type.into()], false);
+        let realloc = self
+            .module
+            .add_function("realloc", realloc_type, Some(Linkage::External));
+        let dprintf_type = i32_type.fn_type(&[i32_type.into(), ptr_type.into()], true);
+        let dprintf = self.module.get_function("dprintf").unwrap_or_else(|| {
+            self.module
+                .add_function("dprintf", dprintf_type, Some(Linkage::External))
+        });
+        let exit = self.exit_function();
+        let fn_type = self.context.void_type().fn_type(&[i32_type.into()], false);
+        let function = self
+            .module
+            .add_function("tape.grow", fn_type, Some(Linkage::Internal));
+        for name in ["noinline", "cold"] {
+            let kind_id = Attribute::get_named_enum_kind_id(name);
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(kind_id, 0),
+            );
+        }
+
+        let caller_bb = self.builder.get_insert_block().unwrap();
+        let entry = self.context.append_basic_block(function, "entry");
+        let alloc_bb = self.context.append_basic_block(function, "alloc");
+        let zero_bb = self.context.append_basic_block(function, "zero");
+        let fail_bb = self.context.append_basic_block(function, "fail");
+        self.builder.position_at_end(entry);
+        let index = function.get_nth_param(0).unwrap().into_int_value();
+        // only the right end grows, a negative pointer is off the tape for good
+        let negative =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index, i32_type.const_zero(), "")?;
+        self.builder
+            .build_conditional_branch(negative, fail_bb, alloc_bb)?;
+
+        self.builder.position_at_end(alloc_bb);
+        let old_cap = self
+            .builder
+            .build_load(i64_type, cap.as_pointer_value(), "cap")?
+            .into_int_value();
+        let doubled = self
+            .builder
+            .build_int_mul(old_cap, i64_type.const_int(2, false), "")?;
+        let wide = self.builder.build_int_z_extend(index, i64_type, "")?;
+        let needed = self
+            .builder
+            .build_int_add(wide, i64_type.const_int(1, false), "")?;
+        let enough = self
+            .builder
+            .build_int_compare(IntPredicate::UGE, doubled, needed, "")?;
+        let new_cap = self
+            .builder
+            .build_select(enough, doubled, needed, "")?
+            .into_int_value();
+        let old_mem = self
+            .builder
+            .build_load(ptr_type, mem.as_pointer_value(), "mem")?;
+        let new_mem = self
+            .builder
+            .build_direct_call(realloc, &[old_mem.into(), new_cap.into()], "mem")?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
             .into_pointer_value();
+        let failed = self.builder.build_is_null(new_mem, "")?;
+        self.builder
+            .build_conditional_branch(failed, fail_bb, zero_bb)?;
 
-        Ok(Compiler {
-            context,
-            builder,
-            module,
-            main,
-            ptr,
-            mem,
-            putchar,
-            getchar,
-        })
+        self.builder.position_at_end(zero_bb);
+        let i8_type = self.context.i8_type();
+        let tail = unsafe { self.builder.build_gep(i8_type, new_mem, &[old_cap], "") }?;
+        let tail_len = self.builder.build_int_sub(new_cap, old_cap, "")?;
+        self.builder
+            .build_memset(tail, 1, i8_type.const_zero(), tail_len)?;
+        self.builder.build_store(mem.as_pointer_value(), new_mem)?;
+        self.builder.build_store(cap.as_pointer_value(), new_cap)?;
+        self.builder.build_return(None)?;
+
+        self.builder.position_at_end(fail_bb);
+        self.build_flush()?;
+        let msg = self
+            .builder
+            .build_global_string_ptr(
+                "pointer %d exceeded tape, it cannot grow there\n",
+                "grow.msg",
+            )?
+            .as_pointer_value();
+        self.builder.build_direct_call(
+            dprintf,
+            &[
+                i32_type.const_int(2, false).into(),
+                msg.into(),
+                index.into(),
+            ],
+            "",
+        )?;
+        self.builder
+            .build_direct_call(exit, &[i32_type.const_int(1, false).into()], "")?;
+        self.builder.build_unreachable()?;
+
+        self.builder.position_at_end(caller_bb);
+        Ok(Grow { mem, cap, function })
     }
 
     fn compile(&mut self, prog: Vec<Stmt>) -> Result<String> {
-        self.compile_rec(prog)?;
+        if self.outline_loops {
+            for stmt in prog {
+                match stmt {
+                    Stmt::Loop(_) | Stmt::DoLoop(_) => self.build_outlined(stmt)?,
+                    stmt => self.compile_rec(vec![stmt])?,
+                }
+            }
+        } else {
+            self.compile_rec(prog)?;
+        }
+
+        if let Some(tape) = self.guard {
+            self.build_guard_check(tape, None)?;
+        }
+        self.build_flush()?;
+        if let Some(ssa) = &mut self.ssa {
+            let ret = if self.lib { ssa.ptr() } else { "0".to_owned() };
+            ssa.inst(format!("ret {ret}"));
+        }
+        if self.lib {
+            self.builder.build_return(Some(&self.ptr))?;
+        } else {
+            self.builder
+                .build_return(Some(&self.context.i32_type().const_zero()))?;
+        }
+        self.module
+            .verify()
+            .map_err(|e| eyre!("LLVM module verification failed: {}", e.to_string_lossy()))?;
+
+        Ok(self.module.print_to_string().to_string())
+    }
+
+    /// `--multi`'s `main`: call the program function whose name is the first argument and
+    /// return what it returns, or list the names on stderr and return 1
+    fn build_dispatcher(&mut self, programs: &[(String, String)]) -> Result<String> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let main_type = i32_type.fn_type(&[i32_type.into(), ptr_type.into()], false);
+        let main = self.module.add_function("main", main_type, None);
+        let strcmp_type = i32_type.fn_type(&[ptr_type.into(), ptr_type.into()], false);
+        let strcmp = self
+            .module
+            .add_function("strcmp", strcmp_type, Some(Linkage::External));
+        let write = self.module.get_function("write").unwrap_or_else(|| {
+            let write_type =
+                i64_type.fn_type(&[i32_type.into(), ptr_type.into(), i64_type.into()], false);
+            self.module
+                .add_function("write", write_type, Some(Linkage::External))
+        });
+
+        let entry_bb = self.context.append_basic_block(main, "entry");
+        let dispatch_bb = self.context.append_basic_block(main, "dispatch");
+        let usage_bb = self.context.append_basic_block(main, "usage");
+        self.builder.position_at_end(entry_bb);
+        let argc = main.get_nth_param(0).unwrap().into_int_value();
+        let argv = main.get_nth_param(1).unwrap().into_pointer_value();
+        let has_arg = self.builder.build_int_compare(
+            IntPredicate::SGT,
+            argc,
+            i32_type.const_int(1, false),
+            "has_arg",
+        )?;
+        self.builder
+            .build_conditional_branch(has_arg, dispatch_bb, usage_bb)?;
+
+        self.builder.position_at_end(dispatch_bb);
+        let arg_ptr = unsafe {
+            self.builder.build_in_bounds_gep(
+                ptr_type,
+                argv,
+                &[i32_type.const_int(1, false)],
+                "arg_ptr",
+            )
+        }?;
+        let arg = self
+            .builder
+            .build_load(ptr_type, arg_ptr, "arg")?
+            .into_pointer_value();
+        for (name, symbol) in programs {
+            let function = self
+                .module
+                .get_function(symbol)
+                .ok_or_eyre("program function missing from the linked module")?;
+            let run_bb = self.context.insert_basic_block_after(
+                self.builder.get_insert_block().unwrap(),
+                &format!("run.{name}"),
+            );
+            let next_bb = self.context.insert_basic_block_after(run_bb, "next");
+            let name = self.builder.build_global_string_ptr(name, "name")?;
+            let cmp = self
+                .builder
+                .build_direct_call(strcmp, &[arg.into(), name.as_pointer_value().into()], "cmp")?
+                .try_as_basic_value()
+                .left()
+                .unwrap()
+                .into_int_value();
+            let matched = self.builder.build_int_compare(
+                IntPredicate::EQ,
+                cmp,
+                i32_type.const_zero(),
+                "matched",
+            )?;
+            self.builder
+                .build_conditional_branch(matched, run_bb, next_bb)?;
+            self.builder.position_at_end(run_bb);
+            let status = self
+                .builder
+                .build_direct_call(function, &[], "status")?
+                .try_as_basic_value()
+                .left()
+                .unwrap();
+            self.builder.build_return(Some(&status))?;
+            self.builder.position_at_end(next_bb);
+        }
+        self.builder.build_unconditional_branch(usage_bb)?;
 
+        self.builder.position_at_end(usage_bb);
+        let names: Vec<_> = programs.iter().map(|(name, _)| name.as_str()).collect();
+        let msg = format!(
+            "expected the program to run as the first argument, one of: {}\n",
+            names.join(", ")
+        );
+        let msg_ptr = self.builder.build_global_string_ptr(&msg, "usage")?;
+        self.builder.build_direct_call(
+            write,
+            &[
+                i32_type.const_int(2, false).into(),
+                msg_ptr.as_pointer_value().into(),
+                i64_type.const_int(msg.len() as u64, false).into(),
+            ],
+            "",
+        )?;
         self.builder
-            .build_return(Some(&self.context.i32_type().const_zero()))?;
+            .build_return(Some(&i32_type.const_int(1, false)))?;
 
+        self.module
+            .verify()
+            .map_err(|e| eyre!("LLVM module verification failed: {}", e.to_string_lossy()))?;
         Ok(self.module.print_to_string().to_string())
     }
 
+    /// Exit with 1 and a message on stderr if any canary around the tape changed, naming
+    /// the loop just left if there is one
+    fn build_guard_check(&mut self, tape: GlobalValue<'ctx>, in_loop: Option<usize>) -> Result<()> {
+        let Io::Freestanding { write, .. } = self.io else {
+            unreachable!("guarded tapes are freestanding")
+        };
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let tape_type = tape.get_value_type().into_struct_type();
+        let mut intact = self.context.bool_type().const_all_ones();
+        for field in [0, 2] {
+            for i in 0..TAPE_GUARD {
+                let indices = [
+                    i32_type.const_zero(),
+                    i32_type.const_int(field, false),
+                    i32_type.const_int(i as u64, false),
+                ];
+                let canary_ptr = unsafe {
+                    self.builder.build_in_bounds_gep(
+                        tape_type,
+                        tape.as_pointer_value(),
+                        &indices,
+                        "",
+                    )
+                }?;
+                let val = self.builder.build_load(i8_type, canary_ptr, "")?;
+                let eq = self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    val.into_int_value(),
+                    i8_type.const_int(CANARY, false),
+                    "",
+                )?;
+                intact = self.builder.build_and(intact, eq, "")?;
+            }
+        }
+        let overrun_bb = self.context.append_basic_block(self.function, "overrun");
+        let done_bb = self.context.append_basic_block(self.function, "done");
+        self.builder
+            .build_conditional_branch(intact, done_bb, overrun_bb)?;
+        self.builder.position_at_end(overrun_bb);
+        let msg = match in_loop {
+            Some(n) => format!("pointer overran the tape guard in loop {n}\n"),
+            None => "pointer overran the tape guard\n".to_owned(),
+        };
+        let msg_ptr = self
+            .builder
+            .build_global_string_ptr(&msg, "guard.msg")?
+            .as_pointer_value();
+        let i64_type = self.context.i64_type();
+        self.builder.build_direct_call(
+            write,
+            &[
+                i32_type.const_int(2, false).into(),
+                msg_ptr.into(),
+                i64_type.const_int(msg.len() as u64, false).into(),
+            ],
+            "",
+        )?;
+        self.builder
+            .build_return(Some(&i32_type.const_int(1, false)))?;
+        self.builder.position_at_end(done_bb);
+        Ok(())
+    }
+
+    /// Assembly or object code for the module's triple, or the host's if it has none
+    fn emit(&self, args: &Args, file_type: FileType) -> Result<Vec<u8>> {
+        Target::initialize_native(&InitializationConfig::default()).map_err(|e| eyre!(e))?;
+        let triple = self.module.get_triple();
+        let triple = if triple.as_str().is_empty() {
+            TargetMachine::get_default_triple()
+        } else {
+            triple
+        };
+        let target = Target::from_triple(&triple).map_err(|e| eyre!("{}", e.to_string_lossy()))?;
+        let (cpu, features) = if args.arch_native {
+            (
+                TargetMachine::get_host_cpu_name()
+                    .to_string_lossy()
+                    .into_owned(),
+                TargetMachine::get_host_cpu_features()
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        } else {
+            ("generic".to_owned(), String::new())
+        };
+        let reloc = if args.pie {
+            RelocMode::PIC
+        } else if args.no_pie || args.r#static {
+            RelocMode::Static
+        } else {
+            RelocMode::Default
+        };
+        let machine = target
+            .create_target_machine(
+                &triple,
+                &cpu,
+                &features,
+                OptimizationLevel::Default,
+                reloc,
+                CodeModel::Default,
+            )
+            .ok_or_else(|| eyre!("no target machine for {}", triple))?;
+        let code = machine
+            .write_to_memory_buffer(&self.module, file_type)
+            .map_err(|e| eyre!("{}", e.to_string_lossy()))?;
+        Ok(code.as_slice().to_vec())
+    }
+
+    /// Call the outlined function of `stmt` in place, compiling it on first use
+    fn build_outlined(&mut self, stmt: Stmt) -> Result<()> {
+        let function = match self.loops.get(&stmt) {
+            Some(&function) => function,
+            None => self.outline(stmt)?,
+        };
+        if let Some(ssa) = &mut self.ssa {
+            let old = ssa.ptr();
+            let new = ssa.next_ptr();
+            let name = function.get_name().to_string_lossy();
+            ssa.inst(format!("{new} = call {name}({old})"));
+        }
+        let (mem, ptr) = (self.mem, self.ptr);
+        self.ptr = self
+            .builder
+            .build_direct_call(function, &[mem.into(), ptr.into()], "ptr")?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        self.cell = None;
+        Ok(())
+    }
+
+    /// Compile `stmt` into a `noinline` function `i32 (ptr mem, i32 ptr)` returning the
+    /// new pointer
+    fn outline(&mut self, stmt: Stmt) -> Result<FunctionValue<'ctx>> {
+        let i32_type = self.context.i32_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let fn_type = i32_type.fn_type(&[ptr_type.into(), i32_type.into()], false);
+        let name = format!("loop.{}", self.outlined);
+        self.outlined += 1;
+        let function = self
+            .module
+            .add_function(&name, fn_type, Some(Linkage::Internal));
+        let noinline_kind_id = Attribute::get_named_enum_kind_id("noinline");
+        let noinline = self.context.create_enum_attribute(noinline_kind_id, 0);
+        function.add_attribute(AttributeLoc::Function, noinline);
+        if self.arch_native {
+            tune_for_host(self.context, function);
+        }
+
+        let caller_bb = self.builder.get_insert_block().unwrap();
+        let (caller, mem, ptr) = (self.function, self.mem, self.ptr);
+        self.function = function;
+        self.mem = function.get_nth_param(0).unwrap().into_pointer_value();
+        self.ptr = function.get_nth_param(1).unwrap().into_int_value();
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        self.loops.insert(stmt.clone(), function);
+        let caller_ssa = self.ssa.take();
+        self.ssa = caller_ssa
+            .is_some()
+            .then(|| Ssa::new(format!("{name}(%ptr0):")));
+        self.compile_rec(vec![stmt])?;
+        self.builder.build_return(Some(&self.ptr))?;
+        if let Some(mut ssa) = std::mem::replace(&mut self.ssa, caller_ssa) {
+            ssa.inst(format!("ret {}", ssa.ptr()));
+            let caller = self.ssa.as_mut().unwrap();
+            caller.functions.push(ssa.listing());
+        }
+
+        self.function = caller;
+        self.mem = mem;
+        self.ptr = ptr;
+        self.builder.position_at_end(caller_bb);
+        Ok(function)
+    }
+
+    /// Address of `mem[index]`. With `--grow` the tape grows first if `index` is past its
+    /// end, which may move it, so the address is only good until the next access
+    fn tape_ptr(&mut self, index: IntValue<'ctx>) -> Result<PointerValue<'ctx>> {
+        let i8_type = self.context.i8_type();
+        let Some(grow) = self.grow else {
+            return Ok(unsafe { self.builder.build_gep(i8_type, self.mem, &[index], "") }?);
+        };
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let cap = self
+            .builder
+            .build_load(i64_type, grow.cap.as_pointer_value(), "cap")?
+            .into_int_value();
+        // a negative index compares as huge and fails in `grow`
+        let wide = self.builder.build_int_z_extend(index, i64_type, "")?;
+        let outside = self
+            .builder
+            .build_int_compare(IntPredicate::UGE, wide, cap, "")?;
+        let grow_bb = self.context.append_basic_block(self.function, "grow");
+        let grown_bb = self.context.append_basic_block(self.function, "grown");
+        self.builder
+            .build_conditional_branch(outside, grow_bb, grown_bb)?;
+        self.builder.position_at_end(grow_bb);
+        self.builder
+            .build_direct_call(grow.function, &[index.into()], "")?;
+        self.builder.build_unconditional_branch(grown_bb)?;
+        self.builder.position_at_end(grown_bb);
+        let mem = self
+            .builder
+            .build_load(ptr_type, grow.mem.as_pointer_value(), "mem")?
+            .into_pointer_value();
+        Ok(unsafe { self.builder.build_gep(i8_type, mem, &[index], "") }?)
+    }
+
+    /// Address of `mem[ptr + offset]`
+    fn offset_ptr(&mut self, offset: i32) -> Result<PointerValue<'ctx>> {
+        let target = self.build_ptr_add(self.ptr, offset)?;
+        self.tape_ptr(target)
+    }
+
+    /// libc's `exit`, declared once however many runtime checks call it
+    fn exit_function(&self) -> FunctionValue<'ctx> {
+        self.module.get_function("exit").unwrap_or_else(|| {
+            let i32_type = self.context.i32_type();
+            let exit_type = self.context.void_type().fn_type(&[i32_type.into()], false);
+            self.module
+                .add_function("exit", exit_type, Some(Linkage::External))
+        })
+    }
+
+    /// `ptr + delta`, which with `--halt-on-overflow` exits with a message instead of
+    /// wrapping when it overflows `i32`
+    fn build_ptr_add(&mut self, ptr: IntValue<'ctx>, delta: i32) -> Result<IntValue<'ctx>> {
+        let i32_type = self.context.i32_type();
+        let delta_val = i32_type.const_int(delta as u64, true);
+        if let Some(bounds) = self.bounds {
+            return self.build_bounded_add(ptr, delta, bounds);
+        }
+        if !self.halt_on_overflow {
+            return Ok(self.builder.build_int_add(ptr, delta_val, "ptr")?);
+        }
+        let sadd = Intrinsic::find("llvm.sadd.with.overflow")
+            .and_then(|sadd| sadd.get_declaration(&self.module, &[i32_type.into()]))
+            .ok_or_eyre("LLVM has no llvm.sadd.with.overflow")?;
+        let sum = self
+            .builder
+            .build_direct_call(sadd, &[ptr.into(), delta_val.into()], "")?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let moved = self.builder.build_extract_value(sum, 0, "ptr")?;
+        let overflowed = self.builder.build_extract_value(sum, 1, "")?;
+        let overflow_bb = self.context.append_basic_block(self.function, "overflow");
+        let moved_bb = self.context.append_basic_block(self.function, "moved");
+        self.builder.build_conditional_branch(
+            overflowed.into_int_value(),
+            overflow_bb,
+            moved_bb,
+        )?;
+        self.builder.position_at_end(overflow_bb);
+        let report = self.overflow_function()?;
+        self.builder
+            .build_direct_call(report, &[ptr.into(), delta_val.into()], "")?;
+        self.builder.build_unreachable()?;
+        self.builder.position_at_end(moved_bb);
+        Ok(moved.into_int_value())
+    }
+
+    /// `ptr + delta` in `i64`, where it cannot overflow since `ptr` is on the tape.
+    /// `--wrap` brings it back onto the tape, `--safe` exits with the interpreters' error if
+    /// it left
+    fn build_bounded_add(
+        &mut self,
+        ptr: IntValue<'ctx>,
+        delta: i32,
+        bounds: Bounds,
+    ) -> Result<IntValue<'ctx>> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let len = i64_type.const_int(bounds.len as u64, false);
+        let wide = self.builder.build_int_z_extend(ptr, i64_type, "")?;
+        if bounds.wrap {
+            // the step is folded onto the tape, so one subtraction wraps the sum back
+            let step = (delta as i64).rem_euclid(bounds.len as i64) as u64;
+            let sum = self
+                .builder
+                .build_int_add(wide, i64_type.const_int(step, false), "")?;
+            let past = self
+                .builder
+                .build_int_compare(IntPredicate::UGE, sum, len, "")?;
+            let wrapped = self.builder.build_int_sub(sum, len, "")?;
+            let moved = self
+                .builder
+                .build_select(past, wrapped, sum, "")?
+                .into_int_value();
+            return Ok(self.builder.build_int_truncate(moved, i32_type, "ptr")?);
+        }
+        let sum = self
+            .builder
+            .build_int_add(wide, i64_type.const_int(delta as u64, true), "")?;
+        // a negative sum compares as huge
+        let outside = self
+            .builder
+            .build_int_compare(IntPredicate::UGE, sum, len, "")?;
+        let overrun_bb = self.context.append_basic_block(self.function, "overrun");
+        let moved_bb = self.context.append_basic_block(self.function, "moved");
+        self.builder
+            .build_conditional_branch(outside, overrun_bb, moved_bb)?;
+        self.builder.position_at_end(overrun_bb);
+        let msg = format!("pointer %lld exceeded tape {}\n", bounds.len);
+        let report = self.report_function("tape.overrun", &msg, i64_type.into())?;
+        self.builder.build_direct_call(report, &[sum.into()], "")?;
+        self.builder.build_unreachable()?;
+        self.builder.position_at_end(moved_bb);
+        Ok(self.builder.build_int_truncate(sum, i32_type, "ptr")?)
+    }
+
+    /// `void (i32 ptr, i32 delta)` reporting the overflowing move and exiting with 1, built
+    /// the first time a move needs it
+    fn overflow_function(&mut self) -> Result<FunctionValue<'ctx>> {
+        let i32_type = self.context.i32_type();
+        self.report_function(
+            "ptr.overflow",
+            "pointer %d overflowed moving by %d\n",
+            i32_type.into(),
+        )
+    }
+
+    /// `void name(ty, ..)` taking one `ty` per `%` in `fmt`, which prints them with `fmt` on
+    /// stderr and exits with 1. Built the first time it is needed
+    fn report_function(
+        &mut self,
+        name: &str,
+        fmt: &str,
+        ty: BasicMetadataTypeEnum<'ctx>,
+    ) -> Result<FunctionValue<'ctx>> {
+        if let Some(function) = self.module.get_function(name) {
+            return Ok(function);
+        }
+        let i32_type = self.context.i32_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let dprintf_type = i32_type.fn_type(&[i32_type.into(), ptr_type.into()], true);
+        let dprintf = self.module.get_function("dprintf").unwrap_or_else(|| {
+            self.module
+                .add_function("dprintf", dprintf_type, Some(Linkage::External))
+        });
+        let exit = self.exit_function();
+        let params = vec![ty; fmt.matches('%').count()];
+        let fn_type = self.context.void_type().fn_type(&params, false);
+        let function = self
+            .module
+            .add_function(name, fn_type, Some(Linkage::Internal));
+        for name in ["noinline", "cold", "noreturn"] {
+            let kind_id = Attribute::get_named_enum_kind_id(name);
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(kind_id, 0),
+            );
+        }
+
+        let caller_bb = self.builder.get_insert_block().unwrap();
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        self.build_flush()?;
+        let msg = self
+            .builder
+            .build_global_string_ptr(fmt, &format!("{name}.msg"))?
+            .as_pointer_value();
+        let mut call_args: Vec<BasicMetadataValueEnum> =
+            vec![i32_type.const_int(2, false).into(), msg.into()];
+        call_args.extend(
+            function
+                .get_params()
+                .into_iter()
+                .map(BasicMetadataValueEnum::from),
+        );
+        self.builder.build_direct_call(dprintf, &call_args, "")?;
+        self.builder
+            .build_direct_call(exit, &[i32_type.const_int(1, false).into()], "")?;
+        self.builder.build_unreachable()?;
+        self.builder.position_at_end(caller_bb);
+        Ok(function)
+    }
+
+    /// Address of `mem[ptr]`, reused within a block unless the tape can grow
+    fn cell_ptr(&mut self) -> Result<PointerValue<'ctx>> {
+        if self.grow.is_some() {
+            return self.tape_ptr(self.ptr);
+        }
+        let block = self.builder.get_insert_block().unwrap();
+        if let Some((cell_block, ptr, cell)) = self.cell
+            && cell_block == block
+            && ptr == self.ptr
+        {
+            return Ok(cell);
+        }
+        let cell = self.tape_ptr(self.ptr)?;
+        self.cell = Some((block, self.ptr, cell));
+        Ok(cell)
+    }
+
+    /// `n` wrapped to the cell width like the interpreters' arithmetic. The o2 IR keeps
+    /// deltas as `i32`, so a coalesced run of 257 `+` is `ValInc(257)`, which adds 1
+    fn cell_const(&self, n: i32) -> IntValue<'ctx> {
+        let cell_type = self.context.i8_type();
+        let wrapped = n as i64 as u64 & (u64::MAX >> (64 - cell_type.get_bit_width()));
+        debug_assert_eq!(wrapped, n as u8 as u64, "cells are 8 bits in every backend");
+        cell_type.const_int(wrapped, false)
+    }
+
+    /// Loads `mem[ptr]` widened to the `int` libc takes, sign-extended when `signed`
+    fn load_cell_i32(&mut self, signed: bool) -> Result<IntValue<'ctx>> {
+        let i32_type = self.context.i32_type();
+        let element_ptr = self.cell_ptr()?;
+        let val = self
+            .builder
+            .build_load(self.context.i8_type(), element_ptr, "")?
+            .into_int_value();
+        Ok(if signed {
+            self.builder.build_int_s_extend(val, i32_type, "")?
+        } else {
+            self.builder.build_int_z_extend(val, i32_type, "")?
+        })
+    }
+
+    fn build_io_call(
+        &self,
+        function: FunctionValue<'ctx>,
+        fd: u64,
+        buf: PointerValue<'ctx>,
+        len: u64,
+    ) -> Result<()> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        self.builder.build_direct_call(
+            function,
+            &[
+                i32_type.const_int(fd, false).into(),
+                buf.into(),
+                i64_type.const_int(len, false).into(),
+            ],
+            "",
+        )?;
+        Ok(())
+    }
+
+    /// One `write` of a private constant, or a `putchar` loop over it with libc, whose
+    /// stdout buffers anyway. `--buffered-runtime` writes what it holds first
+    fn build_output_const(&mut self, bytes: &[u8]) -> Result<()> {
+        let i32_type = self.context.i32_type();
+        let i8_type = self.context.i8_type();
+        let init = self.context.const_string(bytes, false);
+        let global = self.module.add_global(init.get_type(), None, "outconst");
+        global.set_linkage(Linkage::Private);
+        global.set_constant(true);
+        global.set_initializer(&init);
+        let zero = i32_type.const_zero();
+        let buf = unsafe {
+            self.builder.build_in_bounds_gep(
+                init.get_type(),
+                global.as_pointer_value(),
+                &[zero, zero],
+                "",
+            )
+        }?;
+        match self.io {
+            Io::Freestanding { write, .. } => {
+                self.build_io_call(write, 1, buf, bytes.len() as u64)?;
+            }
+            Io::Buffered { flush, .. } => {
+                // the bytes are already in one buffer, so they skip the output buffer
+                self.builder.build_direct_call(flush, &[], "")?;
+                let write = self.module.get_function("write").unwrap();
+                self.build_io_call(write, 1, buf, bytes.len() as u64)?;
+            }
+            Io::Libc { putchar, .. } => {
+                let current_bb = self.builder.get_insert_block().unwrap();
+                let body_bb = self.context.append_basic_block(self.function, "outconst");
+                let end_bb = self
+                    .context
+                    .append_basic_block(self.function, "outconst.end");
+                self.builder.build_unconditional_branch(body_bb)?;
+                self.builder.position_at_end(body_bb);
+                let i = self.builder.build_phi(i32_type, "i")?;
+                i.add_incoming(&[(&zero, current_bb)]);
+                let i_val = i.as_basic_value().into_int_value();
+                let byte_ptr = unsafe { self.builder.build_gep(i8_type, buf, &[i_val], "") }?;
+                let val = self.builder.build_load(i8_type, byte_ptr, "")?;
+                let val = self
+                    .builder
+                    .build_int_z_extend(val.into_int_value(), i32_type, "")?;
+                self.builder.build_direct_call(putchar, &[val.into()], "")?;
+                let next = self
+                    .builder
+                    .build_int_add(i_val, i32_type.const_int(1, false), "")?;
+                i.add_incoming(&[(&next, body_bb)]);
+                let done = self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    next,
+                    i32_type.const_int(bytes.len() as u64, false),
+                    "",
+                )?;
+                self.builder
+                    .build_conditional_branch(done, end_bb, body_bb)?;
+                self.builder.position_at_end(end_bb);
+            }
+        }
+        Ok(())
+    }
+
+    /// `printf("%d", cell)` for `:`, `dprintf(2, "%d\n", cell)` for `$`
+    fn build_print_num(&mut self, debug: bool) -> Result<()> {
+        match self.io {
+            Io::Libc { .. } => {}
+            Io::Buffered { .. } => Err(eyre!(
+                "'$' and ':' print through libc's stdio, which --buffered-runtime bypasses"
+            ))?,
+            Io::Freestanding { .. } => Err(eyre!("'$' and ':' need --runtime libc"))?,
+        }
+        let i32_type = self.context.i32_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let (name, fmt_name, fmt) = if debug {
+            ("dprintf", "fmt.dbg", "%d\n")
+        } else {
+            ("printf", "fmt.num", "%d")
+        };
+        let function = self.module.get_function(name).unwrap_or_else(|| {
+            let params = if debug {
+                &[i32_type.into(), ptr_type.into()][..]
+            } else {
+                &[ptr_type.into()][..]
+            };
+            let fn_type = i32_type.fn_type(params, true);
+            self.module
+                .add_function(name, fn_type, Some(Linkage::External))
+        });
+        let fmt = match self.module.get_global(fmt_name) {
+            Some(fmt) => fmt,
+            None => self.builder.build_global_string_ptr(fmt, fmt_name)?,
+        }
+        .as_pointer_value();
+
+        let val = self.load_cell_i32(self.signed_cells)?;
+        if debug {
+            let stderr = i32_type.const_int(2, false);
+            self.builder.build_direct_call(
+                function,
+                &[stderr.into(), fmt.into(), val.into()],
+                "",
+            )?;
+        } else {
+            self.builder
+                .build_direct_call(function, &[fmt.into(), val.into()], "")?;
+        }
+        Ok(())
+    }
+
+    /// Loop on the current cell, `body` gets the guard's element pointer, which stays valid
+    /// for the iteration it emits
+    fn build_loop(
+        &mut self,
+        body: impl FnOnce(&mut Self, PointerValue<'ctx>) -> Result<()>,
+    ) -> Result<()> {
+        let i32_type = self.context.i32_type();
+        let i8_type = self.context.i8_type();
+        // numbered on entry, counting from 1 like the listing reads
+        let number = self.canary.as_mut().map(|n| {
+            *n += 1;
+            *n
+        });
+        let block = self.ssa.as_mut().map(|ssa| ssa.loop_header("loop", true));
+        let current_bb = self.builder.get_insert_block().unwrap();
+        let cond_bb = self.context.append_basic_block(self.function, "cond");
+        self.builder.build_unconditional_branch(cond_bb)?;
+        self.builder.position_at_end(cond_bb);
+        let phi = self.builder.build_phi(i32_type, "ptr")?;
+        phi.add_incoming(&[(&self.ptr, current_bb)]);
+        self.ptr = phi.as_basic_value().into_int_value();
+
+        let element_ptr = self.cell_ptr()?;
+        let val = self.builder.build_load(i8_type, element_ptr, "")?;
+        let cond = self.builder.build_int_compare(
+            IntPredicate::NE,
+            val.into_int_value(),
+            i8_type.const_zero(),
+            "",
+        )?;
+
+        let true_bb = self.context.append_basic_block(self.function, "t");
+        let false_bb = self.context.append_basic_block(self.function, "f");
+        self.builder
+            .build_conditional_branch(cond, true_bb, false_bb)?;
+        self.builder.position_at_end(true_bb);
+        // the header dominates both successors, so its element pointer for the
+        // phi'd ptr stays valid there instead of being recomputed per iteration
+        self.cell = Some((true_bb, self.ptr, element_ptr));
+
+        body(self, element_ptr)?;
+
+        if let (Some(ssa), Some(block)) = (&mut self.ssa, block) {
+            ssa.loop_latch(block, true);
+        }
+        self.builder.build_unconditional_branch(cond_bb)?;
+        phi.add_incoming(&[(&self.ptr, self.builder.get_insert_block().unwrap())]);
+        self.builder.position_at_end(false_bb);
+        self.ptr = phi.as_basic_value().into_int_value();
+        self.cell = Some((false_bb, self.ptr, element_ptr));
+        if let (Some(number), Some(tape)) = (number, self.guard) {
+            self.build_guard_check(tape, Some(number))?;
+        }
+        Ok(())
+    }
+
+    /// A loop rotated to test its guard at the bottom, entered straight into the body
+    fn build_do_loop(&mut self, stmts: Vec<Stmt>) -> Result<()> {
+        let i32_type = self.context.i32_type();
+        let i8_type = self.context.i8_type();
+        let number = self.canary.as_mut().map(|n| {
+            *n += 1;
+            *n
+        });
+        let block = self.ssa.as_mut().map(|ssa| ssa.loop_header("do", false));
+        let current_bb = self.builder.get_insert_block().unwrap();
+        let body_bb = self.context.append_basic_block(self.function, "do");
+        self.builder.build_unconditional_branch(body_bb)?;
+        self.builder.position_at_end(body_bb);
+        let phi = self.builder.build_phi(i32_type, "ptr")?;
+        phi.add_incoming(&[(&self.ptr, current_bb)]);
+        self.ptr = phi.as_basic_value().into_int_value();
+        self.cell = None;
+
+        self.compile_rec(stmts)?;
+
+        if let (Some(ssa), Some(block)) = (&mut self.ssa, block) {
+            ssa.loop_latch(block, false);
+        }
+        let element_ptr = self.cell_ptr()?;
+        let val = self.builder.build_load(i8_type, element_ptr, "")?;
+        let cond = self.builder.build_int_compare(
+            IntPredicate::NE,
+            val.into_int_value(),
+            i8_type.const_zero(),
+            "",
+        )?;
+        let latch_bb = self.builder.get_insert_block().unwrap();
+        let end_bb = self.context.append_basic_block(self.function, "enddo");
+        self.builder
+            .build_conditional_branch(cond, body_bb, end_bb)?;
+        phi.add_incoming(&[(&self.ptr, latch_bb)]);
+        self.builder.position_at_end(end_bb);
+        // the latch is the only way out, so its element pointer is valid after the loop
+        self.cell = Some((end_bb, self.ptr, element_ptr));
+        if let (Some(number), Some(tape)) = (number, self.guard) {
+            self.build_guard_check(tape, Some(number))?;
+        }
+        Ok(())
+    }
+
     fn compile_rec(&mut self, prog: Vec<Stmt>) -> Result<()> {
         let i32_type = self.context.i32_type();
         let i8_type = self.context.i8_type();
-        for stmt in prog {
+        let mut prog = prog.into_iter().peekable();
+        while let Some(stmt) = prog.next() {
+            if let Some(ssa) = &mut self.ssa {
+                ssa.stmt(&stmt);
+            }
             match stmt {
-                Stmt::PtrInc(n) => {
-                    self.ptr = self.builder.build_int_add(
-                        self.ptr,
-                        i32_type.const_int(n as u64, true),
-                        "ptr",
-                    )?;
-                }
+                Stmt::PtrInc(n) => self.ptr = self.build_ptr_add(self.ptr, n)?,
                 Stmt::ValInc(n) => {
-                    let element_ptr =
-                        unsafe { self.builder.build_gep(i8_type, self.mem, &[self.ptr], "") }?;
+                    let element_ptr = self.cell_ptr()?;
+                    let val = self.builder.build_load(i8_type, element_ptr, "")?;
+                    let val =
+                        self.builder
+                            .build_int_add(val.into_int_value(), self.cell_const(n), "")?;
+                    self.builder.build_store(element_ptr, val)?;
+                }
+                Stmt::Set(n) => {
+                    let element_ptr = self.cell_ptr()?;
+                    self.builder.build_store(element_ptr, self.cell_const(n))?;
+                }
+                Stmt::AddMul(offset, factor) => {
+                    // the run a multiply loop lowered to shares one load of the guard, up
+                    // to an addmul that writes the guard itself
+                    let mut muls = vec![(offset, factor)];
+                    while muls.last().is_some_and(|&(offset, _)| offset != 0)
+                        && let Some(&Stmt::AddMul(offset, factor)) = prog.peek()
+                    {
+                        muls.push((offset, factor));
+                        prog.next();
+                    }
+                    if let Some(ssa) = &mut self.ssa {
+                        ssa.add_muls(&muls);
+                    }
+                    let element_ptr = self.cell_ptr()?;
+                    let guard = self
+                        .builder
+                        .build_load(i8_type, element_ptr, "")?
+                        .into_int_value();
+                    for (offset, factor) in muls {
+                        let val = self
+                            .builder
+                            .build_int_mul(guard, self.cell_const(factor), "")?;
+                        let target_ptr = self.offset_ptr(offset)?;
+                        let target_val = self.builder.build_load(i8_type, target_ptr, "")?;
+                        let target_val =
+                            self.builder
+                                .build_int_add(target_val.into_int_value(), val, "")?;
+                        self.builder.build_store(target_ptr, target_val)?;
+                    }
+                }
+                Stmt::Mod => {
+                    // the divisor first, as --grow may move the tape to reach it
+                    let next_ptr = self.offset_ptr(1)?;
+                    let divisor = self.builder.build_load(i8_type, next_ptr, "")?;
+                    let element_ptr = self.cell_ptr()?;
                     let val = self.builder.build_load(i8_type, element_ptr, "")?;
-                    let val = self.builder.build_int_add(
+                    let zero = self.builder.build_int_compare(
+                        IntPredicate::EQ,
+                        divisor.into_int_value(),
+                        i8_type.const_zero(),
+                        "",
+                    )?;
+                    // urem by 0 is undefined, so divide by 1 and keep the cell instead
+                    let divisor = self.builder.build_select(
+                        zero,
+                        i8_type.const_int(1, false),
+                        divisor.into_int_value(),
+                        "",
+                    )?;
+                    let rem = self.builder.build_int_unsigned_rem(
                         val.into_int_value(),
-                        i8_type.const_int(n as i8 as u64, true),
+                        divisor.into_int_value(),
                         "",
                     )?;
+                    let val = self.builder.build_select(zero, val, rem.into(), "")?;
                     self.builder.build_store(element_ptr, val)?;
                 }
-                Stmt::Loop(stmts) => {
-                    let current_bb = self.builder.get_insert_block().unwrap();
-                    let cond_bb = self.context.append_basic_block(self.main, "cond");
-                    self.builder.build_unconditional_branch(cond_bb)?;
-                    self.builder.position_at_end(cond_bb);
-                    let phi = self.builder.build_phi(i32_type, "ptr")?;
-                    phi.add_incoming(&[(&self.ptr, current_bb)]);
-                    self.ptr = phi.as_basic_value().into_int_value();
-
-                    let element_ptr =
-                        unsafe { self.builder.build_gep(i8_type, self.mem, &[self.ptr], "") }?;
+                Stmt::ValIncAt(offset, n) => {
+                    let target_ptr = self.offset_ptr(offset)?;
+                    let val = self.builder.build_load(i8_type, target_ptr, "")?;
+                    let val =
+                        self.builder
+                            .build_int_add(val.into_int_value(), self.cell_const(n), "")?;
+                    self.builder.build_store(target_ptr, val)?;
+                }
+                Stmt::SetAt(offset, n) => {
+                    let target_ptr = self.offset_ptr(offset)?;
+                    self.builder.build_store(target_ptr, self.cell_const(n))?;
+                }
+                Stmt::Scan(n) => {
+                    // a scan only reads and is not a loop in --o2 --text, so it is not
+                    // numbered or checked
+                    let canary = self.canary.take();
+                    self.compile_rec(vec![Stmt::Loop(vec![Stmt::PtrInc(n)])])?;
+                    self.canary = canary;
+                }
+                Stmt::Loop(stmts) => self.build_loop(|this, _| this.compile_rec(stmts))?,
+                Stmt::DoLoop(stmts) => self.build_do_loop(stmts)?,
+                Stmt::StridedLoop { stride, body } => self.build_loop(|this, element_ptr| {
+                    if let Some(ssa) = &mut this.ssa {
+                        body.iter().for_each(|stmt| ssa.stmt(stmt));
+                        ssa.stmt(&Stmt::PtrInc(stride));
+                    }
+                    // every add is addressed from the guard's element pointer, so an
+                    // iteration computes one index and steps the pointer once
+                    for stmt in body {
+                        let (offset, n) = match stmt {
+                            Stmt::ValInc(n) => (0, n),
+                            Stmt::ValIncAt(offset, n) => (offset, n),
+                            _ => unreachable!("strided loops only add"),
+                        };
+                        let target_ptr = match offset {
+                            // a growing tape may have moved since the guard was read
+                            _ if this.grow.is_some() || this.bounds.is_some() => {
+                                this.offset_ptr(offset)?
+                            }
+                            0 => element_ptr,
+                            _ => unsafe {
+                                this.builder.build_gep(
+                                    i8_type,
+                                    element_ptr,
+                                    &[i32_type.const_int(offset as u64, true)],
+                                    "",
+                                )
+                            }?,
+                        };
+                        let val = this.builder.build_load(i8_type, target_ptr, "")?;
+                        let val = this.builder.build_int_add(
+                            val.into_int_value(),
+                            this.cell_const(n),
+                            "",
+                        )?;
+                        this.builder.build_store(target_ptr, val)?;
+                    }
+                    this.ptr = this.build_ptr_add(this.ptr, stride)?;
+                    Ok(())
+                })?,
+                Stmt::If(stmts) => {
+                    let block = self.ssa.as_mut().map(Ssa::if_header);
+                    let element_ptr = self.cell_ptr()?;
                     let val = self.builder.build_load(i8_type, element_ptr, "")?;
                     let cond = self.builder.build_int_compare(
                         IntPredicate::NE,
@@ -130,56 +1838,133 @@ impl<'ctx> Compiler<'ctx> {
                         i8_type.const_zero(),
                         "",
                     )?;
-
-                    let true_bb = self.context.append_basic_block(self.main, "t");
-                    let false_bb = self.context.append_basic_block(self.main, "f");
+                    let current_bb = self.builder.get_insert_block().unwrap();
+                    let then_bb = self.context.append_basic_block(self.function, "then");
+                    let end_bb = self.context.append_basic_block(self.function, "endif");
                     self.builder
-                        .build_conditional_branch(cond, true_bb, false_bb)?;
-                    self.builder.position_at_end(true_bb);
+                        .build_conditional_branch(cond, then_bb, end_bb)?;
+                    self.builder.position_at_end(then_bb);
+                    let ptr = self.ptr;
+                    self.cell = Some((then_bb, ptr, element_ptr));
 
                     self.compile_rec(stmts)?;
 
-                    self.builder.build_unconditional_branch(cond_bb)?;
-                    phi.add_incoming(&[(&self.ptr, self.builder.get_insert_block().unwrap())]);
-                    self.builder.position_at_end(false_bb);
+                    if let (Some(ssa), Some(block)) = (&mut self.ssa, block) {
+                        ssa.if_end(block);
+                    }
+                    let then_end_bb = self.builder.get_insert_block().unwrap();
+                    self.builder.build_unconditional_branch(end_bb)?;
+                    self.builder.position_at_end(end_bb);
+                    let phi = self.builder.build_phi(i32_type, "ptr")?;
+                    phi.add_incoming(&[(&ptr, current_bb), (&self.ptr, then_end_bb)]);
                     self.ptr = phi.as_basic_value().into_int_value();
+                    self.cell = None;
                 }
-                Stmt::Output => {
-                    let element_ptr =
-                        unsafe { self.builder.build_gep(i8_type, self.mem, &[self.ptr], "") }?;
-                    let val = self.builder.build_load(i8_type, element_ptr, "")?;
-                    self.builder
-                        .build_direct_call(self.putchar, &[val.into()], "")?;
-                }
-                Stmt::Input => {
-                    let val = self.builder.build_direct_call(self.getchar, &[], "")?;
-                    let element_ptr =
-                        unsafe { self.builder.build_gep(i8_type, self.mem, &[self.ptr], "") }?;
-                    self.builder.build_store(
-                        element_ptr,
-                        val.try_as_basic_value().left().ok_or_eyre("")?,
-                    )?;
-                }
+                Stmt::Output => match self.io {
+                    Io::Libc { putchar, .. } => {
+                        // putchar writes its argument as an unsigned char, so the cell is
+                        // zero-extended even with --signed-cells
+                        let val = self.load_cell_i32(false)?;
+                        self.builder.build_direct_call(putchar, &[val.into()], "")?;
+                    }
+                    Io::Freestanding { write, .. } => {
+                        let element_ptr = self.cell_ptr()?;
+                        self.build_io_call(write, 1, element_ptr, 1)?;
+                    }
+                    Io::Buffered { putc, .. } => {
+                        let element_ptr = self.cell_ptr()?;
+                        let val = self.builder.build_load(i8_type, element_ptr, "")?;
+                        self.builder.build_direct_call(putc, &[val.into()], "")?;
+                    }
+                },
+                Stmt::OutputConst(bytes) => self.build_output_const(&bytes)?,
+                Stmt::DebugCell => self.build_print_num(true)?,
+                Stmt::OutputNum => self.build_print_num(false)?,
+                Stmt::Input => match self.io {
+                    Io::Libc { getchar, .. } => {
+                        let val = self
+                            .builder
+                            .build_direct_call(getchar, &[], "")?
+                            .try_as_basic_value()
+                            .left()
+                            .ok_or_eyre("getchar returns void")?
+                            .into_int_value();
+                        // EOF (-1) reads as 0 like the interpreters, 0xFF stays 0xFF
+                        let eof = self.builder.build_int_compare(
+                            IntPredicate::EQ,
+                            val,
+                            i32_type.const_all_ones(),
+                            "eof",
+                        )?;
+                        let byte = self.builder.build_int_truncate(val, i8_type, "")?;
+                        let val = self
+                            .builder
+                            .build_select(eof, i8_type.const_zero(), byte, "")?;
+                        let element_ptr = self.cell_ptr()?;
+                        self.builder.build_store(element_ptr, val)?;
+                    }
+                    Io::Freestanding { read, .. } => {
+                        // cell stays 0 if read hits EOF
+                        let element_ptr = self.cell_ptr()?;
+                        self.builder
+                            .build_store(element_ptr, i8_type.const_zero())?;
+                        self.build_io_call(read, 0, element_ptr, 1)?;
+                    }
+                    Io::Buffered { getc, .. } => {
+                        let val = self
+                            .builder
+                            .build_direct_call(getc, &[], "")?
+                            .try_as_basic_value()
+                            .left()
+                            .ok_or_eyre("io.getc returns void")?;
+                        let element_ptr = self.cell_ptr()?;
+                        self.builder.build_store(element_ptr, val)?;
+                    }
+                },
             }
         }
         Ok(())
     }
 }
 
-fn compile(prog: Vec<Stmt>) -> Result<String> {
-    Compiler::new(&Context::create())?.compile(prog)
+fn tune_for_host(context: &Context, function: FunctionValue) {
+    let cpu = TargetMachine::get_host_cpu_name();
+    let features = TargetMachine::get_host_cpu_features();
+    for (key, value) in [("target-cpu", cpu), ("target-features", features)] {
+        let attribute = context.create_string_attribute(key, &value.to_string_lossy());
+        function.add_attribute(AttributeLoc::Function, attribute);
+    }
 }
 
-pub fn main(args: Args, f: File) -> Result<()> {
-    let prog = o1::compile(f)?;
-    let prog = o2::compile(prog);
-    let ir = compile(prog)?;
-    if args.text {
-        print!("{ir}");
-        return Ok(());
+/// IR text, or the code `--emit` asks for
+fn compile(prog: Vec<Stmt>, args: &Args) -> Result<Vec<u8>> {
+    let context = Context::create();
+    let mut compiler = Compiler::new(&context, args)?;
+    let ir = compiler.compile(prog)?;
+    match args.emit {
+        Emit::Exe => Ok(ir.into_bytes()),
+        Emit::Asm => compiler.emit(args, FileType::Assembly),
+        Emit::Obj => compiler.emit(args, FileType::Object),
+        Emit::SsaIr => Ok(compiler.ssa.unwrap().listing().into_bytes()),
+        Emit::JsonAst | Emit::Rust | Emit::Tokens => {
+            unreachable!("{:?} is emitted before compiling", args.emit)
+        }
     }
-    let path = PathBuf::from_str(&args.input).unwrap();
-    let ir_path = path.with_added_extension("ll");
+}
+
+/// Hash of the IR and the flags clang links it with, so every flag that changes the
+/// code is covered
+fn cache_key(args: &Args, ir: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    ir.hash(&mut hasher);
+    (args.r#static, args.pie, args.no_pie, args.arch_native).hash(&mut hasher);
+    format!("{:016x}\n", hasher.finish())
+}
+
+pub fn main(args: Args, mut f: impl Read) -> Result<()> {
+    let mut src = Vec::new();
+    f.read_to_end(&mut src)?;
+    let path = PathBuf::from_str(&args.input[0]).unwrap();
     let exe_path = path.with_added_extension("out");
     let exe_path = if exe_path.is_relative() {
         let mut temp = PathBuf::from_str("./").unwrap();
@@ -188,15 +1973,226 @@ pub fn main(args: Args, f: File) -> Result<()> {
     } else {
         exe_path
     };
-    File::create(&ir_path)?.write_all(ir.as_bytes())?;
-    Command::new("clang")
-        .args([
-            "-o".as_ref(),
-            exe_path.as_os_str(),
-            "-O2".as_ref(),
-            ir_path.as_os_str(),
-        ])
-        .status()?;
-    Command::new(exe_path).status()?;
+    // the lints and --count-cells-used run on every build, cached or not
+    let Some(ir) = build(&args, &src, &path, &exe_path)? else {
+        return Ok(());
+    };
+    let cache_path = path.with_added_extension("sbfnj-cache");
+    let key = cache_key(&args, &ir);
+    // a cache hit skips clang
+    let cached = !args.no_cache
+        && exe_path.exists()
+        && fs::read_to_string(&cache_path).is_ok_and(|cached| cached == key);
+    if !cached {
+        // a failed build may leave a broken executable behind the old key
+        let _ = fs::remove_file(&cache_path);
+        write_code(&args, ir, &path, &exe_path)?;
+        fs::write(&cache_path, key)?;
+    }
+    run(&args, &exe_path)
+}
+
+/// IR, assembly or an object for `--text`/`--emit`, otherwise the IR to link
+fn build(args: &Args, src: &[u8], path: &Path, exe_path: &Path) -> Result<Option<Vec<u8>>> {
+    let prog = o2::load(args, src)?;
+    if args.emit == Emit::JsonAst {
+        println!("{}", o2::json_ast(&prog));
+        return Ok(None);
+    }
+    let max_mem = o2::cells_used(args, &prog);
+    if args.emit == Emit::Rust {
+        if args.wrap {
+            Err(eyre!("--emit rust indexes a plain array, it cannot --wrap"))?;
+        }
+        let args = Args {
+            max_mem,
+            ..args.clone()
+        };
+        let code = rust::emit(&prog, &args, &args.input.join(", "));
+        if args.text {
+            print!("{code}");
+        } else {
+            fs::write(path.with_added_extension("rs"), code)?;
+        }
+        return Ok(None);
+    }
+    let code = compile(
+        prog,
+        &Args {
+            max_mem,
+            ..args.clone()
+        },
+    )?;
+    if !args.text
+        && args.emit == Emit::Exe
+        && (args.lib || args.entry.as_deref().is_some_and(|entry| entry != "main"))
+    {
+        Err(eyre!(
+            "nothing to link without main, use --text or --emit with --entry or --lib"
+        ))?;
+    }
+    if args.text || args.emit != Emit::Exe {
+        write_code(args, code, path, exe_path)?;
+        return Ok(None);
+    }
+    Ok(Some(code))
+}
+
+/// Print `code` for `--text`, write it next to `path` for `--emit asm/obj`, or link the
+/// IR into `exe_path`
+fn write_code(args: &Args, code: Vec<u8>, path: &Path, exe_path: &Path) -> Result<()> {
+    if args.text {
+        stdout().write_all(&code)?;
+        return Ok(());
+    }
+    match args.emit {
+        Emit::Exe => {}
+        Emit::Asm => {
+            File::create(path.with_added_extension("s"))?.write_all(&code)?;
+            return Ok(());
+        }
+        Emit::Obj => {
+            File::create(path.with_added_extension("o"))?.write_all(&code)?;
+            return Ok(());
+        }
+        Emit::SsaIr => {
+            File::create(path.with_added_extension("ssa"))?.write_all(&code)?;
+            return Ok(());
+        }
+        Emit::JsonAst | Emit::Rust | Emit::Tokens => {
+            unreachable!("{:?} is emitted before compiling", args.emit)
+        }
+    }
+    let ir = code;
+    let ir_path = path.with_added_extension("ll");
+    File::create(&ir_path)?.write_all(&ir)?;
+    let mut clang = Command::new("clang");
+    clang.args([
+        "-o".as_ref(),
+        exe_path.as_os_str(),
+        "-O2".as_ref(),
+        ir_path.as_os_str(),
+    ]);
+    if args.r#static {
+        clang.arg("-static");
+    }
+    if args.pie {
+        clang.arg("-pie");
+    }
+    if args.no_pie {
+        clang.arg("-no-pie");
+    }
+    if args.arch_native {
+        clang.arg("-march=native");
+    }
+    let status = clang.status()?;
+    if !status.success() {
+        Err(eyre!("clang failed with {status}"))?;
+    }
+    Ok(())
+}
+
+/// `--multi`: every input compiled on its own into `i32 bf_<file stem>()` with its own
+/// tape, linked into one module whose `main` runs the program its first argument names.
+/// Builds `<first input>.out` without running it or caching it
+pub fn multi(args: Args, srcs: Vec<Vec<u8>>) -> Result<()> {
+    if matches!(
+        args.emit,
+        Emit::JsonAst | Emit::Rust | Emit::SsaIr | Emit::Tokens
+    ) {
+        Err(eyre!(
+            "--multi builds one LLVM module, --emit json-ast, rust, ssa-ir and tokens are per program"
+        ))?;
+    }
+    let context = Context::create();
+    let mut programs: Vec<(String, String)> = Vec::new();
+    let mut linked: Option<Compiler> = None;
+    for (input, src) in args.input.iter().zip(srcs) {
+        let path = Path::new(input);
+        let name = path
+            .file_stem()
+            .ok_or_else(|| eyre!("{input} has no file name to name its program after"))?
+            .to_string_lossy()
+            .into_owned();
+        let symbol: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let symbol = format!("bf_{symbol}");
+        if let Some((other, _)) = programs.iter().find(|(_, other)| *other == symbol) {
+            Err(eyre!(
+                "programs {other} and {name} would both be named {symbol}"
+            ))?;
+        }
+        let one = Args {
+            input: vec![input.clone()],
+            entry: Some(symbol.clone()),
+            ..args.clone()
+        };
+        let prog = o2::load(&one, src.as_slice())?;
+        let one = Args {
+            max_mem: o2::cells_used(&one, &prog),
+            ..one
+        };
+        let mut compiler = Compiler::new(&context, &one)?;
+        compiler.compile(prog)?;
+        match &linked {
+            None => linked = Some(compiler),
+            Some(first) => first
+                .module
+                .link_in_module(compiler.module)
+                .map_err(|e| eyre!("linking {input} failed: {}", e.to_string_lossy()))?,
+        }
+        programs.push((name, symbol));
+    }
+    let mut compiler = linked.ok_or_eyre("--multi needs at least one input")?;
+    let ir = compiler.build_dispatcher(&programs)?;
+    let code = match args.emit {
+        Emit::Exe => ir.into_bytes(),
+        Emit::Asm => compiler.emit(&args, FileType::Assembly)?,
+        Emit::Obj => compiler.emit(&args, FileType::Object)?,
+        Emit::JsonAst | Emit::Rust | Emit::SsaIr | Emit::Tokens => unreachable!("rejected above"),
+    };
+    let path = PathBuf::from(&args.input[0]);
+    write_code(&args, code, &path, &path.with_added_extension("out"))
+}
+
+fn run(args: &Args, exe_path: &Path) -> Result<()> {
+    let mut exe = Command::new(exe_path);
+    let feed = args.random_input.is_some() || args.hex_input;
+    if feed {
+        exe.stdin(Stdio::piped());
+    }
+    let check = args.hex_output || args.expect.is_some();
+    if check {
+        exe.stdout(Stdio::piped());
+    }
+    let mut child = exe.spawn()?;
+    if feed {
+        let mut stdin = child.stdin.take().unwrap();
+        let (seed, hex) = (args.random_input, args.hex_input);
+        // feed until the input ends or the program exits and the pipe breaks
+        thread::spawn(move || {
+            let mut input = Input::new(seed, hex);
+            loop {
+                let chunk: Vec<u8> = Iterator::take(&mut input, 4096).collect();
+                if chunk.is_empty() || stdin.write_all(&chunk).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    if check {
+        let mut output = Output::for_args(args)?;
+        let copied = std::io::copy(&mut child.stdout.take().unwrap(), &mut output);
+        if let Err(e) = copied.map_err(Into::into).and_then(|_| output.finish()) {
+            let _ = child.kill();
+            return Err(e);
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        Err(eyre!("{} failed with {status}", exe_path.display()))?;
+    }
     Ok(())
 }
@@ -1,18 +1,26 @@
-#![feature(path_add_extension)]
-
+mod compare;
+mod io;
 mod jit;
 mod llvm;
 mod o0;
 mod o1;
 mod o2;
+mod rust;
+mod signal;
+mod tape;
 
-use std::fs::File;
+use std::{
+    fs::File,
+    io::Read,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
 
-use clap::Parser;
-use eyre::Result;
+use clap::{Parser, ValueEnum, builder::RangedU64ValueParser};
+use eyre::{Result, eyre};
 
 /// Standard BrainFuck of NanJing
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 struct Args {
     /// Emit IR and exit
     #[arg(long)]
@@ -26,19 +34,448 @@ struct Args {
     /// More optimizations
     #[arg(long, group = "opt")]
     o2: bool,
-    /// JIT (TDOO)
+    /// JIT (TODO)
     #[arg(long, group = "opt")]
     jit: bool,
     /// Emit LLVM IR and call clang
     #[arg(long, group = "opt")]
     llvm: bool,
-    /// Input filename
-    input: String,
+    /// Only parse, report front-end throughput to stderr
+    #[arg(long, group = "opt")]
+    parse_only: bool,
+    /// Run every backend on the same input and report where their outputs diverge
+    #[arg(long, group = "opt")]
+    compare_backends: bool,
+    /// Read Brainfuck from stdin line by line and run it on a persistent tape
+    #[arg(long, group = "opt", conflicts_with = "random_input")]
+    repl: bool,
+    /// Step through the program on the o0 interpreter by commands read from stdin: step,
+    /// back, continue, print and quit, each with an optional count
+    #[arg(long, group = "opt", conflicts_with_all = ["random_input", "hex_input", "mmio", "snapshot_every", "ext", "max_steps", "load_state"])]
+    debug: bool,
+    /// Steps --debug keeps to go back over
+    #[arg(long, value_name = "N", default_value_t = 1000, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    history: usize,
+    /// Link the LLVM executable statically
+    #[arg(long, conflicts_with = "pie")]
+    r#static: bool,
+    /// Link the LLVM executable as position-independent
+    #[arg(long, conflicts_with = "no_pie")]
+    pie: bool,
+    /// Link the LLVM executable as position-dependent
+    #[arg(long)]
+    no_pie: bool,
+    /// Rebuild the LLVM executable even if its source and flags are unchanged
+    #[arg(long)]
+    no_cache: bool,
+    /// Tape size in cells, moving the pointer off the tape is an error
+    #[arg(long, value_name = "CELLS", default_value_t = 30000, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    max_mem: usize,
+    /// Size the tape from the --count-cells-used bound when there is one, else --max-mem
+    #[arg(long, conflicts_with = "max_mem")]
+    auto_mem: bool,
+    /// Print a static bound on the tape cells the o2 program can reach to stderr
+    #[arg(long)]
+    count_cells_used: bool,
+    /// Symbol of the generated LLVM function (default: main, bf_run with --lib)
+    #[arg(long, value_name = "NAME")]
+    entry: Option<String>,
+    /// Generate `i32 bf_run(ptr tape)` on a caller-provided tape, returning the final pointer
+    #[arg(long)]
+    lib: bool,
+    /// Compile every input as its own program into one LLVM executable, whose first
+    /// argument names the program to run
+    #[arg(long, requires = "llvm", conflicts_with_all = ["entry", "lib"])]
+    multi: bool,
+    /// Compile each top-level loop into its own LLVM function
+    #[arg(long)]
+    outline_loops: bool,
+    /// Data layout of the emitted LLVM module
+    #[arg(long, value_name = "LAYOUT")]
+    datalayout: Option<String>,
+    /// Tune the LLVM executable for the host CPU, the result may not run elsewhere
+    #[arg(long)]
+    arch_native: bool,
+    /// Leave out timings and host-dependent tuning, so two runs print identical output and IR
+    #[arg(long, conflicts_with = "arch_native")]
+    deterministic: bool,
+    /// Comma-separated o2 passes to run in order (default: all)
+    #[arg(long, value_delimiter = ',')]
+    opt_passes: Option<Vec<String>>,
+    /// Run the o2 passes at most N times while they keep changing the program
+    #[arg(long, value_name = "N", default_value_t = 8)]
+    opt_iterations: u32,
+    /// Rerun pure o2 loops that nest control flow every time they are entered, instead of
+    /// reusing the tape they left the last time they started from the same cells
+    #[arg(long)]
+    no_loop_memo: bool,
+    /// Refuse o2 programs with more than N statements
+    #[arg(long, value_name = "N", default_value_t = 10_000_000)]
+    max_stmts: usize,
+    /// Refuse o2 programs nesting loops more than N deep
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    max_depth: usize,
+    /// Stop execution after N output bytes
+    #[arg(long, value_name = "N")]
+    limit_output: Option<usize>,
+    /// Reject bytes other than the 8 commands and whitespace instead of ignoring them
+    #[arg(long)]
+    strict: bool,
+    /// Reject a source that is not valid UTF-8
+    #[arg(long)]
+    validate_utf8_source: bool,
+    /// Enable the non-standard commands '$' (cell as decimal on stderr), ':' (cell as
+    /// decimal) and '%' (cell modulo the next cell)
+    #[arg(long)]
+    ext: bool,
+    /// Show cells as signed in '$', ':', --snapshot-every and the REPL, loops still test for
+    /// zero
+    #[arg(long)]
+    signed_cells: bool,
+    /// Print o1 line numbers and jump targets in hex
+    #[arg(long, requires = "text")]
+    hex: bool,
+    /// After the o1 listing, list every loop as its '[' and ']' lines with its nesting depth
+    #[arg(long, requires = "text")]
+    xref: bool,
+    /// Spaces per loop nesting level in the o1 listing
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "text")]
+    indent: usize,
+    /// Leave line numbers out of the o1 listing, jump targets still refer to them
+    #[arg(long, requires = "text", conflicts_with = "xref")]
+    no_line_numbers: bool,
+    /// Unroll loops provably running at most N times, 0 disables
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    unroll: u32,
+    /// Run the program on the o1 interpreter first and only --unroll the loops that ran
+    /// hot there
+    #[arg(long, requires = "unroll", conflicts_with = "from_stmt")]
+    profile_guided: bool,
+    /// Input for the --profile-guided run, which otherwise reads EOF from the start
+    #[arg(long, value_name = "FILE", requires = "profile_guided")]
+    profile_input: Option<PathBuf>,
+    /// Input is o2 IR as printed by --o2 --text, run it without optimizing
+    #[arg(long)]
+    from_stmt: bool,
+    /// Check that optimizing kept the order and bytes of the program's I/O by running it
+    /// before and after on the same random input (--random-input seed, default 0)
+    #[arg(long)]
+    interleave_check: bool,
+    /// Warn about pure loops that move the pointer but are not scans
+    #[arg(long)]
+    warn_unbalanced_ptr: bool,
+    /// Warn about clear loops like '[-]' on a cell that is already zero
+    #[arg(long)]
+    warn_redundant_clears: bool,
+    /// Print the source without loops that never run and '+-' or '<>' that cancel,
+    /// checked to do the same I/O as the original
+    #[arg(long, conflicts_with = "from_stmt")]
+    fix: bool,
+    /// Make the last cell an I/O port for the o0 interpreter: moving onto it reads a byte
+    /// into it, every '+' or '-' on it writes the new value
+    #[arg(long)]
+    mmio: bool,
+    /// Write a text frame of the o0 tape every K commands, plus one at exit
+    #[arg(long, value_name = "K", requires = "snapshot_dir", value_parser = clap::value_parser!(u64).range(1..))]
+    snapshot_every: Option<u64>,
+    /// Directory for --snapshot-every frames, created if missing
+    #[arg(long, value_name = "DIR", requires = "snapshot_every")]
+    snapshot_dir: Option<PathBuf>,
+    /// Stop the o0 interpreter after N more commands
+    #[arg(long, value_name = "N")]
+    max_steps: Option<u64>,
+    /// When an interpreter stops because the pointer left the tape, print the cells around
+    /// it and where the program was to stderr
+    #[arg(long)]
+    tape_dump_on_error: bool,
+    /// Save the o0 tape, pointer, position and I/O counts to FILE when --max-steps stops
+    /// the program
+    #[arg(long, value_name = "FILE", requires = "max_steps")]
+    save_state: Option<PathBuf>,
+    /// Resume the o0 interpreter from a --save-state file of the same program, skipping the
+    /// input it already read
+    #[arg(long, value_name = "FILE")]
+    load_state: Option<PathBuf>,
+    /// Run the o2 interpreter N times reusing one tape, timing each run
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    runs: usize,
+    /// Run the o2 interpreter N times, every run reading on from where the last one stopped
+    #[arg(long, value_name = "N", default_value_t = 1, conflicts_with = "runs", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    repeat: usize,
+    /// Don't clear the tape and pointer between --runs or --repeat
+    #[arg(long)]
+    keep_tape: bool,
+    /// Print o2 IR statistics to stderr
+    #[arg(long)]
+    ir_stats: bool,
+    /// Print o2 statistics before and after optimizing, and the passes that changed the
+    /// program, as a table on stderr
+    #[arg(long)]
+    opt_report: bool,
+    /// Print --ir-stats, --opt-report or --caps as JSON to stdout instead of running the
+    /// program (needs the serde feature, except for --caps)
+    #[arg(long)]
+    json: bool,
+    /// Print what this build supports as key=value lines and exit
+    #[arg(long)]
+    caps: bool,
+    /// When output reaches stdout. Unset, the interpreters flush at newlines and o2 also
+    /// before each read, compiled code keeps libc's buffering
+    #[arg(long, value_enum, conflicts_with = "raw_io")]
+    flush_on: Option<io::FlushOn>,
+    /// Decode stdin from hex pairs before feeding it to ','
+    #[arg(long, conflicts_with_all = ["random_input", "repl"])]
+    hex_input: bool,
+    /// Write output as two hex digits per byte
+    #[arg(long)]
+    hex_output: bool,
+    /// Write interpreter output with write(2) calls instead of the buffered stdout, for
+    /// measuring syscall overhead (needs the raw-io feature)
+    #[arg(long, conflicts_with = "hex_output")]
+    raw_io: bool,
+    /// Compare the program's output with FILE instead of printing it, failing at the
+    /// first byte that differs
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["hex_output", "raw_io", "repl", "compare_backends"])]
+    expect: Option<PathBuf>,
+    /// Report executed I/O operations to stderr
+    #[arg(long)]
+    count_io: bool,
+    /// Feed a deterministic pseudo-random byte stream to ',' instead of stdin
+    #[arg(long, value_name = "SEED", num_args = 0..=1, require_equals = true, default_missing_value = "0")]
+    random_input: Option<u64>,
+    /// What the LLVM backend produces
+    #[arg(long, value_enum, default_value_t)]
+    emit: llvm::Emit,
+    /// Wrap the static LLVM tape in canaries and fail at exit if they were overwritten
+    #[arg(long)]
+    tape_guard: bool,
+    /// Also check the --tape-guard canaries every time a loop exits, reporting which loop of
+    /// --o2 --text overran
+    #[arg(long, requires = "tape_guard", conflicts_with = "outline_loops")]
+    canary: bool,
+    /// Grow the LLVM tape with realloc when the pointer moves past its right end instead
+    /// of running off it, checking every access
+    #[arg(long)]
+    grow: bool,
+    /// Error when a pointer move overflows the pointer type itself, usize in the
+    /// interpreters and i32 in LLVM code, instead of wrapping it around
+    #[arg(long)]
+    halt_on_overflow: bool,
+    /// Check every LLVM pointer move against the tape and exit with the interpreters' error
+    /// when it leaves it, instead of the undefined behavior of running off it
+    #[arg(long, conflicts_with_all = ["grow", "wrap"])]
+    safe: bool,
+    /// Make the tape circular in every backend, moving off one end comes back at the other
+    #[arg(long, conflicts_with_all = ["grow", "auto_mem"])]
+    wrap: bool,
+    /// Runtime the LLVM backend links against
+    #[arg(long, value_enum, default_value_t)]
+    runtime: llvm::Runtime,
+    /// Batch the LLVM executable's output into write(2) calls flushed when full, before
+    /// reading and at exit, and read input in blocks, instead of a libc call per byte
+    #[arg(long, conflicts_with = "flush_on")]
+    buffered_runtime: bool,
+    /// Input filenames, concatenated in order unless --multi, gzip-compressed ones are
+    /// decompressed (needs the gzip feature)
+    #[arg(required_unless_present_any = ["repl", "caps"])]
+    input: Vec<String>,
+}
+
+/// First bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Contents of a source file, decompressed if it starts like a gzip stream, whatever its
+/// name
+fn read_source(input: &str) -> Result<Vec<u8>> {
+    let mut src = Vec::new();
+    File::open(input)?.read_to_end(&mut src)?;
+    if !src.starts_with(&GZIP_MAGIC) {
+        return Ok(src);
+    }
+    #[cfg(feature = "gzip")]
+    {
+        let mut plain = Vec::new();
+        flate2::read::GzDecoder::new(src.as_slice())
+            .read_to_end(&mut plain)
+            .map_err(|e| eyre!("cannot decompress {input}: {e}"))?;
+        Ok(plain)
+    }
+    #[cfg(not(feature = "gzip"))]
+    Err(eyre!(
+        "{input} is gzip-compressed, which needs sbfnj built with the gzip feature"
+    ))
+}
+
+/// Control bytes other than whitespace hardly appear in text, so a source with more than
+/// 10% of them is most likely a binary passed by mistake, which would run as comments
+fn check_source(args: &Args, src: &[u8]) -> Result<()> {
+    if args.validate_utf8_source
+        && let Err(e) = str::from_utf8(src)
+    {
+        Err(eyre!("source is not valid UTF-8: {e}"))?;
+    }
+    let control = src
+        .iter()
+        .filter(|&&c| (c < 0x20 && !matches!(c, b'\t' | b'\n' | b'\r')) || c == 0x7f)
+        .count();
+    if control * 10 > src.len() {
+        let msg = format!(
+            "source has {control} control bytes in {}, is it a binary?",
+            src.len()
+        );
+        if args.strict {
+            Err(eyre!("{msg}"))?;
+        }
+        eprintln!("warning: {msg}");
+    }
+    Ok(())
+}
+
+/// `--caps`: backends, cargo features and tools of this build, for scripts picking flags
+/// before running it
+fn print_caps(json: bool) {
+    fn names<T: ValueEnum>() -> Vec<String> {
+        T::value_variants()
+            .iter()
+            .filter_map(|v| Some(v.to_possible_value()?.get_name().to_owned()))
+            .collect()
+    }
+    let clang = Command::new("clang")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    let flags = [
+        ("llvm", true),
+        // --jit is a stub
+        ("jit", false),
+        ("clang", clang),
+        ("sigint", cfg!(feature = "sigint")),
+        ("raw_io", cfg!(feature = "raw-io")),
+        ("serde", cfg!(feature = "serde")),
+        ("gzip", cfg!(feature = "gzip")),
+    ];
+    let lists = [
+        (
+            "backends",
+            ["o0", "o1", "o2", "llvm"].map(str::to_owned).to_vec(),
+        ),
+        ("emit", names::<llvm::Emit>()),
+        ("runtime", names::<llvm::Runtime>()),
+    ];
+    let version = env!("CARGO_PKG_VERSION");
+    if json {
+        let mut fields = vec![format!(r#""version":"{version}""#)];
+        fields.extend(flags.iter().map(|(key, val)| format!(r#""{key}":{val}"#)));
+        fields.extend(lists.iter().map(|(key, vals)| {
+            let vals: Vec<_> = vals.iter().map(|val| format!(r#""{val}""#)).collect();
+            format!(r#""{key}":[{}]"#, vals.join(","))
+        }));
+        println!("{{{}}}", fields.join(","));
+    } else {
+        println!("version={version}");
+        for (key, val) in flags {
+            println!("{key}={val}");
+        }
+        for (key, vals) in lists {
+            println!("{key}={}", vals.join(","));
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
-    let f = File::open(&args.input)?;
+    if args.caps {
+        print_caps(args.json);
+        return Ok(());
+    }
+    if args.json && !args.ir_stats && !args.opt_report {
+        Err(eyre!("--json needs --ir-stats, --opt-report or --caps"))?;
+    }
+    if args.json && !cfg!(feature = "serde") {
+        Err(eyre!(
+            "--json with --ir-stats or --opt-report needs sbfnj built with the serde feature"
+        ))?;
+    }
+    if args.opt_report && !args.o2 {
+        Err(eyre!("--opt-report needs --o2"))?;
+    }
+    signal::install();
+    if args.multi {
+        let mut srcs = Vec::new();
+        for input in &args.input {
+            let src = read_source(input)?;
+            check_source(&args, &src)?;
+            if !args.from_stmt {
+                o1::warn_empty_loops(o1::Syntax::new(&args), &src);
+            }
+            srcs.push(src);
+        }
+        return llvm::multi(args, srcs);
+    }
+    let mut src = Vec::new();
+    for input in &args.input {
+        src.extend(read_source(input)?);
+    }
+    let f = src.as_slice();
+    check_source(&args, f)?;
+    if args.emit == llvm::Emit::Tokens {
+        // the same for every backend, nothing is compiled
+        return o1::tokens(&args, f);
+    }
+    // the REPL warns per snippet
+    if !args.from_stmt && !args.repl {
+        o1::warn_empty_loops(o1::Syntax::new(&args), f);
+    }
+    if args.fix {
+        return o2::fix(&args, f);
+    }
+    for (set, flag) in [
+        (args.from_stmt, "--from-stmt"),
+        (args.auto_mem, "--auto-mem"),
+        (args.count_cells_used, "--count-cells-used"),
+        (args.interleave_check, "--interleave-check"),
+    ] {
+        if set && !(args.o2 || args.llvm) {
+            Err(eyre!("{flag} needs --o2 or --llvm"))?;
+        }
+    }
+    if args.raw_io && !cfg!(feature = "raw-io") {
+        Err(eyre!("--raw-io needs sbfnj built with the raw-io feature"))?;
+    }
+    if args.emit == llvm::Emit::JsonAst && !cfg!(feature = "serde") {
+        Err(eyre!(
+            "--emit json-ast needs sbfnj built with the serde feature"
+        ))?;
+    }
+    if args.raw_io && (args.llvm || args.compare_backends) {
+        Err(eyre!("--raw-io is only supported by the interpreters"))?;
+    }
+    if args.tape_dump_on_error && (args.llvm || args.compare_backends) {
+        Err(eyre!(
+            "--tape-dump-on-error is only supported by the interpreters"
+        ))?;
+    }
+    if args.mmio
+        && (args.o1 || args.o2 || args.jit || args.llvm || args.compare_backends || args.repl)
+    {
+        Err(eyre!("--mmio is only supported by the o0 interpreter"))?;
+    }
+    if args.snapshot_every.is_some()
+        && (args.o1 || args.o2 || args.jit || args.llvm || args.compare_backends || args.repl)
+    {
+        Err(eyre!(
+            "--snapshot-every is only supported by the o0 interpreter"
+        ))?;
+    }
+    if (args.max_steps.is_some() || args.load_state.is_some())
+        && (args.o1 || args.o2 || args.jit || args.llvm || args.compare_backends || args.repl)
+    {
+        Err(eyre!(
+            "--max-steps and --load-state are only supported by the o0 interpreter"
+        ))?;
+    }
     if args.o1 {
         o1::main(args, f)
     } else if args.o2 {
@@ -47,6 +484,14 @@ fn main() -> Result<()> {
         jit::main(args, f)
     } else if args.llvm {
         llvm::main(args, f)
+    } else if args.parse_only {
+        o1::parse_only(args, f)
+    } else if args.compare_backends {
+        compare::main(args, f)
+    } else if args.repl {
+        o2::repl(args, f)
+    } else if args.debug {
+        o0::debug(args, f)
     } else {
         o0::main(args, f)
     }
@@ -1,13 +1,148 @@
 use std::{
-    fs::File,
-    io::{Read, Write, stdin, stdout},
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{Read, Write},
+    path::Path,
 };
 
 use eyre::{Result, eyre};
 
-use crate::Args;
+use crate::{
+    Args,
+    io::{Input, IoCounts, Output},
+    o1, signal,
+    tape::{PtrMoves, SPIN_WARNING, SpinGuard, cell_num, dump_tape, tape_window},
+};
+
+/// Cells per row of a snapshot frame
+const FRAME_WIDTH: usize = 16;
+
+/// Tape up to the last nonzero cell or the pointer, in rows of [`FRAME_WIDTH`] cells with
+/// the current one bracketed
+fn write_frame(
+    dir: &Path,
+    frame: usize,
+    step: u64,
+    mem: &[u8],
+    ptr: usize,
+    signed: bool,
+) -> Result<()> {
+    let last = mem.iter().rposition(|&c| c != 0).unwrap_or(0).max(ptr);
+    let mut text = format!("step {step}, ptr {ptr}\n");
+    for (row, cells) in mem[..=last].chunks(FRAME_WIDTH).enumerate() {
+        text += &format!("{:>6}:", row * FRAME_WIDTH);
+        for (i, &cell) in cells.iter().enumerate() {
+            let cell = cell_num(cell, signed);
+            if row * FRAME_WIDTH + i == ptr {
+                text += &format!("[{cell:>4}]");
+            } else {
+                text += &format!(" {cell:>4} ");
+            }
+        }
+        text += "\n";
+    }
+    fs::write(dir.join(format!("frame-{frame:06}.txt")), text)?;
+    Ok(())
+}
+
+/// First line of a `--save-state` file, bumped when the format changes
+const STATE_HEADER: &str = "sbfnj o0 state 1";
+
+/// Cells per line of a state file's tape
+const STATE_WIDTH: usize = 32;
+
+/// Everything `--load-state` needs to resume where `--max-steps` stopped `--save-state`. Saved
+/// as `key value` lines, then the tape up to its last nonzero cell in hex
+#[derive(Debug, Default)]
+struct State {
+    /// Hash of the source, a state only resumes the program it came from
+    source: u64,
+    pc: usize,
+    ptr: usize,
+    steps: u64,
+    counts: IoCounts,
+    mem: Vec<u8>,
+}
+
+fn source_hash(instructions: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    instructions.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl State {
+    fn save(&self, path: &Path) -> Result<()> {
+        let mut text = format!("{STATE_HEADER}\n");
+        let counts = &self.counts;
+        let fields = [
+            ("pc", self.pc as u64),
+            ("ptr", self.ptr as u64),
+            ("steps", self.steps),
+            ("outputs", counts.outputs as u64),
+            ("inputs", counts.inputs as u64),
+            ("input-bytes", counts.input_bytes as u64),
+            ("tape", self.mem.len() as u64),
+        ];
+        writeln!(text, "source {:016x}", self.source)?;
+        for (key, value) in fields {
+            writeln!(text, "{key} {value}")?;
+        }
+        let used = self
+            .mem
+            .iter()
+            .rposition(|&c| c != 0)
+            .map_or(0, |last| last + 1);
+        for cells in self.mem[..used].chunks(STATE_WIDTH) {
+            cells
+                .iter()
+                .try_for_each(|cell| write!(text, "{cell:02x}"))?;
+            text += "\n";
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let bad = || eyre!("{} is not a saved o0 state", path.display());
+        let mut lines = text.lines();
+        if lines.next() != Some(STATE_HEADER) {
+            Err(bad())?;
+        }
+        let mut state = State::default();
+        let source = lines.next().and_then(|line| line.strip_prefix("source "));
+        state.source = u64::from_str_radix(source.ok_or_else(bad)?, 16).map_err(|_| bad())?;
+        let mut field = |key: &str| -> Result<usize> {
+            let line = lines.next().ok_or_else(bad)?;
+            let value = line.strip_prefix(key).and_then(|v| v.strip_prefix(' '));
+            value.ok_or_else(bad)?.parse().map_err(|_| bad())
+        };
+        state.pc = field("pc")?;
+        state.ptr = field("ptr")?;
+        state.steps = field("steps")? as u64;
+        state.counts.outputs = field("outputs")?;
+        state.counts.inputs = field("inputs")?;
+        state.counts.input_bytes = field("input-bytes")?;
+        let len = field("tape")?;
+        for line in lines {
+            for i in (0..line.len()).step_by(2) {
+                let cell = line.get(i..i + 2).ok_or_else(bad)?;
+                state
+                    .mem
+                    .push(u8::from_str_radix(cell, 16).map_err(|_| bad())?);
+            }
+        }
+        if state.mem.len() > len || state.ptr >= len {
+            Err(bad())?;
+        }
+        state.mem.resize(len, 0);
+        Ok(state)
+    }
+}
 
-pub fn main(args: Args, mut f: File) -> Result<()> {
+pub fn main(args: Args, mut f: impl Read) -> Result<()> {
     if args.text {
         return Err(eyre!("o0 interpreter has no IR"));
     }
@@ -15,26 +150,105 @@ pub fn main(args: Args, mut f: File) -> Result<()> {
     let mut instructions = Vec::new();
     f.read_to_end(&mut instructions)?;
     let instructions = instructions;
+    if args.strict {
+        o1::compile(instructions.as_slice(), o1::Syntax::new(&args))?;
+    }
     let mut pc = 0;
     let mut nest_level;
-    let mut mem = vec![0u8; 30000];
+    let mut mem = vec![0u8; args.max_mem];
     let mut ptr = 0usize;
-    let mut output = stdout().lock();
-    let lock = stdin().lock();
-    let mut input = lock.bytes().fuse();
+    let mut counts = IoCounts::default();
+    let mut output = Output::for_args(&args)?;
+    let mut input = Input::new(args.random_input, args.hex_input);
+    let port = if args.mmio {
+        mem.len().checked_sub(1)
+    } else {
+        None
+    };
+    let mut spin = SpinGuard::default();
+    let moves = PtrMoves::new(&args);
+    let (mut steps, mut frames) = (0, 0);
+    if let Some(path) = &args.load_state {
+        let state = State::load(path)?;
+        if state.source != source_hash(&instructions) {
+            Err(eyre!("{} was saved from another program", path.display()))?;
+        }
+        if state.mem.len() != mem.len() {
+            Err(eyre!(
+                "{} was saved with --max-mem {}",
+                path.display(),
+                state.mem.len()
+            ))?;
+        }
+        // the resumed run is fed the same input, minus what the saved one read
+        for _ in 0..state.counts.inputs {
+            input.next();
+        }
+        (pc, ptr, steps, counts, mem) = (state.pc, state.ptr, state.steps, state.counts, state.mem);
+    }
+    let step_limit = args.max_steps.map(|n| steps + n);
+    let counting = args.snapshot_every.is_some() || step_limit.is_some();
+    if let Some(dir) = &args.snapshot_dir {
+        fs::create_dir_all(dir)?;
+    }
     while pc < instructions.len() {
-        match instructions[pc] {
-            b'>' => {
-                ptr += 1;
+        let c = instructions[pc];
+        if counting && b"><+-[].,".contains(&c) {
+            if Some(steps) == step_limit {
+                let Some(path) = &args.save_state else {
+                    eprintln!("stopped after {steps} steps");
+                    break;
+                };
+                let state = State {
+                    source: source_hash(&instructions),
+                    pc,
+                    ptr,
+                    steps,
+                    counts,
+                    mem: mem.clone(),
+                };
+                state.save(path)?;
+                eprintln!(
+                    "stopped after {steps} steps, state saved to {}",
+                    path.display()
+                );
+                break;
             }
-            b'<' => {
-                ptr -= 1;
+            steps += 1;
+            if let (Some(every), Some(dir)) = (args.snapshot_every, &args.snapshot_dir)
+                && steps % every == 0
+            {
+                write_frame(dir, frames, steps, &mem, ptr, args.signed_cells)?;
+                frames += 1;
             }
-            b'+' => {
-                mem[ptr] = mem[ptr].wrapping_add(1);
+        }
+        match c {
+            b'>' | b'<' => {
+                let delta = if c == b'>' { 1 } else { -1 };
+                ptr = moves.apply(ptr, delta, mem.len()).inspect_err(|_| {
+                    if args.tape_dump_on_error {
+                        let at = format!("source byte {pc}");
+                        dump_tape(&mem, ptr, Some(at), args.signed_cells);
+                    }
+                })?;
+                if Some(ptr) == port {
+                    mem[ptr] = counts.input(input.next());
+                }
             }
-            b'-' => {
-                mem[ptr] = mem[ptr].wrapping_sub(1);
+            b'+' | b'-' => {
+                mem[ptr] = if c == b'+' {
+                    mem[ptr].wrapping_add(1)
+                } else {
+                    mem[ptr].wrapping_sub(1)
+                };
+                if Some(ptr) == port {
+                    if args.limit_output == Some(counts.outputs) {
+                        eprintln!("output limit of {} bytes reached", counts.outputs);
+                        break;
+                    }
+                    output.write_all(&[mem[ptr]])?;
+                    counts.outputs += 1;
+                }
             }
             b'[' if mem[ptr] == 0 => {
                 pc += 1;
@@ -54,6 +268,11 @@ pub fn main(args: Args, mut f: File) -> Result<()> {
                 pc -= 1;
             }
             b']' => {
+                signal::check(&mut output)?;
+                if mem[ptr] != 0 && spin.spinning(pc, &mem, ptr, &counts) {
+                    eprintln!("{SPIN_WARNING}");
+                    break;
+                }
                 pc -= 1;
                 nest_level = 1;
                 while nest_level > 0 {
@@ -70,12 +289,214 @@ pub fn main(args: Args, mut f: File) -> Result<()> {
                 }
             }
             b'.' => {
+                if args.limit_output == Some(counts.outputs) {
+                    eprintln!("output limit of {} bytes reached", counts.outputs);
+                    break;
+                }
                 output.write_all(&[mem[ptr]])?;
+                counts.outputs += 1;
+            }
+            b',' => mem[ptr] = counts.input(input.next()),
+            b'$' if args.ext => eprintln!("{}", cell_num(mem[ptr], args.signed_cells)),
+            b':' if args.ext => {
+                let num = cell_num(mem[ptr], args.signed_cells).to_string();
+                let room = counts.room(args.limit_output, num.len());
+                output.write_all(&num.as_bytes()[..room])?;
+                counts.outputs += room;
+                if room < num.len() {
+                    eprintln!("output limit of {} bytes reached", counts.outputs);
+                    break;
+                }
+            }
+            b'%' if args.ext => {
+                let next = moves.apply(ptr, 1, mem.len()).inspect_err(|_| {
+                    if args.tape_dump_on_error {
+                        let at = format!("source byte {pc}");
+                        dump_tape(&mem, ptr, Some(at), args.signed_cells);
+                    }
+                })?;
+                let divisor = mem[next];
+                if divisor != 0 {
+                    mem[ptr] %= divisor;
+                }
             }
-            b',' => mem[ptr] = input.next().and_then(Result::ok).unwrap_or(0),
             _ => {}
         }
         pc += 1;
     }
+    if let (Some(every), Some(dir)) = (args.snapshot_every, &args.snapshot_dir)
+        && steps % every != 0
+    {
+        write_frame(dir, frames, steps, &mem, ptr, args.signed_cells)?;
+    }
+    output.finish()?;
+    if args.count_io {
+        eprintln!("{counts}");
+    }
+    Ok(())
+}
+
+/// What `back` needs to undo one `--debug` step: where it ran, the cell under the pointer
+/// before it, and what `,` read, if it was one
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    pc: usize,
+    ptr: usize,
+    cell: u8,
+    read: Option<Option<u8>>,
+}
+
+/// `--debug`: step through the program by commands read line by line from stdin, which
+/// `,` reads from too. `back` undoes steps from a log of the last `--history` ones, a byte
+/// `,` read is read again, but output stays written
+pub fn debug(args: Args, mut f: impl Read) -> Result<()> {
+    let mut src = Vec::new();
+    f.read_to_end(&mut src)?;
+    // reports unbalanced brackets where they are in the source
+    o1::compile(src.as_slice(), o1::Syntax::new(&args))?;
+    let prog: Vec<u8> = src
+        .into_iter()
+        .filter(|c| b"><+-[].,".contains(c))
+        .collect();
+    let mut jumps = vec![0; prog.len()];
+    let mut open = Vec::new();
+    for (pc, &c) in prog.iter().enumerate() {
+        match c {
+            b'[' => open.push(pc),
+            b']' => {
+                let start = open.pop().unwrap_or_default();
+                (jumps[start], jumps[pc]) = (pc, start);
+            }
+            _ => {}
+        }
+    }
+    let mut mem = vec![0u8; args.max_mem];
+    let (mut pc, mut ptr) = (0, 0usize);
+    let mut counts = IoCounts::default();
+    let mut output = Output::for_args(&args)?;
+    let mut input = Input::new(None, false);
+    // bytes `back` gave back to `,`, read last first
+    let mut unread = Vec::new();
+    let mut history: VecDeque<Undo> = VecDeque::with_capacity(args.history);
+    let moves = PtrMoves::new(&args);
+    let show = |pc: usize, ptr: usize, mem: &[u8]| {
+        let next = prog
+            .get(pc)
+            .map_or("end".into(), |&c| format!("'{}'", c as char));
+        let cells = tape_window(mem, ptr, args.signed_cells);
+        eprintln!("pc {pc} {next}, ptr {ptr}: {cells}");
+    };
+    loop {
+        eprint!("> ");
+        let mut line = Vec::new();
+        let eof = loop {
+            match input.next() {
+                None => break true,
+                Some(b'\n') => break false,
+                Some(c) => line.push(c),
+            }
+        };
+        if eof && line.is_empty() {
+            break;
+        }
+        let line = String::from_utf8_lossy(&line);
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap_or("step");
+        let Ok(n) = words.next().map_or(Ok(1), str::parse::<usize>) else {
+            eprintln!("expected a count after {cmd}");
+            continue;
+        };
+        let n = match cmd {
+            "s" | "step" => n,
+            "c" | "continue" => usize::MAX,
+            "b" | "back" => {
+                for _ in 0..n {
+                    let Some(undo) = history.pop_back() else {
+                        eprintln!("no more history, --history keeps {}", args.history);
+                        break;
+                    };
+                    (pc, ptr, mem[undo.ptr]) = (undo.pc, undo.ptr, undo.cell);
+                    if let Some(read) = undo.read {
+                        counts.inputs -= 1;
+                        if let Some(byte) = read {
+                            counts.input_bytes -= 1;
+                            unread.push(byte);
+                        }
+                    }
+                }
+                show(pc, ptr, &mem);
+                continue;
+            }
+            "p" | "print" => {
+                show(pc, ptr, &mem);
+                continue;
+            }
+            "q" | "quit" => break,
+            _ => {
+                eprintln!("unknown command {cmd}, expected step, back, continue, print or quit");
+                continue;
+            }
+        };
+        for _ in 0..n {
+            let Some(&c) = prog.get(pc) else {
+                eprintln!("program finished");
+                break;
+            };
+            let mut undo = Undo {
+                pc,
+                ptr,
+                cell: mem[ptr],
+                read: None,
+            };
+            match c {
+                b'>' | b'<' => {
+                    let delta = if c == b'>' { 1 } else { -1 };
+                    match moves.apply(ptr, delta, mem.len()) {
+                        Ok(to) => ptr = to,
+                        Err(e) => {
+                            eprintln!("error: {e}");
+                            break;
+                        }
+                    }
+                }
+                b'+' => mem[ptr] = mem[ptr].wrapping_add(1),
+                b'-' => mem[ptr] = mem[ptr].wrapping_sub(1),
+                b'[' if mem[ptr] == 0 => pc = jumps[pc],
+                b']' if mem[ptr] != 0 => {
+                    signal::check(&mut output)?;
+                    pc = jumps[pc];
+                }
+                b'.' => {
+                    if args.limit_output == Some(counts.outputs) {
+                        eprintln!("output limit of {} bytes reached", counts.outputs);
+                        break;
+                    }
+                    output.write_all(&[mem[ptr]])?;
+                    counts.outputs += 1;
+                }
+                b',' => {
+                    let read = unread.pop().or_else(|| input.next());
+                    mem[ptr] = counts.input(read);
+                    undo.read = Some(read);
+                }
+                _ => {}
+            }
+            pc += 1;
+            if history.len() == args.history {
+                history.pop_front();
+            }
+            history.push_back(undo);
+        }
+        output.flush()?;
+        show(pc, ptr, &mem);
+        if eof {
+            break;
+        }
+    }
+    eprintln!();
+    output.finish()?;
+    if args.count_io {
+        eprintln!("{counts}");
+    }
     Ok(())
 }
@@ -1,14 +1,22 @@
 use std::{
     fmt::{Display, Formatter},
-    fs::File,
-    io::{BufReader, Read, Write, stdin, stdout},
+    io::{Read, Write},
+    str::FromStr,
+    time::Instant,
 };
 
-use eyre::{Result, eyre};
+use eyre::Result;
 
-use crate::Args;
+use crate::{
+    Args,
+    io::{self, IoCounts},
+    signal,
+    tape::{PtrMoves, SPIN_WARNING, SpinGuard, cell_num, dump_tape},
+};
 
+/// o1 instructions, more are added as the peephole pass learns new forms
 #[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
 pub enum Inst {
     PtrInc(i32),
     ValInc(i32),
@@ -16,8 +24,22 @@ pub enum Inst {
     LoopEnd(usize),
     Output,
     Input,
+    /// `$`, cell as decimal on stderr (`--ext`)
+    DebugCell,
+    /// `:`, cell as decimal (`--ext`)
+    OutputNum,
+    /// `%`, cell modulo the cell to its right, unchanged when that is 0 (`--ext`)
+    Mod,
+    /// `[-]` or `[+]`, only produced by `peephole`
+    SetZero,
+    /// `.` on `mem[ptr + offset]`, from `>.<` and friends by `peephole`
+    OutputAt(i32),
+    /// `,` into `mem[ptr + offset]`, from `>,<` and friends by `peephole`
+    InputAt(i32),
 }
 
+/// Jump targets are line numbers in the `Prog` listing, the alternate form `{:#}` prints
+/// them in hex
 impl Display for Inst {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use Inst::*;
@@ -25,43 +47,213 @@ impl Display for Inst {
         match self {
             PtrInc(n) => write!(f, "add ptr, {n}"),
             ValInc(n) => write!(f, "add val, {n}"),
+            LoopStart(target) if f.alternate() => write!(f, "jz {target:x}"),
+            LoopEnd(target) if f.alternate() => write!(f, "jnz {target:x}"),
             LoopStart(target) => write!(f, "jz {target}"),
             LoopEnd(target) => write!(f, "jnz {target}"),
             Output => write!(f, "out"),
             Input => write!(f, "in"),
+            DebugCell => write!(f, "dbg"),
+            OutputNum => write!(f, "outnum"),
+            Mod => write!(f, "mod"),
+            SetZero => write!(f, "set 0"),
+            OutputAt(offset) => write!(f, "outat {offset}"),
+            InputAt(offset) => write!(f, "inat {offset}"),
         }
     }
 }
 
 #[derive(Debug, Clone)]
-struct Prog(Vec<Inst>);
+struct Prog {
+    prog: Vec<Inst>,
+    hex: bool,
+    /// Follow the listing with every loop as its bracket pair and depth
+    xref: bool,
+    /// Spaces per loop nesting level
+    indent: usize,
+    line_numbers: bool,
+}
 
 impl Display for Prog {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use Inst::*;
 
-        let lines = self.0.len();
-        let line_number_width = lines.to_string().len().max(2);
+        let lines = self.prog.len();
+        let line_number_width = if self.hex {
+            format!("{lines:x}").len().max(2)
+        } else {
+            lines.to_string().len().max(2)
+        };
         let mut tabs = 0;
-        for (line, inst) in self.0.iter().enumerate() {
+        for (line, inst) in self.prog.iter().enumerate() {
             if let LoopEnd(_) = inst {
                 tabs -= 1
             }
-            writeln!(
-                f,
-                "{0:>1$}  {2}{inst}",
-                line,
-                line_number_width,
-                " ".repeat(tabs * 2)
-            )?;
+            let indent = " ".repeat(tabs * self.indent);
+            if !self.line_numbers {
+                if self.hex {
+                    writeln!(f, "{indent}{inst:#}")?;
+                } else {
+                    writeln!(f, "{indent}{inst}")?;
+                }
+            } else if self.hex {
+                writeln!(f, "{line:>line_number_width$x}  {indent}{inst:#}")?;
+            } else {
+                writeln!(f, "{line:>line_number_width$}  {indent}{inst}")?;
+            }
             if let LoopStart(_) = inst {
                 tabs += 1
             }
         }
+        if self.xref {
+            self.fmt_xref(f, line_number_width)?;
+        }
+        Ok(())
+    }
+}
+
+impl Prog {
+    /// `[ @start -> ] @end` per loop in order of their `[`, depth 1 is a top-level loop.
+    /// Computed from the jump targets in one pass, so any nesting depth is fine
+    fn fmt_xref(&self, f: &mut Formatter<'_>, width: usize) -> std::fmt::Result {
+        writeln!(f, "loops:")?;
+        let mut depth = 0;
+        for (line, inst) in self.prog.iter().enumerate() {
+            match inst {
+                Inst::LoopStart(target) => {
+                    depth += 1;
+                    // the target is the instruction after the matching `jnz`
+                    let end = target - 1;
+                    if self.hex {
+                        writeln!(f, "  [ @{line:<width$x} -> ] @{end:<width$x} depth {depth}")?;
+                    } else {
+                        writeln!(f, "  [ @{line:<width$} -> ] @{end:<width$} depth {depth}")?;
+                    }
+                }
+                Inst::LoopEnd(_) => depth -= 1,
+                _ => {}
+            }
+        }
         Ok(())
     }
 }
 
+/// Compile BF source held in memory, without a file to read it from
+impl FromStr for Prog {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self {
+            prog: compile(s.as_bytes(), Syntax::default())?,
+            hex: false,
+            xref: false,
+            indent: 2,
+            line_numbers: true,
+        })
+    }
+}
+
+/// Which bytes the parser accepts
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Syntax {
+    /// Bytes other than commands and whitespace are an error instead of a comment
+    pub strict: bool,
+    /// Non-standard commands `$`, `:` and `%`
+    pub ext: bool,
+}
+
+impl Syntax {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            strict: args.strict,
+            ext: args.ext,
+        }
+    }
+}
+
+/// Why `compile` rejected a source, the root cause of its error for callers to
+/// `downcast_ref`, under the message with the position and the quoted source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// A `[` never closed by a `]`
+    UnmatchedOpen,
+    /// A `]` with no `[` open
+    UnmatchedClose,
+    /// A byte that is not a command, under `--strict`
+    Stray(u8),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnmatchedOpen => write!(f, "unmatched '['"),
+            ParseError::UnmatchedClose => write!(f, "unmatched ']'"),
+            ParseError::Stray(c) => write!(f, "stray byte {:?}", *c as char),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Bytes of context on each side of the offending byte in a diagnostic
+const SNIPPET_CONTEXT: usize = 10;
+
+/// Line and column of `offset`, both from 1
+fn position(src: &[u8], offset: usize) -> (usize, usize) {
+    let line_start = src[..offset]
+        .iter()
+        .rposition(|&c| c == b'\n')
+        .map_or(0, |i| i + 1);
+    let line = 1 + src[..line_start].iter().filter(|&&c| c == b'\n').count();
+    (line, offset - line_start + 1)
+}
+
+/// `kind` wrapped with `message`, then with the source line around `offset` and a caret
+/// under it, like rustc
+fn source_error(kind: ParseError, message: String, src: &[u8], offset: usize) -> eyre::Report {
+    let (line, col) = position(src, offset);
+    let line_start = offset + 1 - col;
+    let start = line_start.max(offset.saturating_sub(SNIPPET_CONTEXT));
+    let end = src[offset..]
+        .iter()
+        .position(|&c| c == b'\n')
+        .map_or(src.len(), |i| offset + i)
+        .min(offset + SNIPPET_CONTEXT + 1);
+    let text: String = src[start..end]
+        .iter()
+        .map(|&c| match c {
+            b' ' | b'!'..=b'~' => c as char,
+            b'\t' => ' ',
+            _ => '.',
+        })
+        .collect();
+    let width = line.to_string().len();
+    let caret = offset - start + 1;
+    eyre::Report::new(kind).wrap_err(message).wrap_err(format!(
+        "source:\n{line} | {text}\n{:width$} | {:>caret$}",
+        "", "^"
+    ))
+}
+
+/// Bytes the parse loop has to look at: the commands, and under `--strict` every byte
+/// but whitespace. Everything else is skipped by a table lookup, which matters for
+/// comment-heavy sources where most bytes are prose
+fn significant(syntax: Syntax) -> [bool; 256] {
+    let mut table = [syntax.strict; 256];
+    for c in *b" \t\r\n" {
+        table[c as usize] = false;
+    }
+    for c in *b"><+-[].," {
+        table[c as usize] = true;
+    }
+    if syntax.ext {
+        table[b'$' as usize] = true;
+        table[b':' as usize] = true;
+        table[b'%' as usize] = true;
+    }
+    table
+}
+
 #[derive(Debug)]
 enum State {
     PtrArithm(i32),
@@ -69,17 +261,55 @@ enum State {
     None,
 }
 
-pub fn compile(f: File) -> Result<Vec<Inst>> {
+/// `n + step` for a run of `>`/`<` or `+`/`-`. A run that would overflow `i32`, a few
+/// billion bytes of one command, is pushed as `inst` so far and started over
+fn extend_run(prog: &mut Vec<Inst>, n: i32, step: i32, inst: fn(i32) -> Inst) -> i32 {
+    n.checked_add(step).unwrap_or_else(|| {
+        prog.push(inst(n));
+        step
+    })
+}
+
+/// Warn about every empty loop `[]`, a no-op on zero that never exits otherwise. Called
+/// by the front end, as some backends compile one source more than once
+pub fn warn_empty_loops(syntax: Syntax, src: &[u8]) {
+    let significant = significant(syntax);
+    let mut open = None;
+    for (offset, &c) in src.iter().enumerate() {
+        if !significant[c as usize] {
+            continue;
+        }
+        if c == b']'
+            && let Some(start) = open
+        {
+            let (line, col) = position(src, start);
+            eprintln!("warning: empty loop at {line}:{col} never exits if its cell is nonzero");
+        }
+        open = (c == b'[').then_some(offset);
+    }
+}
+
+pub fn compile(mut f: impl Read, syntax: Syntax) -> Result<Vec<Inst>> {
     use Inst::*;
 
-    let bytes = BufReader::new(f).bytes().map_while(Result::ok);
+    // positions are only needed for diagnostics, so they are recovered from the offset
+    // in the whole source instead of being tracked per byte
+    let mut src = Vec::new();
+    f.read_to_end(&mut src)?;
+    let significant = significant(syntax);
     let mut prog: Vec<Inst> = Vec::new();
     let mut state = State::None;
-    let mut stack: Vec<usize> = Vec::new();
-    for c in bytes {
+    // per open loop: its instruction index and the offset of the '['
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    for (offset, &c) in src.iter().enumerate() {
+        if !significant[c as usize] {
+            continue;
+        }
         match c {
             b'>' => match state {
-                State::PtrArithm(n) => state = State::PtrArithm(n + 1),
+                State::PtrArithm(n) => {
+                    state = State::PtrArithm(extend_run(&mut prog, n, 1, PtrInc))
+                }
                 State::ValArithm(n) => {
                     prog.push(ValInc(n));
                     state = State::PtrArithm(1);
@@ -87,7 +317,9 @@ pub fn compile(f: File) -> Result<Vec<Inst>> {
                 State::None => state = State::PtrArithm(1),
             },
             b'<' => match state {
-                State::PtrArithm(n) => state = State::PtrArithm(n - 1),
+                State::PtrArithm(n) => {
+                    state = State::PtrArithm(extend_run(&mut prog, n, -1, PtrInc))
+                }
                 State::ValArithm(n) => {
                     prog.push(ValInc(n));
                     state = State::PtrArithm(-1);
@@ -95,7 +327,9 @@ pub fn compile(f: File) -> Result<Vec<Inst>> {
                 State::None => state = State::PtrArithm(-1),
             },
             b'+' => match state {
-                State::ValArithm(n) => state = State::ValArithm(n + 1),
+                State::ValArithm(n) => {
+                    state = State::ValArithm(extend_run(&mut prog, n, 1, ValInc))
+                }
                 State::PtrArithm(n) => {
                     prog.push(PtrInc(n));
                     state = State::ValArithm(1);
@@ -103,14 +337,18 @@ pub fn compile(f: File) -> Result<Vec<Inst>> {
                 State::None => state = State::ValArithm(1),
             },
             b'-' => match state {
-                State::ValArithm(n) => state = State::ValArithm(n - 1),
+                State::ValArithm(n) => {
+                    state = State::ValArithm(extend_run(&mut prog, n, -1, ValInc))
+                }
                 State::PtrArithm(n) => {
                     prog.push(PtrInc(n));
                     state = State::ValArithm(-1);
                 }
                 State::None => state = State::ValArithm(-1),
             },
-            b'[' | b']' | b'.' | b',' => {
+            b'[' | b']' | b'.' | b',' | b'$' | b':' | b'%'
+                if syntax.ext || !matches!(c, b'$' | b':' | b'%') =>
+            {
                 match state {
                     State::ValArithm(n) => {
                         prog.push(ValInc(n));
@@ -123,26 +361,44 @@ pub fn compile(f: File) -> Result<Vec<Inst>> {
                 state = State::None;
                 match c {
                     b'[' => {
-                        stack.push(prog.len());
+                        stack.push((prog.len(), offset));
                         prog.push(LoopStart(0));
                     }
                     b']' => {
-                        let start = stack
-                            .pop()
-                            .ok_or_else(|| eyre!("Orphan ']' should be matched with '['"))?;
+                        let Some((start, _)) = stack.pop() else {
+                            let (line, col) = position(&src, offset);
+                            let message =
+                                format!("Orphan ']' at {line}:{col} should be matched with '['");
+                            let kind = ParseError::UnmatchedClose;
+                            return Err(source_error(kind, message, &src, offset));
+                        };
                         prog.push(LoopEnd(start + 1));
                         prog[start] = LoopStart(prog.len());
                     }
                     b'.' => prog.push(Output),
                     b',' => prog.push(Input),
+                    b'$' => prog.push(DebugCell),
+                    b':' => prog.push(OutputNum),
+                    b'%' => prog.push(Mod),
                     _ => unreachable!(),
                 }
             }
-            _ => {}
+            _ => {
+                let (line, col) = position(&src, offset);
+                let message = format!("Stray {:?} at {line}:{col} is not a command", c as char);
+                return Err(source_error(ParseError::Stray(c), message, &src, offset));
+            }
         }
     }
-    if !stack.is_empty() {
-        Err(eyre!("Orphan '[' should be matched with ']'"))?;
+    if let Some(&(_, offset)) = stack.last() {
+        let (line, col) = position(&src, offset);
+        let message = format!("Orphan '[' at {line}:{col} should be matched with ']'");
+        return Err(source_error(
+            ParseError::UnmatchedOpen,
+            message,
+            &src,
+            offset,
+        ));
     }
     match state {
         State::ValArithm(n) => prog.push(ValInc(n)),
@@ -152,25 +408,176 @@ pub fn compile(f: File) -> Result<Vec<Inst>> {
     Ok(prog)
 }
 
-pub fn main(args: Args, f: File) -> Result<()> {
+/// Instructions a `--profile-guided` run may take before its counts are used as they are
+const PROFILE_BUDGET: u64 = 1 << 26;
+
+/// Iterations of each loop, in order of their `[`, running `prog` on `input` with output
+/// discarded. Stops early at the end of the budget or when the pointer leaves the tape,
+/// any loop that ran by then is still counted
+pub fn profile(prog: &[Inst], input: &[u8], max_mem: usize) -> Vec<u64> {
+    use Inst::*;
+
+    let mut numbers = vec![0; prog.len()];
+    let mut loops = 0;
+    for (pc, inst) in prog.iter().enumerate() {
+        if let LoopStart(_) = inst {
+            numbers[pc] = loops;
+            loops += 1;
+        }
+    }
+    let mut iterations = vec![0; loops];
+    let mut mem = vec![0u8; max_mem];
+    let (mut pc, mut ptr, mut input) = (0, 0usize, input.iter());
+    for _ in 0..PROFILE_BUDGET {
+        let Some(&inst) = prog.get(pc) else {
+            break;
+        };
+        pc += 1;
+        match inst {
+            PtrInc(n) => match ptr.checked_add_signed(n as isize) {
+                Some(moved) if moved < mem.len() => ptr = moved,
+                _ => break,
+            },
+            ValInc(n) => mem[ptr] = mem[ptr].wrapping_add_signed(n as i8),
+            LoopStart(target) if mem[ptr] == 0 => pc = target,
+            LoopStart(_) => iterations[numbers[pc - 1]] += 1,
+            // back to the instruction after the `[`
+            LoopEnd(target) if mem[ptr] != 0 => {
+                iterations[numbers[target - 1]] += 1;
+                pc = target;
+            }
+            Input => mem[ptr] = input.next().copied().unwrap_or(0),
+            SetZero => mem[ptr] = 0,
+            Mod => match mem.get(ptr + 1) {
+                Some(0) => {}
+                Some(&divisor) => mem[ptr] %= divisor,
+                None => break,
+            },
+            _ => {}
+        }
+    }
+    iterations
+}
+
+/// Replace clear loops with `SetZero` and I/O between pointer moves that cancel with
+/// `OutputAt` or `InputAt`, and recompute jump targets
+fn peephole(prog: Vec<Inst>) -> Vec<Inst> {
+    use Inst::*;
+
+    let mut result = Vec::with_capacity(prog.len());
+    let mut stack = Vec::new();
+    let mut i = 0;
+    while i < prog.len() {
+        match prog[i..] {
+            [LoopStart(_), ValInc(1 | -1), LoopEnd(_), ..] => {
+                result.push(SetZero);
+                i += 3;
+                continue;
+            }
+            [PtrInc(n), inst @ (Output | Input), PtrInc(back), ..]
+                if n.checked_add(back) == Some(0) =>
+            {
+                result.push(match inst {
+                    Output => OutputAt(n),
+                    _ => InputAt(n),
+                });
+                i += 3;
+                continue;
+            }
+            [LoopStart(_), ..] => {
+                stack.push(result.len());
+                result.push(LoopStart(0));
+            }
+            [LoopEnd(_), ..] => {
+                let start = stack.pop().unwrap();
+                result.push(LoopEnd(start + 1));
+                result[start] = LoopStart(result.len());
+            }
+            _ => result.push(prog[i]),
+        }
+        i += 1;
+    }
+    result
+}
+
+/// `--emit tokens`: `<offset> <command>` per command byte, skipping comments and
+/// whitespace, which `--strict` rejects first
+pub fn tokens(args: &Args, f: &[u8]) -> Result<()> {
+    let syntax = Syntax::new(args);
+    if syntax.strict {
+        compile(f, syntax)?;
+    }
+    let commands = significant(Syntax {
+        strict: false,
+        ..syntax
+    });
+    let mut out = std::io::stdout().lock();
+    for (offset, &c) in f.iter().enumerate() {
+        if commands[c as usize] {
+            writeln!(out, "{offset} {}", c as char)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_only(args: Args, f: &[u8]) -> Result<()> {
+    let start = Instant::now();
+    let prog = compile(f, Syntax::new(&args))?;
+    let elapsed = start.elapsed();
+    if args.deterministic {
+        eprintln!("parsed {} bytes into {} insts", f.len(), prog.len());
+        return Ok(());
+    }
+    eprintln!(
+        "parsed {} bytes into {} insts in {elapsed:?}, {:.0} bytes/s",
+        f.len(),
+        prog.len(),
+        f.len() as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
+}
+
+pub fn main(args: Args, f: impl Read) -> Result<()> {
     use Inst::*;
 
-    let prog = compile(f)?;
+    let prog = peephole(compile(f, Syntax::new(&args))?);
     if args.text {
-        print!("{}", Prog(prog.clone()));
+        let (hex, xref, indent) = (args.hex, args.xref, args.indent);
+        let line_numbers = !args.no_line_numbers;
+        print!(
+            "{}",
+            Prog {
+                prog,
+                hex,
+                xref,
+                indent,
+                line_numbers
+            }
+        );
         return Ok(());
     }
 
     let mut pc = 0;
-    let mut mem = vec![0u8; 30000];
+    let mut mem = vec![0u8; args.max_mem];
     let mut ptr = 0usize;
-    let mut output = stdout().lock();
-    let lock = stdin().lock();
-    let mut input = lock.bytes().fuse();
+    let mut counts = IoCounts::default();
+    let mut output = io::Output::for_args(&args)?;
+    let mut input = io::Input::new(args.random_input, args.hex_input);
+    let mut spin = SpinGuard::default();
+    let moves = PtrMoves::new(&args);
+    // `ptr + n` on the tape, as the instruction at `pc` moves it
+    let moved = |mem: &[u8], ptr: usize, n: i32, pc: usize| {
+        moves.apply(ptr, n as isize, mem.len()).inspect_err(|_| {
+            if args.tape_dump_on_error {
+                let at = format!("o1 line {pc}");
+                dump_tape(mem, ptr, Some(at), args.signed_cells);
+            }
+        })
+    };
     while pc < prog.len() {
         match prog[pc] {
             PtrInc(n) => {
-                ptr = ptr.wrapping_add_signed(n as isize);
+                ptr = moved(&mem, ptr, n, pc)?;
                 pc += 1;
             }
             ValInc(n) => {
@@ -178,17 +585,119 @@ pub fn main(args: Args, f: File) -> Result<()> {
                 pc += 1;
             }
             LoopStart(target) if mem[ptr] == 0 => pc = target,
-            LoopEnd(target) if mem[ptr] != 0 => pc = target,
+            LoopEnd(target) if mem[ptr] != 0 => {
+                signal::check(&mut output)?;
+                if spin.spinning(pc, &mem, ptr, &counts) {
+                    eprintln!("{SPIN_WARNING}");
+                    break;
+                }
+                pc = target;
+            }
             Output => {
+                if args.limit_output == Some(counts.outputs) {
+                    eprintln!("output limit of {} bytes reached", counts.outputs);
+                    break;
+                }
                 output.write_all(&[mem[ptr]])?;
+                counts.outputs += 1;
                 pc += 1;
             }
             Input => {
-                mem[ptr] = input.next().and_then(Result::ok).unwrap_or(0);
+                mem[ptr] = counts.input(input.next());
+                pc += 1;
+            }
+            OutputAt(offset) => {
+                let target = moved(&mem, ptr, offset, pc)?;
+                if args.limit_output == Some(counts.outputs) {
+                    eprintln!("output limit of {} bytes reached", counts.outputs);
+                    break;
+                }
+                output.write_all(&[mem[target]])?;
+                counts.outputs += 1;
+                pc += 1;
+            }
+            InputAt(offset) => {
+                let target = moved(&mem, ptr, offset, pc)?;
+                mem[target] = counts.input(input.next());
+                pc += 1;
+            }
+            DebugCell => {
+                eprintln!("{}", cell_num(mem[ptr], args.signed_cells));
+                pc += 1;
+            }
+            OutputNum => {
+                let num = cell_num(mem[ptr], args.signed_cells).to_string();
+                let room = counts.room(args.limit_output, num.len());
+                output.write_all(&num.as_bytes()[..room])?;
+                counts.outputs += room;
+                if room < num.len() {
+                    eprintln!("output limit of {} bytes reached", counts.outputs);
+                    break;
+                }
+                pc += 1;
+            }
+            Mod => {
+                let divisor = mem[moved(&mem, ptr, 1, pc)?];
+                if divisor != 0 {
+                    mem[ptr] %= divisor;
+                }
+                pc += 1;
+            }
+            SetZero => {
+                mem[ptr] = 0;
                 pc += 1;
             }
             _ => pc += 1,
         }
     }
+    output.finish()?;
+    if args.count_io {
+        eprintln!("{counts}");
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        let prog: Prog = "+++.".parse().unwrap();
+        assert!(matches!(prog.prog[..], [Inst::ValInc(3), Inst::Output]));
+        assert!("+[".parse::<Prog>().is_err());
+    }
+
+    #[test]
+    fn parse_error_kind() {
+        let kind = |src: &str, syntax| {
+            let error = compile(src.as_bytes(), syntax).unwrap_err();
+            *error.root_cause().downcast_ref::<ParseError>().unwrap()
+        };
+        let strict = Syntax {
+            strict: true,
+            ..Syntax::default()
+        };
+        assert_eq!(kind("+[", Syntax::default()), ParseError::UnmatchedOpen);
+        assert_eq!(kind("+]", Syntax::default()), ParseError::UnmatchedClose);
+        assert_eq!(kind("+x", strict), ParseError::Stray(b'x'));
+    }
+
+    #[test]
+    fn run_overflow() {
+        let mut prog = Vec::new();
+        assert_eq!(
+            extend_run(&mut prog, i32::MAX - 1, 1, Inst::ValInc),
+            i32::MAX
+        );
+        assert!(prog.is_empty());
+        // one more would overflow, so the run so far is pushed and a new one starts
+        assert_eq!(extend_run(&mut prog, i32::MAX, 1, Inst::ValInc), 1);
+        assert!(matches!(prog[..], [Inst::ValInc(i32::MAX)]));
+        assert_eq!(extend_run(&mut prog, i32::MIN, -1, Inst::PtrInc), -1);
+        assert!(matches!(
+            prog[..],
+            [Inst::ValInc(i32::MAX), Inst::PtrInc(i32::MIN)]
+        ));
+    }
+}
@@ -1,42 +1,300 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
     fmt::{Display, Formatter},
-    fs::File,
-    io::{Bytes, Read, StdinLock, StdoutLock, Write, stdin, stdout},
-    iter::Fuse,
+    fs,
+    io::{self, Read, Write},
     mem::take,
+    ops::ControlFlow,
     ops::{Add, AddAssign},
+    time::Instant,
 };
 
 use eyre::{Result, eyre};
 
-use crate::{Args, o1};
+use crate::{
+    Args,
+    io::{FlushOn, Input, IoCounts, Output, XorShift},
+    llvm::Emit,
+    o1, signal,
+    tape::{PtrMoves, SPIN_WARNING, SpinGuard, cell_num, dump_tape, tape_window},
+};
 
-#[derive(Debug, Clone)]
+/// o2 IR. Variants are added as the optimizer learns new forms, so matches outside the
+/// passes should go through [`Stmt::is_io`], [`Stmt::is_control`] and
+/// [`Stmt::as_ptr_inc`], which keep their meaning for new variants, instead of listing
+/// every variant. The `Display` format is stable for the variants it already covers
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
 pub enum Stmt {
     PtrInc(i32),
     ValInc(i32),
+    /// `mem[ptr] = n`
+    Set(i32),
+    /// `mem[ptr + offset] += mem[ptr] * factor`
+    AddMul(i32, i32),
+    /// `mem[ptr + offset] += n`
+    ValIncAt(i32, i32),
+    /// `mem[ptr + offset] = n`
+    SetAt(i32, i32),
+    /// `while mem[ptr] != 0 { ptr += n }`
+    Scan(i32),
     Loop(Vec<Stmt>),
+    /// `while mem[ptr] != 0 { body; ptr += stride }`, the body only adds constants from
+    /// `ValInc` and `ValIncAt`
+    StridedLoop {
+        stride: i32,
+        body: Vec<Stmt>,
+    },
+    /// Loop whose body always leaves a zero guard, so it runs at most once
+    If(Vec<Stmt>),
+    /// `do { body } while mem[ptr] != 0`, from the `rotate` pass for loops entered on a
+    /// guard known to be nonzero, which skips the check before the first iteration
+    DoLoop(Vec<Stmt>),
     Output,
+    /// Bytes the program is known to print, from the `output` pass
+    OutputConst(Vec<u8>),
     Input,
+    /// `$`, cell as decimal on stderr (`--ext`)
+    DebugCell,
+    /// `:`, cell as decimal (`--ext`)
+    OutputNum,
+    /// `%`, `mem[ptr] %= mem[ptr + 1]` unless that is 0 (`--ext`)
+    Mod,
+}
+
+/// Guessed trip count of a loop that can't be analyzed statically
+pub const LOOP_TRIPS: u64 = 16;
+
+pub fn cost(prog: &[Stmt]) -> u64 {
+    prog.iter().map(Stmt::cost).sum()
+}
+
+fn loop_cost(body: &[Stmt]) -> u64 {
+    // guard check on every iteration plus the final one
+    LOOP_TRIPS * (cost(body) + 1) + 1
 }
 
 impl Display for Stmt {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        todo!()
+        self.fmt_indented(f, 0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Prog(Vec<Stmt>);
+
+impl Display for Prog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for stmt in &self.0 {
+            write!(f, "{stmt}")?;
+        }
+        Ok(())
     }
 }
 
 impl Stmt {
+    /// Reads input or writes output, including the `--ext` commands
+    pub fn is_io(&self) -> bool {
+        matches!(
+            self,
+            Stmt::Output | Stmt::OutputConst(_) | Stmt::Input | Stmt::DebugCell | Stmt::OutputNum
+        )
+    }
+
+    /// Runs a data-dependent number of times: loops, scans and ifs
+    pub fn is_control(&self) -> bool {
+        matches!(
+            self,
+            Stmt::Loop(_)
+                | Stmt::StridedLoop { .. }
+                | Stmt::Scan(_)
+                | Stmt::If(_)
+                | Stmt::DoLoop(_)
+        )
+    }
+
+    /// The distance of a plain pointer move
+    pub fn as_ptr_inc(&self) -> Option<i32> {
+        match self {
+            Stmt::PtrInc(n) => Some(*n),
+            _ => None,
+        }
+    }
+
     pub fn pure(&self) -> bool {
         match self {
-            Stmt::PtrInc(_) | Stmt::ValInc(_) => true,
-            Stmt::Loop(stmts) => stmts.iter().all(Stmt::pure),
-            Stmt::Output | Stmt::Input => false,
+            Stmt::Loop(stmts)
+            | Stmt::If(stmts)
+            | Stmt::DoLoop(stmts)
+            | Stmt::StridedLoop { body: stmts, .. } => stmts.iter().all(Stmt::pure),
+            stmt => !stmt.is_io(),
+        }
+    }
+
+    /// Estimated runtime weight, loops whose trip count is unknown are assumed to run
+    /// [`LOOP_TRIPS`] times
+    pub fn cost(&self) -> u64 {
+        match self {
+            Stmt::PtrInc(_)
+            | Stmt::ValInc(_)
+            | Stmt::Set(_)
+            | Stmt::ValIncAt(..)
+            | Stmt::SetAt(..)
+            | Stmt::Output
+            | Stmt::OutputConst(_)
+            | Stmt::Input
+            | Stmt::DebugCell
+            | Stmt::OutputNum => 1,
+            // load, multiply, add
+            Stmt::AddMul(..) => 2,
+            // two loads, remainder
+            Stmt::Mod => 2,
+            Stmt::Scan(_) => LOOP_TRIPS,
+            Stmt::Loop(body) => loop_cost(body),
+            // the pointer step comes free with the guard check
+            Stmt::StridedLoop { body, .. } => loop_cost(body),
+            Stmt::If(body) => cost(body) + 1,
+            Stmt::DoLoop(body) => loop_cost(body) - 1,
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut Formatter<'_>, tabs: usize) -> std::fmt::Result {
+        use Stmt::*;
+
+        let indent = " ".repeat(tabs * 2);
+        match self {
+            PtrInc(n) => writeln!(f, "{indent}add ptr, {n}"),
+            ValInc(n) => writeln!(f, "{indent}add val, {n}"),
+            Set(n) => writeln!(f, "{indent}set val, {n}"),
+            AddMul(offset, factor) => writeln!(f, "{indent}addmul {offset}, {factor}"),
+            ValIncAt(offset, n) => writeln!(f, "{indent}addat {offset}, {n}"),
+            SetAt(offset, n) => writeln!(f, "{indent}setat {offset}, {n}"),
+            Scan(n) => writeln!(f, "{indent}scan {n}"),
+            Loop(body) => {
+                writeln!(f, "{indent}loop {{")?;
+                for stmt in body {
+                    stmt.fmt_indented(f, tabs + 1)?;
+                }
+                writeln!(f, "{indent}}}")
+            }
+            StridedLoop { stride, body } => {
+                writeln!(f, "{indent}strided {stride} {{")?;
+                for stmt in body {
+                    stmt.fmt_indented(f, tabs + 1)?;
+                }
+                writeln!(f, "{indent}}}")
+            }
+            If(body) => {
+                writeln!(f, "{indent}if {{")?;
+                for stmt in body {
+                    stmt.fmt_indented(f, tabs + 1)?;
+                }
+                writeln!(f, "{indent}}}")
+            }
+            DoLoop(body) => {
+                writeln!(f, "{indent}do {{")?;
+                for stmt in body {
+                    stmt.fmt_indented(f, tabs + 1)?;
+                }
+                writeln!(f, "{indent}}}")
+            }
+            Output => writeln!(f, "{indent}out"),
+            OutputConst(bytes) => {
+                let bytes: Vec<_> = bytes.iter().map(u8::to_string).collect();
+                writeln!(f, "{indent}outconst {}", bytes.join(", "))
+            }
+            Input => writeln!(f, "{indent}in"),
+            DebugCell => writeln!(f, "{indent}dbg"),
+            OutputNum => writeln!(f, "{indent}outnum"),
+            Mod => writeln!(f, "{indent}mod"),
         }
     }
 }
 
+/// Parse the `Display` format back, loops are delimited by `loop {`, `strided n {`,
+/// `if {` or `do {` and `}`
+pub fn parse(s: &str) -> Result<Vec<Stmt>> {
+    // each open block with the variant that wraps it when closed
+    let mut stack = vec![(Box::new(Stmt::Loop) as Box<dyn FnOnce(_) -> _>, Vec::new())];
+    for (line_number, line) in s.lines().enumerate() {
+        let invalid = || {
+            eyre!(
+                "line {}: invalid statement '{}'",
+                line_number + 1,
+                line.trim()
+            )
+        };
+        let num = |token: &str| token.parse::<i32>().map_err(|_| invalid());
+        let byte = |token: &&str| token.parse::<u8>().map_err(|_| invalid());
+        let tokens: Vec<_> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .collect();
+        let stmt = match tokens[..] {
+            [] => continue,
+            ["add", "ptr", n] => Stmt::PtrInc(num(n)?),
+            ["add", "val", n] => Stmt::ValInc(num(n)?),
+            ["set", "val", n] => Stmt::Set(num(n)?),
+            ["addmul", offset, factor] => Stmt::AddMul(num(offset)?, num(factor)?),
+            ["addat", offset, n] => Stmt::ValIncAt(num(offset)?, num(n)?),
+            ["setat", offset, n] => Stmt::SetAt(num(offset)?, num(n)?),
+            ["scan", n] => Stmt::Scan(num(n)?),
+            ["loop", "{"] => {
+                stack.push((Box::new(Stmt::Loop), Vec::new()));
+                continue;
+            }
+            ["strided", stride, "{"] => {
+                let stride = num(stride)?;
+                stack.push((
+                    Box::new(move |body| Stmt::StridedLoop { stride, body }),
+                    Vec::new(),
+                ));
+                continue;
+            }
+            ["if", "{"] => {
+                stack.push((Box::new(Stmt::If), Vec::new()));
+                continue;
+            }
+            ["do", "{"] => {
+                stack.push((Box::new(Stmt::DoLoop), Vec::new()));
+                continue;
+            }
+            ["}"] if stack.len() > 1 => {
+                let (wrap, body) = stack.pop().unwrap();
+                match wrap(body) {
+                    Stmt::StridedLoop { body, .. }
+                        if !body
+                            .iter()
+                            .all(|stmt| matches!(stmt, Stmt::ValInc(_) | Stmt::ValIncAt(..))) =>
+                    {
+                        Err(eyre!(
+                            "line {}: a strided loop may only contain add val and addat",
+                            line_number + 1
+                        ))?
+                    }
+                    stmt => stmt,
+                }
+            }
+            ["}"] => Err(eyre!("line {}: orphan '}}'", line_number + 1))?,
+            ["out"] => Stmt::Output,
+            ["outconst", ref bytes @ ..] => {
+                Stmt::OutputConst(bytes.iter().map(byte).collect::<Result<_>>()?)
+            }
+            ["in"] => Stmt::Input,
+            ["dbg"] => Stmt::DebugCell,
+            ["outnum"] => Stmt::OutputNum,
+            ["mod"] => Stmt::Mod,
+            _ => Err(invalid())?,
+        };
+        stack.last_mut().unwrap().1.push(stmt);
+    }
+    if stack.len() > 1 {
+        Err(eyre!("unclosed '{{'"))?;
+    }
+    Ok(stack.pop().unwrap().1)
+}
+
 pub fn compile(prog: Vec<o1::Inst>) -> Vec<Stmt> {
     compile_rec(&mut prog.into_iter())
 }
@@ -53,6 +311,18 @@ fn compile_rec(iter: &mut impl Iterator<Item = o1::Inst>) -> Vec<Stmt> {
             }
             o1::Inst::Output => Stmt::Output,
             o1::Inst::Input => Stmt::Input,
+            o1::Inst::DebugCell => Stmt::DebugCell,
+            o1::Inst::OutputNum => Stmt::OutputNum,
+            o1::Inst::Mod => Stmt::Mod,
+            o1::Inst::SetZero => Stmt::Set(0),
+            o1::Inst::OutputAt(offset) => {
+                prog.extend([Stmt::PtrInc(offset), Stmt::Output]);
+                Stmt::PtrInc(-offset)
+            }
+            o1::Inst::InputAt(offset) => {
+                prog.extend([Stmt::PtrInc(offset), Stmt::Input]);
+                Stmt::PtrInc(-offset)
+            }
         };
         prog.push(stmt);
     }
@@ -62,7 +332,6 @@ fn compile_rec(iter: &mut impl Iterator<Item = o1::Inst>) -> Vec<Stmt> {
 #[derive(Debug, Clone)]
 enum SymExVal {
     Const(i32),
-    Cell(i32),
     Add(Box<SymExVal>, Box<SymExVal>),
     // Mul(Box<SymExVal>, Box<SymExVal>),
 }
@@ -71,6 +340,7 @@ impl SymExVal {
     fn is_const(&self) -> bool {
         matches!(self, SymExVal::Const(_))
     }
+
     fn const_val(&self) -> Option<i32> {
         if let SymExVal::Const(v) = self {
             Some(*v)
@@ -87,12 +357,11 @@ impl SymExVal {
                 let rhs = rhs.simplify();
                 match (lhs, rhs) {
                     (Const(lv), Const(rv)) => Const(lv.wrapping_add(rv)),
-                    (Cell(n), Const(rv)) => todo!(),
                     (Add(llhs, lrhs), Const(rv)) => match *lrhs {
                         Const(lv) => Add(llhs, Box::new(Const(lv.wrapping_add(rv)))),
                         _ => Add(Box::new(Add(llhs, lrhs)), Box::new(Const(rv))),
                     },
-                    _ => todo!(),
+                    (lhs, rhs) => Add(Box::new(lhs), Box::new(rhs)),
                 }
             }
             _ => self,
@@ -142,8 +411,21 @@ fn symbolic_execution(prog: &Vec<Stmt>) -> Result<SymExInfo> {
                     mem_delta.insert(ptr_delta, Const(*n));
                 }
             },
-            Loop(_) => Err(eyre!("nested loop is not implemented"))?,
-            Output | Input => Err(eyre!("not pure, env model is not implemented"))?,
+            ValIncAt(offset, n) => match mem_delta.get_mut(&(ptr_delta + offset)) {
+                Some(delta) => *delta += Const(*n),
+                None => {
+                    mem_delta.insert(ptr_delta + offset, Const(*n));
+                }
+            },
+            Set(_) | SetAt(..) | AddMul(..) | Scan(_) | Mod => {
+                Err(eyre!("absolute store is not implemented"))?
+            }
+            Loop(_) | StridedLoop { .. } | If(_) | DoLoop(_) => {
+                Err(eyre!("nested loop is not implemented"))?
+            }
+            Output | OutputConst(_) | Input | DebugCell | OutputNum => {
+                Err(eyre!("not pure, env model is not implemented"))?
+            }
         }
     }
     Ok(SymExInfo {
@@ -152,100 +434,1958 @@ fn symbolic_execution(prog: &Vec<Stmt>) -> Result<SymExInfo> {
     })
 }
 
-fn optimize(prog: Vec<Stmt>) -> Vec<Stmt> {
+/// Cell values known at compile time, keyed by offset from the pointer at the start of
+/// the region
+#[derive(Debug, Clone)]
+struct ConstTape {
+    pos: i32,
+    cells: BTreeMap<i32, Option<u8>>,
+    /// Cells missing from `cells` are zero rather than unknown, true at program start
+    zeroed: bool,
+}
+
+impl ConstTape {
+    fn zeroed() -> Self {
+        Self {
+            pos: 0,
+            cells: BTreeMap::new(),
+            zeroed: true,
+        }
+    }
+
+    fn unknown() -> Self {
+        Self {
+            zeroed: false,
+            ..Self::zeroed()
+        }
+    }
+
+    fn get(&self, offset: i32) -> Option<u8> {
+        match self.cells.get(&(self.pos + offset)) {
+            Some(val) => *val,
+            None => self.zeroed.then_some(0),
+        }
+    }
+
+    fn set(&mut self, offset: i32, val: Option<u8>) {
+        self.cells.insert(self.pos + offset, val);
+    }
+
+    /// Forget everything but the zero guard a loop leaves behind
+    fn after_loop(&mut self) {
+        *self = Self::unknown();
+        self.set(0, Some(0));
+    }
+
+    fn step(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::PtrInc(n) => self.pos += n,
+            Stmt::ValInc(n) => self.set(0, self.get(0).map(|val| val.wrapping_add(*n as u8))),
+            Stmt::Set(n) => self.set(0, Some(*n as u8)),
+            Stmt::AddMul(offset, factor) => {
+                let val = match (self.get(0), self.get(*offset)) {
+                    (Some(0), target) => target,
+                    (Some(val), Some(target)) => {
+                        Some(target.wrapping_add(val.wrapping_mul(*factor as u8)))
+                    }
+                    _ => None,
+                };
+                self.set(*offset, val);
+            }
+            Stmt::Mod => {
+                let val = match (self.get(0), self.get(1)) {
+                    (Some(0), _) => Some(0),
+                    (Some(val), Some(0)) => Some(val),
+                    (Some(val), Some(divisor)) => Some(val % divisor),
+                    _ => None,
+                };
+                self.set(0, val);
+            }
+            Stmt::ValIncAt(offset, n) => self.set(
+                *offset,
+                self.get(*offset).map(|val| val.wrapping_add(*n as u8)),
+            ),
+            Stmt::SetAt(offset, n) => self.set(*offset, Some(*n as u8)),
+            // an if leaves the pointer in place but a zero guard either way
+            Stmt::Scan(_)
+            | Stmt::Loop(_)
+            | Stmt::StridedLoop { .. }
+            | Stmt::If(_)
+            | Stmt::DoLoop(_) => self.after_loop(),
+            Stmt::Output | Stmt::OutputConst(_) | Stmt::DebugCell | Stmt::OutputNum => {}
+            Stmt::Input => self.set(0, None),
+        }
+    }
+}
+
+pub type Pass = fn(Vec<Stmt>, &Args) -> Vec<Stmt>;
+
+/// Named o2 passes, in their default order. Every pass keeps the invariants of
+/// [`verify`], `fold` also establishes that no pointer move or add is a no-op, which the
+/// passes after it must not undo
+pub const PASSES: &[(&str, Pass)] = &[
+    ("set", set_loops),
+    ("mul", mul_loops),
+    ("scan", scan_loops),
+    ("strided", strided_loops),
+    ("dead", dead_loops),
+    ("fold", fold_segments),
+    ("offset", offset_segments),
+    ("unroll", unroll_loops),
+    ("if", if_loops),
+    ("rotate", rotate_loops),
+    ("output", output_prefix),
+];
+
+pub fn passes(names: &Option<Vec<String>>) -> Result<Vec<(&'static str, Pass)>> {
+    let Some(names) = names else {
+        return Ok(PASSES.to_vec());
+    };
+    names
+        .iter()
+        .map(|name| {
+            PASSES
+                .iter()
+                .find(|(pass_name, _)| pass_name == name)
+                .copied()
+                .ok_or_else(|| {
+                    let valid: Vec<_> = PASSES.iter().map(|(pass_name, _)| *pass_name).collect();
+                    eyre!("unknown pass '{name}', valid passes: {}", valid.join(", "))
+                })
+        })
+        .collect()
+}
+
+/// Rerun the pipeline until the program stops changing, as one pass can open up work for
+/// an earlier one, or until `--opt-iterations`. Counts the rounds each pass changed the
+/// program in `fired` if given
+pub fn optimize(
+    mut prog: Vec<Stmt>,
+    passes: &[(&'static str, Pass)],
+    args: &Args,
+    mut fired: Option<&mut BTreeMap<&'static str, u32>>,
+) -> Vec<Stmt> {
+    let mut normalized = false;
+    for _ in 0..args.opt_iterations {
+        let next = passes.iter().fold(prog.clone(), |prog, (name, pass)| {
+            let before = fired.is_some().then(|| prog.clone());
+            let after = pass(prog, args);
+            if let (Some(fired), Some(before)) = (fired.as_deref_mut(), before)
+                && after != before
+            {
+                *fired.entry(name).or_default() += 1;
+            }
+            if cfg!(debug_assertions) {
+                normalized |= *name == "fold";
+                if let Err(e) = verify(&after, normalized) {
+                    panic!("o2 pass '{name}' broke an invariant: {e}");
+                }
+            }
+            after
+        });
+        if next == prog {
+            return prog;
+        }
+        prog = next;
+    }
+    eprintln!(
+        "warning: o2 passes did not reach a fixed point within --opt-iterations {}",
+        args.opt_iterations
+    );
+    prog
+}
+
+/// Structural invariants of the o2 IR, checked after every pass in debug builds: a strided
+/// loop steps the pointer and only adds constants, a scan moves the pointer, and `Set`
+/// and `SetAt` store a cell value. With `normalized`, after `fold`, no `PtrInc(0)` or
+/// `ValInc` that wraps to no change is left
+fn verify(prog: &[Stmt], normalized: bool) -> std::result::Result<(), String> {
+    for stmt in prog {
+        match stmt {
+            Stmt::PtrInc(0) if normalized => Err("add ptr, 0 after fold")?,
+            Stmt::ValInc(n) if normalized && *n as u8 == 0 => {
+                Err(format!("add val, {n} after fold"))?
+            }
+            Stmt::Set(n) | Stmt::SetAt(_, n) if !(0..=255).contains(n) => {
+                Err(format!("store of {n}, not a cell value"))?
+            }
+            Stmt::Scan(0) => Err("scan 0 never moves")?,
+            Stmt::StridedLoop { stride: 0, .. } => Err("strided loop with stride 0")?,
+            Stmt::StridedLoop { body, .. }
+                if !body
+                    .iter()
+                    .all(|stmt| matches!(stmt, Stmt::ValInc(_) | Stmt::ValIncAt(..))) =>
+            {
+                Err("strided loop body does more than add")?
+            }
+            Stmt::Loop(body) | Stmt::If(body) | Stmt::DoLoop(body) => verify(body, normalized)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Rewrite loops bottom-up, `rewrite` returns `None` to keep the loop as is, rewrites
+/// that don't lower the estimated cost are dropped. A rotated loop is rewritten like the
+/// loop it came from, which it runs the same as
+fn rewrite_loops(prog: Vec<Stmt>, rewrite: &impl Fn(&Vec<Stmt>) -> Option<Vec<Stmt>>) -> Vec<Stmt> {
+    let rewrite_loop = |body, wrap: fn(Vec<Stmt>) -> Stmt| {
+        let body = rewrite_loops(body, rewrite);
+        match rewrite(&body) {
+            Some(stmts) if cost(&stmts) < loop_cost(&body) => stmts,
+            _ => vec![wrap(body)],
+        }
+    };
     prog.into_iter()
         .flat_map(|stmt| match stmt {
-            Stmt::Loop(stmts) => optimize_loop(stmts),
+            Stmt::Loop(body) => rewrite_loop(body, Stmt::Loop),
+            Stmt::DoLoop(body) => rewrite_loop(body, Stmt::DoLoop),
+            Stmt::If(body) => vec![Stmt::If(rewrite_loops(body, rewrite))],
             _ => vec![stmt],
         })
         .collect()
 }
 
-fn optimize_loop(body: Vec<Stmt>) -> Vec<Stmt> {
-    use SymExVal::*;
+/// Pure loop body without pointer movement, returns memory deltas and loop index step
+fn balanced_loop(body: &Vec<Stmt>) -> Option<(BTreeMap<i32, SymExVal>, i32)> {
+    if !body.iter().all(Stmt::pure) {
+        return None;
+    }
+    let SymExInfo {
+        ptr_delta,
+        mem_delta,
+    } = symbolic_execution(body).ok()?;
+    if ptr_delta != 0 {
+        return None;
+    }
+    // mem[ptr] is loop index
+    let step = mem_delta.get(&0)?.const_val()?;
+    Some((mem_delta, step))
+}
+
+/// Canonicalize maximal runs of pointer and value arithmetic, including inside loops
+/// that I/O keeps from being rewritten whole
+fn fold_segments(prog: Vec<Stmt>, _args: &Args) -> Vec<Stmt> {
+    fold_rec(prog)
+}
+
+fn fold_rec(prog: Vec<Stmt>) -> Vec<Stmt> {
+    let mut stmts = Vec::new();
+    let mut segment = Vec::new();
+    for stmt in prog {
+        match stmt {
+            Stmt::PtrInc(_) | Stmt::ValInc(_) | Stmt::Set(_) => segment.push(stmt),
+            Stmt::Loop(body) => {
+                stmts.extend(fold_segment(take(&mut segment)));
+                stmts.push(Stmt::Loop(fold_rec(body)));
+            }
+            Stmt::If(body) => {
+                stmts.extend(fold_segment(take(&mut segment)));
+                stmts.push(Stmt::If(fold_rec(body)));
+            }
+            Stmt::DoLoop(body) => {
+                stmts.extend(fold_segment(take(&mut segment)));
+                stmts.push(Stmt::DoLoop(fold_rec(body)));
+            }
+            stmt => {
+                stmts.extend(fold_segment(take(&mut segment)));
+                stmts.push(stmt);
+            }
+        }
+    }
+    stmts.extend(fold_segment(segment));
+    stmts
+}
+
+fn fold_segment(mut segment: Vec<Stmt>) -> Vec<Stmt> {
+    // kept as is when folding doesn't pay, but never with its no-ops
+    segment.retain(|stmt| match stmt {
+        Stmt::PtrInc(n) => *n != 0,
+        Stmt::ValInc(n) => *n as u8 != 0,
+        _ => true,
+    });
+    let mut ptr = 0;
+    // per offset: the last store, and the increment applied after it
+    let mut cells: BTreeMap<i32, (Option<i32>, i32)> = BTreeMap::new();
+    for stmt in &segment {
+        match stmt {
+            Stmt::PtrInc(n) => ptr += n,
+            Stmt::ValInc(n) => {
+                let delta = &mut cells.entry(ptr).or_default().1;
+                *delta = delta.wrapping_add(*n);
+            }
+            Stmt::Set(n) => {
+                cells.insert(ptr, (Some(*n), 0));
+            }
+            _ => unreachable!(),
+        }
+    }
+    let mut folded = Vec::new();
+    let mut pos = 0;
+    for (offset, (set, delta)) in cells {
+        let stmt = match set {
+            Some(n) => Stmt::Set(n.wrapping_add(delta) as u8 as i32),
+            None if delta as u8 == 0 => continue,
+            None => Stmt::ValInc(delta),
+        };
+        if offset != pos {
+            folded.push(Stmt::PtrInc(offset - pos));
+            pos = offset;
+        }
+        folded.push(stmt);
+    }
+    if ptr != pos {
+        folded.push(Stmt::PtrInc(ptr - pos));
+    }
+    if cost(&folded) < cost(&segment) {
+        folded
+    } else {
+        segment
+    }
+}
+
+/// `[-]` and friends: any odd step reaches zero
+fn set_loops(prog: Vec<Stmt>, _args: &Args) -> Vec<Stmt> {
+    rewrite_loops(prog, &|body| {
+        let (mem_delta, step) = balanced_loop(body)?;
+        (mem_delta.len() == 1 && step % 2 != 0).then(|| vec![Stmt::Set(0)])
+    })
+}
+
+/// `[->+>++<<]`: add multiples of the loop index to other cells, on either side of the
+/// guard, so `[<+>-]` moves it one cell left. The `AddMul`s come in offset order from the
+/// `BTreeMap` and are offset-addressed, so the pointer never moves and the guard is
+/// cleared last
+fn mul_loops(prog: Vec<Stmt>, _args: &Args) -> Vec<Stmt> {
+    rewrite_loops(prog, &|body| {
+        let (mem_delta, step) = balanced_loop(body)?;
+        if !mem_delta.values().all(SymExVal::is_const) {
+            return None;
+        }
+        // loop runs mem[ptr] times when counting down, -mem[ptr] times when counting up
+        let sign = match step {
+            -1 => 1,
+            1 => -1,
+            _ => return None,
+        };
+        let mut stmts: Vec<_> = mem_delta
+            .iter()
+            .filter(|(offset, _)| **offset != 0)
+            .filter_map(|(offset, delta)| {
+                let factor = delta.const_val()?.wrapping_mul(sign);
+                (factor != 0).then_some(Stmt::AddMul(*offset, factor))
+            })
+            .collect();
+        stmts.push(Stmt::Set(0));
+        Some(stmts)
+    })
+}
 
-    match symbolic_execution(&body) {
-        Ok(SymExInfo {
+/// `[>]` and friends: move until a zero cell
+fn scan_loops(prog: Vec<Stmt>, _args: &Args) -> Vec<Stmt> {
+    rewrite_loops(prog, &|body| {
+        let SymExInfo {
             ptr_delta,
             mem_delta,
-        }) => {
-            if ptr_delta == 0 {
-                // mem[ptr] is loop index
-                let step = match mem_delta.get(&0) {
-                    Some(Const(0)) => unimplemented!("diverge: dead loop"),
-                    Some(Const(v)) => *v,
-                    _ => return body,
-                };
-                if step != 1 {
-                    return body;
-                }
-                println!("step = {step}, body = {body:?}");
-                body
-            } else {
-                body
+        } = symbolic_execution(body).ok()?;
+        (ptr_delta != 0 && mem_delta.is_empty()).then(|| vec![Stmt::Scan(ptr_delta)])
+    })
+}
+
+/// `[>+>]` and friends: constant increments around a pointer that moves by a fixed stride,
+/// so an iteration is the guard check, the adds relative to it and one pointer step. Loops
+/// whose deltas are not constants stay plain loops
+fn strided_loops(prog: Vec<Stmt>, _args: &Args) -> Vec<Stmt> {
+    rewrite_loops(prog, &|body| {
+        let SymExInfo {
+            ptr_delta,
+            mem_delta,
+        } = symbolic_execution(body).ok()?;
+        if ptr_delta == 0 || mem_delta.is_empty() {
+            return None;
+        }
+        let mut stmts = Vec::new();
+        for (offset, delta) in &mem_delta {
+            let n = delta.const_val()?;
+            if n as u8 == 0 {
+                continue;
+            }
+            stmts.push(match offset {
+                0 => Stmt::ValInc(n),
+                _ => Stmt::ValIncAt(*offset, n),
+            });
+        }
+        Some(vec![Stmt::StridedLoop {
+            stride: ptr_delta,
+            body: stmts,
+        }])
+    })
+}
+
+/// Address the cells of a run of pointer and value arithmetic relative to where it
+/// starts, so the pointer moves at most once, at the end
+fn offset_segments(prog: Vec<Stmt>, _args: &Args) -> Vec<Stmt> {
+    offset_rec(prog)
+}
+
+fn offset_rec(prog: Vec<Stmt>) -> Vec<Stmt> {
+    let mut stmts = Vec::new();
+    let mut segment = Vec::new();
+    for stmt in prog {
+        match stmt {
+            Stmt::PtrInc(_)
+            | Stmt::ValInc(_)
+            | Stmt::Set(_)
+            | Stmt::ValIncAt(..)
+            | Stmt::SetAt(..) => segment.push(stmt),
+            stmt => {
+                stmts.extend(offset_segment(take(&mut segment)));
+                stmts.push(match stmt {
+                    Stmt::Loop(body) => Stmt::Loop(offset_rec(body)),
+                    Stmt::If(body) => Stmt::If(offset_rec(body)),
+                    Stmt::DoLoop(body) => Stmt::DoLoop(offset_rec(body)),
+                    stmt => stmt,
+                });
             }
         }
-        Err(_) => body,
     }
+    stmts.extend(offset_segment(segment));
+    stmts
 }
 
-#[derive(Debug)]
-struct Interpreter<'a, 'b> {
-    output: StdoutLock<'a>,
-    input: Fuse<Bytes<StdinLock<'a>>>,
-    prog: &'b Vec<Stmt>,
+fn offset_segment(segment: Vec<Stmt>) -> Vec<Stmt> {
+    let mut ptr = 0;
+    let mut offset_addressed = Vec::new();
+    for stmt in &segment {
+        let (offset, set, n) = match *stmt {
+            Stmt::PtrInc(n) => {
+                ptr += n;
+                continue;
+            }
+            Stmt::ValInc(n) => (ptr, false, n),
+            Stmt::ValIncAt(offset, n) => (ptr + offset, false, n),
+            Stmt::Set(n) => (ptr, true, n),
+            Stmt::SetAt(offset, n) => (ptr + offset, true, n),
+            _ => unreachable!(),
+        };
+        offset_addressed.push(match (offset, set) {
+            (0, false) => Stmt::ValInc(n),
+            (0, true) => Stmt::Set(n),
+            (_, false) => Stmt::ValIncAt(offset, n),
+            (_, true) => Stmt::SetAt(offset, n),
+        });
+    }
+    if ptr != 0 {
+        offset_addressed.push(Stmt::PtrInc(ptr));
+    }
+    if cost(&offset_addressed) < cost(&segment) {
+        offset_addressed
+    } else {
+        segment
+    }
+}
+
+/// Whether the program starts at cell 0 of a zeroed tape, which passes tracking the tape
+/// from the start rely on. Not so for `--lib`, `--keep-tape` or the REPL
+fn fresh_tape(args: &Args) -> bool {
+    // a circular tape puts cells left of the start at its right end, where a zeroed
+    // ConstTape does not look
+    !args.lib && !args.keep_tape && !args.wrap
+}
+
+/// Drop loops and scans entered on a cell known to be zero, such as a loop right after
+/// another
+fn dead_loops(prog: Vec<Stmt>, args: &Args) -> Vec<Stmt> {
+    let tape = if fresh_tape(args) {
+        ConstTape::zeroed()
+    } else {
+        ConstTape::unknown()
+    };
+    dead_rec(prog, tape)
+}
+
+fn dead_rec(prog: Vec<Stmt>, mut tape: ConstTape) -> Vec<Stmt> {
+    let mut stmts = Vec::new();
+    for stmt in prog {
+        // a rotated loop runs its body before the first check
+        if stmt.is_control() && !matches!(stmt, Stmt::DoLoop(_)) && tape.get(0) == Some(0) {
+            continue;
+        }
+        let stmt = match stmt {
+            Stmt::Loop(body) => Stmt::Loop(dead_rec(body, ConstTape::unknown())),
+            Stmt::If(body) => Stmt::If(dead_rec(body, ConstTape::unknown())),
+            Stmt::DoLoop(body) => Stmt::DoLoop(dead_rec(body, ConstTape::unknown())),
+            stmt => stmt,
+        };
+        tape.step(&stmt);
+        stmts.push(stmt);
+    }
+    stmts
+}
+
+/// Statements the `output` pass may run at compile time before leaving a prefix alone
+const EVAL_BUDGET: u64 = 1 << 20;
+
+/// Statements each side of `--interleave-check` may run
+const CHECK_BUDGET: u64 = 1 << 26;
+
+/// An I/O operation of a compile-time run with the byte it moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IoOp {
+    Output(u8),
+    Input(u8),
+    DebugCell(u8),
+    OutputNum(u8),
+}
+
+/// Compile-time run of statements on a concrete tape
+struct Eval {
     mem: Vec<u8>,
     ptr: usize,
+    trace: Vec<IoOp>,
+    budget: u64,
+    /// Bytes for `,`, without them only `.` runs
+    feed: Option<XorShift>,
 }
 
-impl<'a, 'b> Interpreter<'a, 'b> {
-    fn new(prog: &'b Vec<Stmt>) -> Self {
+impl Eval {
+    fn new(args: &Args, budget: u64, feed: Option<XorShift>) -> Self {
         Self {
-            output: stdout().lock(),
-            input: stdin().lock().bytes().fuse(),
-            prog,
-            mem: vec![0u8; 30000],
+            mem: vec![0; args.max_mem],
             ptr: 0,
+            trace: Vec::new(),
+            budget,
+            feed,
         }
     }
 
-    fn interpret(&mut self) -> Result<()> {
-        self.interpret_rec(self.prog)
-    }
-
-    fn interpret_rec(&mut self, prog: &Vec<Stmt>) -> Result<()> {
+    /// `None` once the budget runs out, on moving off the tape and, without a feed, on
+    /// any I/O but `.`
+    fn run(&mut self, prog: &[Stmt]) -> Option<()> {
         for stmt in prog {
+            self.tick()?;
             match stmt {
-                Stmt::PtrInc(n) => self.ptr = self.ptr.wrapping_add_signed(*n as isize),
-                Stmt::ValInc(n) => {
-                    self.mem[self.ptr] = self.mem[self.ptr].wrapping_add_signed(*n as i8)
+                Stmt::PtrInc(n) => self.ptr = self.at(*n)?,
+                Stmt::ValInc(n) => self.mem[self.ptr] = self.mem[self.ptr].wrapping_add(*n as u8),
+                Stmt::Set(n) => self.mem[self.ptr] = *n as u8,
+                Stmt::AddMul(offset, factor) => {
+                    let target = self.at(*offset)?;
+                    let val = self.mem[self.ptr].wrapping_mul(*factor as u8);
+                    self.mem[target] = self.mem[target].wrapping_add(val);
+                }
+                Stmt::ValIncAt(offset, n) => {
+                    let target = self.at(*offset)?;
+                    self.mem[target] = self.mem[target].wrapping_add(*n as u8);
+                }
+                Stmt::SetAt(offset, n) => {
+                    let target = self.at(*offset)?;
+                    self.mem[target] = *n as u8;
+                }
+                Stmt::Mod => {
+                    let divisor = self.mem[self.at(1)?];
+                    if divisor != 0 {
+                        self.mem[self.ptr] %= divisor;
+                    }
+                }
+                Stmt::Scan(n) => {
+                    while self.mem[self.ptr] != 0 {
+                        self.tick()?;
+                        self.ptr = self.at(*n)?;
+                    }
                 }
                 Stmt::Loop(body) => {
                     while self.mem[self.ptr] != 0 {
-                        self.interpret_rec(body)?
+                        self.tick()?;
+                        self.run(body)?;
                     }
                 }
-                Stmt::Output => {
-                    self.output.write_all(&[self.mem[self.ptr]])?;
+                Stmt::StridedLoop { stride, body } => {
+                    while self.mem[self.ptr] != 0 {
+                        self.run(body)?;
+                        self.ptr = self.at(*stride)?;
+                    }
+                }
+                Stmt::If(body) => {
+                    if self.mem[self.ptr] != 0 {
+                        self.run(body)?;
+                    }
+                }
+                Stmt::DoLoop(body) => loop {
+                    self.tick()?;
+                    self.run(body)?;
+                    if self.mem[self.ptr] == 0 {
+                        break;
+                    }
+                },
+                Stmt::Output => self.trace.push(IoOp::Output(self.mem[self.ptr])),
+                Stmt::OutputConst(bytes) => {
+                    self.trace
+                        .extend(bytes.iter().map(|&byte| IoOp::Output(byte)));
                 }
                 Stmt::Input => {
-                    self.mem[self.ptr] = self.input.next().and_then(Result::ok).unwrap_or(0)
+                    // EOF reads as 0 like the interpreters
+                    let val = self.feed.as_mut()?.next().unwrap_or(0);
+                    self.mem[self.ptr] = val;
+                    self.trace.push(IoOp::Input(val));
+                }
+                Stmt::DebugCell => {
+                    self.feed.as_ref()?;
+                    self.trace.push(IoOp::DebugCell(self.mem[self.ptr]));
+                }
+                Stmt::OutputNum => {
+                    self.feed.as_ref()?;
+                    self.trace.push(IoOp::OutputNum(self.mem[self.ptr]));
                 }
-                _ => unimplemented!(),
             }
         }
-        Ok(())
+        Some(())
+    }
+
+    fn tick(&mut self) -> Option<()> {
+        self.budget = self.budget.checked_sub(1)?;
+        Some(())
+    }
+
+    fn at(&self, offset: i32) -> Option<usize> {
+        self.ptr
+            .checked_add_signed(offset as isize)
+            .filter(|&ptr| ptr < self.mem.len())
     }
 }
 
-pub fn main(args: Args, f: File) -> Result<()> {
-    let prog = o1::compile(f)?;
-    let prog = compile(prog);
-    let prog = optimize(prog);
-    if args.text {
-        // print!("{}", Prog(prog.clone()));
-        // return Ok(());
-        todo!()
+/// Run the statements before the first input at compile time and replace them with one
+/// `OutputConst` of what they print, followed by the stores that rebuild their tape, so
+/// a Hello World becomes a single write. The prefix ends early at a statement that runs
+/// out of [`EVAL_BUDGET`] or moves off the tape, which is left for the real run
+fn output_prefix(prog: Vec<Stmt>, args: &Args) -> Vec<Stmt> {
+    if !fresh_tape(args) {
+        return prog;
+    }
+    let mut eval = Eval::new(args, EVAL_BUDGET, None);
+    let done = prog
+        .iter()
+        .take_while(|stmt| eval.run(std::slice::from_ref(*stmt)).is_some())
+        .count();
+    if done < prog.len() {
+        // the statement that stopped it may have been half run
+        eval = Eval::new(args, EVAL_BUDGET, None);
+        eval.run(&prog[..done]);
+    }
+    let steps = EVAL_BUDGET - eval.budget;
+    let mut stmts = Vec::new();
+    if !eval.trace.is_empty() {
+        let bytes = eval.trace.iter().map(|op| match op {
+            IoOp::Output(byte) => *byte,
+            _ => unreachable!("only `.` runs without a feed"),
+        });
+        stmts.push(Stmt::OutputConst(bytes.collect()));
+    }
+    // with nothing left to run, the final tape is never seen
+    let tape = if done < prog.len() {
+        &eval.mem[..]
+    } else {
+        &[]
+    };
+    for (cell, &val) in tape.iter().enumerate().filter(|(_, val)| **val != 0) {
+        stmts.push(match cell {
+            0 => Stmt::Set(val as i32),
+            _ => Stmt::SetAt(cell as i32, val as i32),
+        });
+    }
+    if eval.ptr != 0 && done < prog.len() {
+        stmts.push(Stmt::PtrInc(eval.ptr as i32));
+    }
+    // also keeps a prefix that is already in this form, so the pipeline settles
+    if stmts.len() as u64 >= steps {
+        return prog;
+    }
+    stmts.extend(prog.into_iter().skip(done));
+    stmts
+}
+
+/// `--interleave-check`: run the program before and after optimizing on the same random
+/// input and fail if the I/O operations differ, say a prompt moved past the read it
+/// belongs to. A run cut short by [`CHECK_BUDGET`] or by leaving the tape only has to
+/// agree with the other one as far as it got. `change` names what came between them
+fn interleave_check(args: &Args, before: &[Stmt], after: &[Stmt], change: &str) -> Result<()> {
+    let trace = |prog| {
+        let feed = XorShift::new(args.random_input.unwrap_or(0));
+        let mut eval = Eval::new(args, CHECK_BUDGET, Some(feed));
+        let finished = eval.run(prog).is_some();
+        (eval.trace, finished)
+    };
+    let (before, before_finished) = trace(before);
+    let (after, after_finished) = trace(after);
+    if let Some((i, (old, new))) = before
+        .iter()
+        .zip(&after)
+        .enumerate()
+        .find(|(_, (old, new))| old != new)
+    {
+        Err(eyre!(
+            "{change} changed I/O operation {i}: {old:?} became {new:?}"
+        ))?;
+    }
+    if (before_finished && after.len() > before.len())
+        || (after_finished && before.len() > after.len())
+    {
+        Err(eyre!(
+            "{change} changed the number of I/O operations from {} to {}",
+            before.len(),
+            after.len()
+        ))?;
+    }
+    Ok(())
+}
+
+/// Loops that leave the pointer where it started and its cell zero after one iteration
+fn if_loops(prog: Vec<Stmt>, _args: &Args) -> Vec<Stmt> {
+    rewrite_loops(prog, &|body| {
+        let mut tape = ConstTape::unknown();
+        // the guard cell in tape coordinates, lost after an inner loop that moves the pointer
+        let mut guard = Some(0);
+        for stmt in body {
+            match stmt {
+                Stmt::Loop(inner) | Stmt::DoLoop(inner) if ptr_delta(inner) == Some(0) => {
+                    // after_loop makes the current cell the new origin
+                    guard = guard.map(|guard| guard - tape.pos);
+                }
+                Stmt::Loop(_) | Stmt::DoLoop(_) | Stmt::Scan(_) | Stmt::StridedLoop { .. } => {
+                    guard = None
+                }
+                _ => {}
+            }
+            tape.step(stmt);
+        }
+        (guard == Some(tape.pos) && tape.get(0) == Some(0)).then(|| vec![Stmt::If(body.clone())])
+    })
+}
+
+/// Rotate loops entered on a guard known to be nonzero into `DoLoop`s, which check it
+/// after each iteration only
+fn rotate_loops(prog: Vec<Stmt>, args: &Args) -> Vec<Stmt> {
+    let tape = if fresh_tape(args) {
+        ConstTape::zeroed()
+    } else {
+        ConstTape::unknown()
+    };
+    rotate_rec(prog, tape)
+}
+
+fn rotate_rec(prog: Vec<Stmt>, mut tape: ConstTape) -> Vec<Stmt> {
+    let mut stmts = Vec::new();
+    for stmt in prog {
+        let stmt = match stmt {
+            Stmt::Loop(body) => {
+                let body = rotate_rec(body, ConstTape::unknown());
+                match tape.get(0) {
+                    Some(val) if val != 0 => Stmt::DoLoop(body),
+                    _ => Stmt::Loop(body),
+                }
+            }
+            Stmt::If(body) => Stmt::If(rotate_rec(body, ConstTape::unknown())),
+            Stmt::DoLoop(body) => Stmt::DoLoop(rotate_rec(body, ConstTape::unknown())),
+            stmt => stmt,
+        };
+        tape.step(&stmt);
+        stmts.push(stmt);
+    }
+    stmts
+}
+
+/// Net pointer movement of `prog`, `None` if it depends on the tape
+fn ptr_delta(prog: &[Stmt]) -> Option<i32> {
+    let mut delta = 0;
+    for stmt in prog {
+        match stmt {
+            Stmt::Loop(body) | Stmt::If(body) | Stmt::DoLoop(body)
+                if ptr_delta(body) == Some(0) => {}
+            stmt if stmt.is_control() => return None,
+            stmt => delta += stmt.as_ptr_inc().unwrap_or(0),
+        }
+    }
+    Some(delta)
+}
+
+/// Upper bound on statements produced by unrolling one loop
+const UNROLL_BUDGET: usize = 1024;
+
+/// Unroll loops whose guard is known on entry and runs at most `--unroll` times, into
+/// copies of the body followed by `Set(0)`. `--profile-guided` unrolls before the pipeline
+/// instead
+fn unroll_loops(prog: Vec<Stmt>, args: &Args) -> Vec<Stmt> {
+    if args.unroll == 0 || args.profile_guided || !fresh_tape(args) {
+        return prog;
+    }
+    unroll_rec(prog, ConstTape::zeroed(), args.unroll, &mut || true)
+}
+
+/// A loop is hot if it ran at least this fraction of all profiled loop iterations
+const HOT_SHARE: u64 = 10;
+
+/// `--profile-guided`: unroll only the loops that ran hot under [`o1::profile`], on the
+/// program straight from [`compile`], whose loops are still those of the o1 program in
+/// order
+fn unroll_hot(prog: Vec<Stmt>, iterations: &[u64], args: &Args) -> Vec<Stmt> {
+    if args.unroll == 0 || !fresh_tape(args) {
+        return prog;
+    }
+    let total: u64 = iterations.iter().sum();
+    let mut hot = iterations.iter().map(|&n| n > 0 && n * HOT_SHARE >= total);
+    unroll_rec(prog, ConstTape::zeroed(), args.unroll, &mut || {
+        hot.next().unwrap_or(false)
+    })
+}
+
+/// `hot` is asked about every loop in preorder, those it rejects are kept
+fn unroll_rec(
+    prog: Vec<Stmt>,
+    mut tape: ConstTape,
+    limit: u32,
+    hot: &mut impl FnMut() -> bool,
+) -> Vec<Stmt> {
+    let mut stmts = Vec::new();
+    for stmt in prog {
+        let (body, wrap): (_, fn(_) -> _) = match stmt {
+            Stmt::Loop(body) => (body, Stmt::Loop),
+            Stmt::DoLoop(body) => (body, Stmt::DoLoop),
+            stmt => {
+                tape.step(&stmt);
+                stmts.push(stmt);
+                continue;
+            }
+        };
+        let is_hot = hot();
+        let body = unroll_rec(body, ConstTape::unknown(), limit, hot);
+        let trips = tape
+            .get(0)
+            .filter(|_| is_hot)
+            .and_then(|guard| trip_count(&body, guard, limit))
+            .filter(|trips| trips * body.len() <= UNROLL_BUDGET);
+        match trips {
+            Some(trips) => {
+                for _ in 0..trips {
+                    for stmt in &body {
+                        tape.step(stmt);
+                        stmts.push(stmt.clone());
+                    }
+                }
+                tape.step(&Stmt::Set(0));
+                stmts.push(Stmt::Set(0));
+            }
+            None => {
+                tape.after_loop();
+                stmts.push(wrap(body));
+            }
+        }
+    }
+    stmts
+}
+
+/// Iterations until the guard hits zero, if the body changes it by a constant
+fn trip_count(body: &[Stmt], guard: u8, limit: u32) -> Option<usize> {
+    let mut offset = 0;
+    let mut step = 0i32;
+    for stmt in body {
+        match stmt {
+            Stmt::PtrInc(n) => offset += n,
+            Stmt::ValInc(n) if offset == 0 => step = step.wrapping_add(*n),
+            Stmt::ValInc(_)
+            | Stmt::Output
+            | Stmt::OutputConst(_)
+            | Stmt::DebugCell
+            | Stmt::OutputNum => {}
+            Stmt::AddMul(target, _) if offset + target != 0 => {}
+            Stmt::ValIncAt(target, n) if offset + target == 0 => step = step.wrapping_add(*n),
+            Stmt::ValIncAt(..) => {}
+            Stmt::SetAt(target, _) if offset + target != 0 => {}
+            Stmt::Input if offset != 0 => {}
+            Stmt::Set(_) | Stmt::Mod if offset != 0 => {}
+            _ => return None,
+        }
+    }
+    if offset != 0 {
+        return None;
+    }
+    let mut guard = guard;
+    let mut trips = 0;
+    while guard != 0 {
+        if trips == limit {
+            return None;
+        }
+        guard = guard.wrapping_add(step as u8);
+        trips += 1;
+    }
+    Some(trips as usize)
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+struct IrStats {
+    stmts: usize,
+    loops: usize,
+    max_depth: usize,
+    cost: u64,
+}
+
+impl IrStats {
+    fn new(prog: &[Stmt]) -> Self {
+        let mut stats = IrStats {
+            cost: cost(prog),
+            ..Default::default()
+        };
+        stats.count(prog, 0);
+        stats
+    }
+
+    fn count(&mut self, prog: &[Stmt], depth: usize) {
+        self.max_depth = self.max_depth.max(depth);
+        for stmt in prog {
+            self.stmts += 1;
+            match stmt {
+                Stmt::Loop(body) | Stmt::StridedLoop { body, .. } | Stmt::DoLoop(body) => {
+                    self.loops += 1;
+                    self.count(body, depth + 1);
+                }
+                Stmt::If(body) => self.count(body, depth + 1),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Display for IrStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "stmts: {}, loops: {}, max depth: {}, estimated cost: {}",
+            self.stmts, self.loops, self.max_depth, self.cost
+        )
+    }
+}
+
+/// `--json`: the stats as one object with a field each
+#[cfg(feature = "serde")]
+fn stats_json(stats: &IrStats) -> String {
+    serde_json::to_string(stats).expect("stats serialize to JSON")
+}
+
+#[cfg(not(feature = "serde"))]
+fn stats_json(_: &IrStats) -> String {
+    unreachable!("main rejects --json for stats without the serde feature")
+}
+
+/// `--opt-report`: [`IrStats`] of the unoptimized statements, as o1 compiled them, next to
+/// those of the optimized ones, and how many rounds each pass changed the program in
+#[derive(Debug)]
+struct OptReport {
+    before: IrStats,
+    fired: BTreeMap<&'static str, u32>,
+}
+
+impl OptReport {
+    fn new(prog: &[Stmt]) -> Self {
+        Self {
+            before: IrStats::new(prog),
+            fired: BTreeMap::new(),
+        }
+    }
+
+    /// A table on stderr, or one JSON object on stdout with `--json`
+    fn print(&self, args: &Args, prog: &[Stmt]) {
+        let after = IrStats::new(prog);
+        let rows = [
+            ("stmts", self.before.stmts as u64, after.stmts as u64),
+            ("loops", self.before.loops as u64, after.loops as u64),
+            (
+                "max depth",
+                self.before.max_depth as u64,
+                after.max_depth as u64,
+            ),
+            ("cost", self.before.cost, after.cost),
+        ];
+        // in pipeline order rather than by name
+        let fired: Vec<_> = PASSES
+            .iter()
+            .filter_map(|(name, _)| Some((*name, *self.fired.get(name)?)))
+            .collect();
+        let reduction = |before: u64, after: u64| {
+            (before != 0).then(|| (1.0 - after as f64 / before as f64) * 100.0)
+        };
+        if args.json {
+            let reductions: Vec<_> = rows
+                .iter()
+                .map(|(name, before, after)| {
+                    let val =
+                        reduction(*before, *after).map_or("null".to_owned(), |r| format!("{r:.1}"));
+                    format!(r#""{}":{val}"#, name.replace(' ', "_"))
+                })
+                .collect();
+            let fired: Vec<_> = fired
+                .iter()
+                .map(|(name, rounds)| format!(r#""{name}":{rounds}"#))
+                .collect();
+            println!(
+                r#"{{"before":{},"after":{},"reduction":{{{}}},"fired":{{{}}}}}"#,
+                stats_json(&self.before),
+                stats_json(&after),
+                reductions.join(","),
+                fired.join(",")
+            );
+            return;
+        }
+        eprintln!("{:<10}{:>10}{:>10}{:>10}", "", "o1", "o2", "reduced");
+        for (name, before, after) in rows {
+            let reduction = reduction(before, after).map_or("-".to_owned(), |r| format!("{r:.1}%"));
+            eprintln!("{name:<10}{before:>10}{after:>10}{reduction:>10}");
+        }
+        let fired: Vec<_> = fired
+            .iter()
+            .map(|(name, rounds)| format!("{name} x{rounds}"))
+            .collect();
+        let fired = if fired.is_empty() {
+            "none".to_owned()
+        } else {
+            fired.join(", ")
+        };
+        eprintln!("passes fired: {fired}");
+    }
+}
+
+/// Tag of the `--emit json-ast` object, bumped when the layout changes
+#[cfg(feature = "serde")]
+const JSON_AST_SCHEMA: &str = "sbfnj-o2-ast/1";
+
+/// The `--emit json-ast` object, the statements under a schema tag
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct JsonAst<'a> {
+    schema: &'static str,
+    stmts: &'a [Stmt],
+}
+
+/// `--emit json-ast`: the statements in serde's default enum layout, a unit variant as its
+/// name and any other as an object from its name to its fields, loop bodies nesting as
+/// arrays
+#[cfg(feature = "serde")]
+pub fn json_ast(prog: &[Stmt]) -> String {
+    let ast = JsonAst {
+        schema: JSON_AST_SCHEMA,
+        stmts: prog,
+    };
+    serde_json::to_string(&ast).expect("statements serialize to JSON")
+}
+
+#[cfg(not(feature = "serde"))]
+pub fn json_ast(_prog: &[Stmt]) -> String {
+    unreachable!("main rejects --emit json-ast without the serde feature")
+}
+
+/// Range of pointer positions and offset-addressed cells relative to the start, `None`
+/// once a scan or a loop that moves the pointer makes it unbounded
+fn footprint(prog: &[Stmt]) -> Option<(i64, i64)> {
+    let mut range = (0, 0);
+    footprint_rec(prog, 0, &mut range)?;
+    Some(range)
+}
+
+/// Position after `prog`, which must be known for loops and ifs to be bounded
+fn footprint_rec(prog: &[Stmt], mut pos: i64, range: &mut (i64, i64)) -> Option<i64> {
+    fn reach(range: &mut (i64, i64), cell: i64) {
+        *range = (range.0.min(cell), range.1.max(cell));
+    }
+    for stmt in prog {
+        match stmt {
+            Stmt::PtrInc(n) => {
+                pos += *n as i64;
+                reach(range, pos);
+            }
+            Stmt::AddMul(offset, _) | Stmt::ValIncAt(offset, _) | Stmt::SetAt(offset, _) => {
+                reach(range, pos + *offset as i64)
+            }
+            Stmt::Mod => reach(range, pos + 1),
+            Stmt::Loop(body) | Stmt::If(body) | Stmt::DoLoop(body) => {
+                if footprint_rec(body, pos, range)? != pos {
+                    return None;
+                }
+            }
+            Stmt::Scan(_) | Stmt::StridedLoop { .. } => return None,
+            Stmt::ValInc(_)
+            | Stmt::Set(_)
+            | Stmt::Output
+            | Stmt::OutputConst(_)
+            | Stmt::Input
+            | Stmt::DebugCell
+            | Stmt::OutputNum => {}
+        }
+    }
+    Some(pos)
+}
+
+/// Report `--count-cells-used`, and return the tape size to run with, the bound under
+/// `--auto-mem` or `--max-mem`
+pub fn cells_used(args: &Args, prog: &[Stmt]) -> usize {
+    let footprint = footprint(prog);
+    if args.count_cells_used {
+        match footprint {
+            Some((min, max)) if min < 0 => {
+                eprintln!("cells used: {min}..={max}, the pointer may move left of the tape start")
+            }
+            Some((_, max)) => eprintln!("cells used: 0..={max}, {} cells", max + 1),
+            None => eprintln!("cells used: unbounded, a loop moves the pointer"),
+        }
+    }
+    match footprint {
+        Some((_, max)) if args.auto_mem => max as usize + 1,
+        _ => args.max_mem,
+    }
+}
+
+/// Largest run of output bytes held back by the interpreter
+const OUTPUT_BATCH: usize = 4096;
+
+/// Widest tape window a memoized loop may read and write
+const MEMO_WINDOW: i64 = 16;
+
+/// Results kept per memoized loop, past which new entries just run
+const MEMO_ENTRIES: usize = 1024;
+
+/// A pure loop that ends where it started and only touches the cells `lo..=hi` around it,
+/// so the window it is entered with decides the window it leaves. The loops the optimizer
+/// could not flatten, those nesting control flow, are the ones worth remembering
+#[derive(Debug)]
+struct LoopMemo {
+    lo: i64,
+    hi: i64,
+    results: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl LoopMemo {
+    fn new(body: &[Stmt]) -> Option<Self> {
+        if !body.iter().all(Stmt::pure)
+            || !body.iter().any(Stmt::is_control)
+            || ptr_delta(body) != Some(0)
+        {
+            return None;
+        }
+        let (lo, hi) = footprint(body)?;
+        (hi - lo < MEMO_WINDOW).then(|| Self {
+            lo,
+            hi,
+            results: HashMap::new(),
+        })
+    }
+}
+
+/// Whether a loop entry could reuse a [`LoopMemo`] result
+#[derive(Debug)]
+enum Recall {
+    /// The tape already holds what the loop would leave
+    Hit,
+    /// Run the loop and remember the window from this start, as it was on entry
+    Miss(usize, Vec<u8>),
+    /// Not memoized, or the window is off the tape
+    Skip,
+}
+
+/// Tape, pointer and I/O counts persist across `interpret` calls until `reset`, so a
+/// sequence of programs can run as one session on shared memory. `,` reads from `R` and
+/// `.` writes to `W`, [`Interpreter::stdio`] wires them to stdin and stdout
+#[derive(Debug)]
+struct Interpreter<R, W> {
+    output: W,
+    input: R,
+    mem: Vec<u8>,
+    ptr: usize,
+    limit_output: Option<usize>,
+    signed_cells: bool,
+    moves: PtrMoves,
+    /// `--tape-dump-on-error`
+    dump_on_error: bool,
+    counts: IoCounts,
+    /// Output not yet written, so runs of `.` cost one `write_all`
+    pending: Vec<u8>,
+    /// Writes `pending` as soon as it would flush, instead of flushing before each read
+    flush_on: Option<FlushOn>,
+    spin: SpinGuard,
+    /// By loop body, `None` for loops that cannot be memoized or with `--no-loop-memo`
+    memos: Option<HashMap<usize, Option<LoopMemo>>>,
+}
+
+impl Interpreter<Input, Output> {
+    /// `--random-input` or stdin, as hex with `--hex-input`, and stdout, as hex with
+    /// `--hex-output`
+    fn stdio(args: &Args) -> Result<Self> {
+        Ok(Self::new(
+            Input::new(args.random_input, args.hex_input),
+            Output::for_args(args)?,
+            args,
+        ))
+    }
+
+    /// Restart a random input stream for another run if `random_seed` is given, stdin
+    /// stays locked and carries on where the last run stopped
+    fn reseed(&mut self, random_seed: Option<u64>) {
+        if random_seed.is_some() {
+            self.input = Input::new(random_seed, false);
+        }
+    }
+}
+
+impl<R: Read, W: Write> Interpreter<R, W> {
+    fn new(input: R, output: W, args: &Args) -> Self {
+        Self {
+            output,
+            input,
+            mem: vec![0u8; args.max_mem],
+            ptr: 0,
+            limit_output: args.limit_output,
+            signed_cells: args.signed_cells,
+            moves: PtrMoves::new(args),
+            dump_on_error: args.tape_dump_on_error,
+            counts: IoCounts::default(),
+            pending: Vec::with_capacity(OUTPUT_BATCH),
+            flush_on: args.flush_on,
+            spin: SpinGuard::default(),
+            memos: (!args.no_loop_memo).then(HashMap::new),
+        }
+    }
+
+    /// Look the loop of `body` up by the cells around the pointer it is entered with,
+    /// copying in what it left if it started from them before
+    fn recall(&mut self, body: &[Stmt]) -> Recall {
+        let Some(memos) = &mut self.memos else {
+            return Recall::Skip;
+        };
+        let memo = memos
+            .entry(body.as_ptr() as usize)
+            .or_insert_with(|| LoopMemo::new(body));
+        let Some(memo) = memo else {
+            return Recall::Skip;
+        };
+        let (lo, hi) = (self.ptr as i64 + memo.lo, self.ptr as i64 + memo.hi);
+        if lo < 0 || hi >= self.mem.len() as i64 {
+            return Recall::Skip;
+        }
+        let window = lo as usize..=hi as usize;
+        match memo.results.get(&self.mem[window.clone()]) {
+            Some(after) => {
+                self.mem[window].copy_from_slice(after);
+                Recall::Hit
+            }
+            None if memo.results.len() < MEMO_ENTRIES => {
+                Recall::Miss(lo as usize, self.mem[window].to_vec())
+            }
+            None => Recall::Skip,
+        }
+    }
+
+    /// Remember what the loop of `body` left after a [`Recall::Miss`]
+    fn remember(&mut self, body: &[Stmt], start: usize, before: Vec<u8>) {
+        let after = self.mem[start..start + before.len()].to_vec();
+        if let Some(memos) = &mut self.memos
+            && let Some(Some(memo)) = memos.get_mut(&(body.as_ptr() as usize))
+        {
+            memo.results.insert(before, after);
+        }
+    }
+
+    /// `ptr + delta` on the tape
+    fn moved(&self, delta: i32) -> Result<usize> {
+        self.moves
+            .apply(self.ptr, delta as isize, self.mem.len())
+            .inspect_err(|_| {
+                if self.dump_on_error {
+                    dump_tape(&self.mem, self.ptr, None, self.signed_cells);
+                }
+            })
+    }
+
+    /// Next byte for `,`, `None` at EOF
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        let mut byte = [0];
+        match self.input.read_exact(&mut byte) {
+            Ok(()) => Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e)?,
+        }
+    }
+
+    fn flushes(&self, bytes: &[u8]) -> bool {
+        self.flush_on
+            .is_some_and(|flush_on| flush_on.flushes(bytes))
+    }
+
+    fn flush_output(&mut self) -> Result<()> {
+        self.output.write_all(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Whether to stop the loop of `body` before another pass because it spins after EOF,
+    /// warning if so
+    fn spinning(&mut self, body: &[Stmt]) -> Result<bool> {
+        // a body is identified by where it lives, which it does not leave
+        if !self
+            .spin
+            .spinning(body.as_ptr() as usize, &self.mem, self.ptr, &self.counts)
+        {
+            return Ok(false);
+        }
+        self.flush_output()?;
+        eprintln!("{SPIN_WARNING}");
+        Ok(true)
+    }
+
+    /// Rewind for another run on the same allocation, zeroing the whole tape unless
+    /// `keep_tape`. Input carries on where the last run stopped
+    fn reset(&mut self, keep_tape: bool) {
+        if !keep_tape {
+            self.mem.fill(0);
+            self.ptr = 0;
+        }
+        self.counts = IoCounts::default();
+        self.spin = SpinGuard::default();
+    }
+
+    /// Run `prog` from the current pointer, all output is written when it returns
+    fn interpret(&mut self, prog: &Vec<Stmt>) -> Result<()> {
+        // memos are keyed by where a body lives, which another program may reuse
+        if let Some(memos) = &mut self.memos {
+            memos.clear();
+        }
+        // flush even on error so output before a pointer overrun is not lost
+        let result = self.interpret_rec(prog);
+        self.flush_output()?;
+        result.map(|_| ())
+    }
+
+    fn interpret_rec(&mut self, prog: &Vec<Stmt>) -> Result<ControlFlow<()>> {
+        // the guard as read by the run of `AddMul`s before the current statement
+        let mut guard = None;
+        for stmt in prog {
+            if !matches!(stmt, Stmt::AddMul(..)) {
+                guard = None;
+            }
+            match stmt {
+                Stmt::PtrInc(n) => self.ptr = self.moved(*n)?,
+                Stmt::ValInc(n) => {
+                    self.mem[self.ptr] = self.mem[self.ptr].wrapping_add_signed(*n as i8)
+                }
+                Stmt::Set(n) => self.mem[self.ptr] = *n as u8,
+                Stmt::AddMul(offset, factor) => {
+                    let val = guard
+                        .get_or_insert(self.mem[self.ptr])
+                        .wrapping_mul(*factor as u8);
+                    let target = self.moved(*offset)?;
+                    self.mem[target] = self.mem[target].wrapping_add(val);
+                    if target == self.ptr {
+                        guard = None;
+                    }
+                }
+                Stmt::Mod => {
+                    let divisor = self.mem[self.moved(1)?];
+                    if divisor != 0 {
+                        self.mem[self.ptr] %= divisor;
+                    }
+                }
+                Stmt::ValIncAt(offset, n) => {
+                    let target = self.moved(*offset)?;
+                    self.mem[target] = self.mem[target].wrapping_add_signed(*n as i8);
+                }
+                Stmt::SetAt(offset, n) => {
+                    let target = self.moved(*offset)?;
+                    self.mem[target] = *n as u8;
+                }
+                Stmt::Scan(n) => {
+                    while self.mem[self.ptr] != 0 {
+                        self.ptr = self.moved(*n)?;
+                    }
+                }
+                Stmt::StridedLoop { stride, body } => {
+                    while self.mem[self.ptr] != 0 {
+                        if self.interpret_rec(body)?.is_break() {
+                            return Ok(ControlFlow::Break(()));
+                        }
+                        self.ptr = self.moved(*stride)?;
+                    }
+                }
+                Stmt::Loop(body) => {
+                    let recall = match self.mem[self.ptr] {
+                        0 => Recall::Skip,
+                        _ => self.recall(body),
+                    };
+                    if let Recall::Hit = recall {
+                        continue;
+                    }
+                    let mut first = true;
+                    while self.mem[self.ptr] != 0 {
+                        if signal::interrupted() {
+                            self.flush_output()?;
+                        }
+                        signal::check(&mut self.output)?;
+                        if !first && self.spinning(body)? {
+                            return Ok(ControlFlow::Break(()));
+                        }
+                        first = false;
+                        if self.interpret_rec(body)?.is_break() {
+                            return Ok(ControlFlow::Break(()));
+                        }
+                    }
+                    if let Recall::Miss(start, before) = recall {
+                        self.remember(body, start, before);
+                    }
+                    self.flush_output()?;
+                }
+                // the same checks as a loop's, after the guard test that moved to the bottom
+                Stmt::DoLoop(body) => {
+                    let recall = self.recall(body);
+                    if let Recall::Hit = recall {
+                        continue;
+                    }
+                    loop {
+                        if self.interpret_rec(body)?.is_break() {
+                            return Ok(ControlFlow::Break(()));
+                        }
+                        if self.mem[self.ptr] == 0 {
+                            break;
+                        }
+                        if signal::interrupted() {
+                            self.flush_output()?;
+                        }
+                        signal::check(&mut self.output)?;
+                        if self.spinning(body)? {
+                            return Ok(ControlFlow::Break(()));
+                        }
+                    }
+                    if let Recall::Miss(start, before) = recall {
+                        self.remember(body, start, before);
+                    }
+                    self.flush_output()?;
+                }
+                Stmt::If(body) => {
+                    if self.mem[self.ptr] != 0 && self.interpret_rec(body)?.is_break() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+                }
+                Stmt::Output => {
+                    if self.limit_output == Some(self.counts.outputs) {
+                        eprintln!("output limit of {} bytes reached", self.counts.outputs);
+                        return Ok(ControlFlow::Break(()));
+                    }
+                    self.pending.push(self.mem[self.ptr]);
+                    self.counts.outputs += 1;
+                    if self.pending.len() >= OUTPUT_BATCH || self.flushes(&[self.mem[self.ptr]]) {
+                        self.flush_output()?;
+                    }
+                }
+                Stmt::OutputConst(bytes) => {
+                    if self.output_bytes(bytes)?.is_break() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+                }
+                Stmt::Input => {
+                    // a prompt must be visible before blocking on the read
+                    self.flush_output()?;
+                    if self.flush_on.is_none() {
+                        self.output.flush()?;
+                    }
+                    let byte = self.read_byte()?;
+                    self.mem[self.ptr] = self.counts.input(byte);
+                }
+                Stmt::DebugCell => {
+                    self.flush_output()?;
+                    eprintln!("{}", cell_num(self.mem[self.ptr], self.signed_cells));
+                }
+                Stmt::OutputNum => {
+                    let num = cell_num(self.mem[self.ptr], self.signed_cells).to_string();
+                    if self.output_bytes(num.as_bytes())?.is_break() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Queue several bytes for output at once, as many as `--limit-output` leaves room for
+    fn output_bytes(&mut self, bytes: &[u8]) -> Result<ControlFlow<()>> {
+        let room = self.counts.room(self.limit_output, bytes.len());
+        self.pending.extend_from_slice(&bytes[..room]);
+        self.counts.outputs += room;
+        if room < bytes.len() {
+            eprintln!("output limit of {} bytes reached", self.counts.outputs);
+            return Ok(ControlFlow::Break(()));
+        }
+        if self.pending.len() >= OUTPUT_BATCH || self.flushes(bytes) {
+            self.flush_output()?;
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Pure loops that move the pointer without being scans, as (preorder index, ptr delta)
+fn unbalanced_loops(prog: &[Stmt], index: &mut usize, found: &mut Vec<(usize, i32)>) {
+    for stmt in prog {
+        if let Stmt::Loop(body) = stmt {
+            if let Ok(info) = symbolic_execution(body)
+                && info.ptr_delta != 0
+                && !info.mem_delta.is_empty()
+            {
+                found.push((*index, info.ptr_delta));
+            }
+            *index += 1;
+            unbalanced_loops(body, index, found);
+        }
+    }
+}
+
+/// Pure loops that keep the pointer in place and add `delta` to a guard known to be `val`
+/// on entry, as preorder indices. Cells wrap, so the guard reaches zero only if the gcd
+/// of `delta` and 256 divides `val`. Best effort: anything else may still never exit
+fn endless_loops(prog: &[Stmt], mut tape: ConstTape, index: &mut usize, found: &mut Vec<usize>) {
+    for stmt in prog {
+        if let Stmt::Loop(body) = stmt {
+            if let Some(val) = tape.get(0)
+                && val != 0
+                && let Ok(info) = symbolic_execution(body)
+                && info.ptr_delta == 0
+                && let Some(delta) = info.mem_delta.get(&0).map_or(Some(0), SymExVal::const_val)
+            {
+                let step = 1u32 << (delta as u8).trailing_zeros();
+                if u32::from(val) % step != 0 {
+                    found.push(*index);
+                }
+            }
+            *index += 1;
+            endless_loops(body, ConstTape::unknown(), index, found);
+        }
+        tape.step(stmt);
+    }
+}
+
+fn warn_endless_loops(args: &Args, prog: &[Stmt], src: &[u8]) {
+    let tape = if fresh_tape(args) {
+        ConstTape::zeroed()
+    } else {
+        ConstTape::unknown()
+    };
+    let mut found = Vec::new();
+    endless_loops(prog, tape, &mut 0, &mut found);
+    let starts = loop_starts(src);
+    for index in found {
+        let (line, col) = starts[index];
+        eprintln!("warning: loop at {line}:{col} never exits, program may not terminate");
+    }
+}
+
+/// Loops entered on a cell known to be zero, which never run, as preorder indices of
+/// those `pick` accepts
+fn loops_on_zero(
+    prog: &[Stmt],
+    mut tape: ConstTape,
+    pick: &impl Fn(&Vec<Stmt>) -> bool,
+    index: &mut usize,
+    found: &mut Vec<usize>,
+) {
+    for stmt in prog {
+        if let Stmt::Loop(body) = stmt {
+            if tape.get(0) == Some(0) && pick(body) {
+                found.push(*index);
+            }
+            *index += 1;
+            loops_on_zero(body, ConstTape::unknown(), pick, index, found);
+        }
+        tape.step(stmt);
+    }
+}
+
+/// [`loops_on_zero`] of a whole program, where the tape starts zeroed unless `--lib` or
+/// `--keep-tape` say otherwise
+fn dead_loops_at(args: &Args, prog: &[Stmt], pick: &impl Fn(&Vec<Stmt>) -> bool) -> Vec<usize> {
+    let tape = if fresh_tape(args) {
+        ConstTape::zeroed()
+    } else {
+        ConstTape::unknown()
+    };
+    let mut found = Vec::new();
+    loops_on_zero(prog, tape, pick, &mut 0, &mut found);
+    found
+}
+
+fn warn_redundant_clears(args: &Args, prog: &[Stmt], src: &[u8]) {
+    // `[-]` and friends, the loops the set pass turns into a `Set(0)`
+    let found = dead_loops_at(args, prog, &|body| {
+        balanced_loop(body).is_some_and(|(mem_delta, step)| mem_delta.len() == 1 && step % 2 != 0)
+    });
+    let starts = loop_starts(src);
+    for index in found {
+        let (line, col) = starts[index];
+        eprintln!("warning: clear at {line}:{col} is redundant, the cell is already zero");
+    }
+}
+
+/// Net effect of a run of `+-` or `<>`, as the shorter run of one of them. Cells wrap, so
+/// `+` runs count modulo 256
+fn net_run(run: &[u8], cells: bool) -> Vec<u8> {
+    let (up, down) = if cells { (b'+', b'-') } else { (b'>', b'<') };
+    let sum: i64 = run.iter().map(|&c| if c == up { 1 } else { -1 }).sum();
+    let sum = match cells {
+        true if sum.rem_euclid(256) > 128 => sum.rem_euclid(256) - 256,
+        true => sum.rem_euclid(256),
+        false => sum,
+    };
+    let c = if sum < 0 { down } else { up };
+    vec![c; sum.unsigned_abs() as usize]
+}
+
+/// `--fix`: the source without what provably does nothing, loops that are never entered
+/// along with the comments in them, and adjacent `+-` and `<>` that cancel. Everything
+/// else, comments included, is kept byte for byte. Repeated until nothing changes, since
+/// a loop taken out can leave `>` next to `<`, then checked against the source like
+/// `--interleave-check` before it is printed
+pub fn fix(args: &Args, f: &[u8]) -> Result<()> {
+    let syntax = o1::Syntax::new(args);
+    let before = compile(o1::compile(f, syntax)?);
+    let mut fixed = f.to_vec();
+    let mut prog = before.clone();
+    loop {
+        let next = fix_once(args, &fixed, &prog);
+        if next == fixed {
+            break;
+        }
+        fixed = next;
+        prog = compile(o1::compile(fixed.as_slice(), syntax)?);
+    }
+    interleave_check(args, &before, &prog, "--fix")?;
+    std::io::stdout().write_all(&fixed)?;
+    Ok(())
+}
+
+/// One pass of [`fix`] over `f`, which `prog` is compiled from
+fn fix_once(args: &Args, f: &[u8], prog: &[Stmt]) -> Vec<u8> {
+    let dead = dead_loops_at(args, prog, &|_| true);
+    let mut fixed = Vec::with_capacity(f.len());
+    let (mut index, mut i) = (0, 0);
+    while i < f.len() {
+        match f[i] {
+            b'[' => {
+                if dead.contains(&index) {
+                    // skip to the matching `]`, counting the loops inside
+                    let mut depth = 0;
+                    loop {
+                        match f[i] {
+                            b'[' => (depth, index) = (depth + 1, index + 1),
+                            b']' if depth == 1 => break,
+                            b']' => depth -= 1,
+                            _ => {}
+                        }
+                        i += 1;
+                    }
+                } else {
+                    index += 1;
+                    fixed.push(b'[');
+                }
+                i += 1;
+            }
+            c @ (b'+' | b'-' | b'<' | b'>') => {
+                let cells = matches!(c, b'+' | b'-');
+                let same = |c: &u8| matches!(c, b'+' | b'-') == cells && b"+-<>".contains(c);
+                let len = f[i..].iter().take_while(|c| same(c)).count();
+                fixed.extend(net_run(&f[i..i + len], cells));
+                i += len;
+            }
+            c => {
+                fixed.push(c);
+                i += 1;
+            }
+        }
+    }
+    fixed
+}
+
+/// Line and column of every `[`. Every `[` becomes a loop, so the nth loop in preorder
+/// starts at the nth of them
+fn loop_starts(src: &[u8]) -> Vec<(usize, usize)> {
+    let mut starts = Vec::new();
+    let (mut line, mut col) = (1, 1);
+    for &c in src {
+        if c == b'[' {
+            starts.push((line, col));
+        }
+        if c == b'\n' {
+            (line, col) = (line + 1, 1);
+        } else {
+            col += 1;
+        }
+    }
+    starts
+}
+
+fn warn_unbalanced_ptr(prog: &[Stmt], src: &[u8]) {
+    let mut found = Vec::new();
+    unbalanced_loops(prog, &mut 0, &mut found);
+    let starts = loop_starts(src);
+    for (index, ptr_delta) in found {
+        let (line, col) = starts[index];
+        eprintln!("warning: loop at {line}:{col} moves the pointer by {ptr_delta} per iteration");
+    }
+}
+
+/// Run lines from stdin against one persistent tape, after the input files if any. Lines
+/// are buffered until their brackets balance, and `,` reads the lines that follow
+pub fn repl(args: Args, f: &[u8]) -> Result<()> {
+    // snippets run on the tape the last one left behind
+    let args = Args {
+        keep_tape: true,
+        ..args
+    };
+    let passes = passes(&args.opt_passes)?;
+    let syntax = o1::Syntax::new(&args);
+    // --random-input would leave no way to type lines
+    let mut interpreter = Interpreter::new(
+        Input::new(None, false),
+        Output::new(args.hex_output, args.raw_io, args.flush_on),
+        &args,
+    );
+    let mut src = f.to_vec();
+    let mut depth = 0;
+    let mut eof = false;
+    while !eof {
+        if !src.is_empty() && depth <= 0 {
+            o1::warn_empty_loops(syntax, &src);
+            let prog = o1::compile(src.as_slice(), syntax)
+                .and_then(|prog| inst_limits(&args, &prog).map(|_| prog))
+                .map(|prog| optimize(compile(prog), &passes, &args, None));
+            let result = prog.and_then(|prog| interpreter.interpret(&prog));
+            interpreter.output.flush()?;
+            if let Err(e) = result {
+                eprintln!("error: {e}");
+            }
+            eprintln!(
+                "ptr {}: {}",
+                interpreter.ptr,
+                tape_window(&interpreter.mem, interpreter.ptr, args.signed_cells)
+            );
+            src.clear();
+            depth = 0;
+        }
+        eprint!("{}", if src.is_empty() { "> " } else { "... " });
+        loop {
+            match interpreter.read_byte()? {
+                None => eof = true,
+                Some(b'\n') => {}
+                Some(c) => {
+                    depth += match c {
+                        b'[' => 1,
+                        b']' => -1,
+                        _ => 0,
+                    };
+                    src.push(c);
+                    continue;
+                }
+            }
+            break;
+        }
+    }
+    eprintln!();
+    Ok(())
+}
+
+/// Refuse programs past `--max-stmts` or `--max-depth` before anything recurses on them
+fn check_limits(args: &Args, stmts: usize, depth: usize) -> Result<()> {
+    if stmts > args.max_stmts {
+        Err(eyre!(
+            "program has {stmts} statements, more than --max-stmts {}",
+            args.max_stmts
+        ))?;
+    }
+    if depth > args.max_depth {
+        Err(eyre!(
+            "program nests loops {depth} deep, more than --max-depth {}",
+            args.max_depth
+        ))?;
+    }
+    Ok(())
+}
+
+/// Statement count and loop depth of the o2 tree `compile` makes, without recursion
+fn inst_limits(args: &Args, prog: &[o1::Inst]) -> Result<()> {
+    let (mut stmts, mut depth, mut max_depth) = (0, 0, 0);
+    for inst in prog {
+        match inst {
+            o1::Inst::LoopStart(_) => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            // closes the loop statement, not one of its own
+            o1::Inst::LoopEnd(_) => {
+                depth -= 1;
+                continue;
+            }
+            _ => {}
+        }
+        stmts += 1;
+    }
+    check_limits(args, stmts, max_depth)
+}
+
+/// Statement count and loop depth of `prog`, walked with an explicit stack
+fn stmt_limits(args: &Args, prog: &[Stmt]) -> Result<()> {
+    let (mut stmts, mut max_depth) = (0, 0);
+    let mut stack = vec![(prog, 0)];
+    while let Some((prog, depth)) = stack.pop() {
+        stmts += prog.len();
+        max_depth = max_depth.max(depth);
+        for stmt in prog {
+            if let Stmt::Loop(body)
+            | Stmt::If(body)
+            | Stmt::DoLoop(body)
+            | Stmt::StridedLoop { body, .. } = stmt
+            {
+                stack.push((body, depth + 1));
+            }
+        }
+    }
+    check_limits(args, stmts, max_depth)
+}
+
+/// Optimized program from Brainfuck source, or as is with `--from-stmt`
+pub fn load(args: &Args, mut f: impl Read) -> Result<Vec<Stmt>> {
+    if args.from_stmt {
+        let mut s = String::new();
+        f.read_to_string(&mut s)?;
+        let prog = parse(&s)?;
+        stmt_limits(args, &prog)?;
+        return Ok(prog);
+    }
+    let mut src = Vec::new();
+    f.read_to_end(&mut src)?;
+    let prog = o1::compile(src.as_slice(), o1::Syntax::new(args))?;
+    inst_limits(args, &prog)?;
+    let iterations = match &args.profile_input {
+        _ if !args.profile_guided => None,
+        Some(path) => Some(o1::profile(&prog, &fs::read(path)?, args.max_mem)),
+        None => Some(o1::profile(&prog, &[], args.max_mem)),
+    };
+    let prog = compile(prog);
+    // before unrolling copies loops, the lints find them by their `[`
+    if args.warn_unbalanced_ptr {
+        warn_unbalanced_ptr(&prog, &src);
+    }
+    if args.warn_redundant_clears {
+        warn_redundant_clears(args, &prog, &src);
+    }
+    warn_endless_loops(args, &prog, &src);
+    let prog = match iterations {
+        Some(iterations) => unroll_hot(prog, &iterations, args),
+        None => prog,
+    };
+    let before = args.interleave_check.then(|| prog.clone());
+    let mut report = args.opt_report.then(|| OptReport::new(&prog));
+    // unrolling can grow the program
+    let prog = optimize(
+        prog,
+        &passes(&args.opt_passes)?,
+        args,
+        report.as_mut().map(|report| &mut report.fired),
+    );
+    if let Some(before) = before {
+        interleave_check(args, &before, &prog, "optimizing")?;
+    }
+    if let Some(report) = report {
+        report.print(args, &prog);
+    }
+    stmt_limits(args, &prog)?;
+    Ok(prog)
+}
+
+pub fn main(args: Args, f: impl Read) -> Result<()> {
+    let prog = load(&args, f)?;
+    if args.json {
+        // analysis only, so stdout holds nothing but the JSON
+        if args.ir_stats {
+            println!("{}", stats_json(&IrStats::new(&prog)));
+        }
+        return Ok(());
+    }
+    if args.ir_stats {
+        eprintln!("{}", IrStats::new(&prog));
+    }
+    if args.text {
+        if args.emit == Emit::JsonAst {
+            println!("{}", json_ast(&prog));
+        } else {
+            print!("{}", Prog(prog));
+        }
+        return Ok(());
+    }
+    let max_mem = cells_used(&args, &prog);
+    let args = Args { max_mem, ..args };
+    let mut interpreter = Interpreter::stdio(&args)?;
+    if args.repeat > 1 {
+        for run in 1..=args.repeat {
+            if run > 1 {
+                interpreter.reset(args.keep_tape);
+            }
+            interpreter.interpret(&prog)?;
+            if args.count_io {
+                eprintln!("{}", interpreter.counts);
+            }
+        }
+        return interpreter.output.finish();
+    }
+    for run in 1..=args.runs {
+        if run > 1 {
+            interpreter.reset(args.keep_tape);
+            interpreter.reseed(args.random_input);
+        }
+        let start = Instant::now();
+        interpreter.interpret(&prog)?;
+        if args.runs > 1 && !args.deterministic {
+            interpreter.output.flush()?;
+            eprintln!("run {run}: {:?}", start.elapsed());
+        }
+        if args.count_io {
+            eprintln!("{}", interpreter.counts);
+        }
+    }
+    interpreter.output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn session_keeps_tape() {
+        let args = Args::parse_from(["sbfnj", "--o2", "--max-mem", "8", "-"]);
+        let mut interpreter = Interpreter::new(std::io::empty(), Vec::new(), &args);
+        let first = vec![Stmt::ValInc(2), Stmt::PtrInc(1), Stmt::ValInc(1)];
+        interpreter.interpret(&first).unwrap();
+        // the second snippet starts where the first left the pointer
+        let second = vec![Stmt::ValInc(2), Stmt::PtrInc(-1), Stmt::ValInc(1)];
+        interpreter.interpret(&second).unwrap();
+        assert_eq!(interpreter.ptr, 0);
+        assert_eq!(interpreter.mem[..3], [3, 3, 0]);
+        interpreter.reset(false);
+        assert_eq!(interpreter.ptr, 0);
+        assert!(interpreter.mem.iter().all(|&cell| cell == 0));
+    }
+
+    #[test]
+    fn clear_loop() {
+        let args = Args::parse_from(["sbfnj", "--o2", "-"]);
+        let prog = vec![
+            Stmt::Input,
+            Stmt::Loop(vec![Stmt::ValInc(-1)]),
+            Stmt::Output,
+        ];
+        assert_eq!(
+            set_loops(prog, &args),
+            [Stmt::Input, Stmt::Set(0), Stmt::Output]
+        );
     }
-    Interpreter::new(&prog).interpret()
 }
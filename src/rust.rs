@@ -0,0 +1,156 @@
+use std::fmt::Write;
+
+use crate::{Args, o2::Stmt};
+
+/// `--emit rust`: a standalone source file whose `run` executes the o2 program, for
+/// embedding it in a Rust project without LLVM. The tape is a `Vec` indexed with bounds
+/// checks, so moving off it panics where the interpreters report an error
+pub fn emit(prog: &[Stmt], args: &Args, source: &str) -> String {
+    let mut rust = String::new();
+    emit_into(&mut rust, prog, args, source).expect("writing to a String cannot fail");
+    rust
+}
+
+fn emit_into(rust: &mut String, prog: &[Stmt], args: &Args, source: &str) -> std::fmt::Result {
+    write!(
+        rust,
+        "// Generated by sbfnj from {source}, do not edit\n\
+         \n\
+         use std::io::{{BufWriter, Read, Write}};\n\
+         \n\
+         /// Run the program on a zeroed tape of {} cells, reading `,` from `input` (0 at\n\
+         /// EOF) and writing `.` to `output`\n\
+         #[allow(unused_mut, unused_variables)]\n\
+         pub fn run<R: Read, W: Write>(mut input: R, output: W) -> std::io::Result<()> {{\n\
+         \x20   let mut output = BufWriter::new(output);\n\
+         \x20   let mut mem = vec![0u8; {}];\n\
+         \x20   let mut ptr: usize = 0;\n",
+        args.max_mem, args.max_mem
+    )?;
+    stmts(rust, prog, 1, args.signed_cells)?;
+    rust.push_str("    output.flush()\n}\n");
+    if reads_input(prog) {
+        rust.push_str(
+            "\n\
+             fn read_byte(input: &mut impl Read) -> std::io::Result<u8> {\n\
+             \x20   let mut byte = [0];\n\
+             \x20   match input.read_exact(&mut byte) {\n\
+             \x20       Ok(()) => Ok(byte[0]),\n\
+             \x20       Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(0),\n\
+             \x20       Err(e) => Err(e),\n\
+             \x20   }\n\
+             }\n",
+        );
+    }
+    Ok(())
+}
+
+fn reads_input(prog: &[Stmt]) -> bool {
+    prog.iter().any(|stmt| match stmt {
+        Stmt::Input => true,
+        Stmt::Loop(body) | Stmt::StridedLoop { body, .. } | Stmt::If(body) | Stmt::DoLoop(body) => {
+            reads_input(body)
+        }
+        _ => false,
+    })
+}
+
+/// `mem[ptr + offset]` as an index expression
+fn cell(offset: i32) -> String {
+    match offset {
+        0 => "mem[ptr]".to_owned(),
+        1.. => format!("mem[ptr + {offset}]"),
+        _ => format!("mem[ptr - {}]", offset.unsigned_abs()),
+    }
+}
+
+fn ptr_inc(rust: &mut String, indent: &str, n: i32) -> std::fmt::Result {
+    match n {
+        0.. => writeln!(rust, "{indent}ptr += {n};"),
+        _ => writeln!(rust, "{indent}ptr -= {};", n.unsigned_abs()),
+    }
+}
+
+fn stmts(rust: &mut String, prog: &[Stmt], depth: usize, signed_cells: bool) -> std::fmt::Result {
+    let indent = "    ".repeat(depth);
+    let num = if signed_cells { " as i8" } else { "" };
+    for stmt in prog {
+        match stmt {
+            Stmt::PtrInc(n) => ptr_inc(rust, &indent, *n),
+            Stmt::ValInc(n) => writeln!(
+                rust,
+                "{indent}mem[ptr] = mem[ptr].wrapping_add({});",
+                *n as u8
+            ),
+            Stmt::Set(n) => writeln!(rust, "{indent}mem[ptr] = {};", *n as u8),
+            Stmt::AddMul(offset, factor) => {
+                let target = cell(*offset);
+                writeln!(
+                    rust,
+                    "{indent}{target} = {target}.wrapping_add(mem[ptr].wrapping_mul({}));",
+                    *factor as u8
+                )
+            }
+            Stmt::ValIncAt(offset, n) => {
+                let target = cell(*offset);
+                writeln!(
+                    rust,
+                    "{indent}{target} = {target}.wrapping_add({});",
+                    *n as u8
+                )
+            }
+            Stmt::SetAt(offset, n) => writeln!(rust, "{indent}{} = {};", cell(*offset), *n as u8),
+            Stmt::Scan(n) => {
+                writeln!(rust, "{indent}while mem[ptr] != 0 {{")?;
+                ptr_inc(rust, &format!("{indent}    "), *n)?;
+                writeln!(rust, "{indent}}}")
+            }
+            Stmt::Loop(body) => {
+                writeln!(rust, "{indent}while mem[ptr] != 0 {{")?;
+                stmts(rust, body, depth + 1, signed_cells)?;
+                writeln!(rust, "{indent}}}")
+            }
+            Stmt::StridedLoop { stride, body } => {
+                writeln!(rust, "{indent}while mem[ptr] != 0 {{")?;
+                stmts(rust, body, depth + 1, signed_cells)?;
+                ptr_inc(rust, &format!("{indent}    "), *stride)?;
+                writeln!(rust, "{indent}}}")
+            }
+            Stmt::If(body) => {
+                writeln!(rust, "{indent}if mem[ptr] != 0 {{")?;
+                stmts(rust, body, depth + 1, signed_cells)?;
+                writeln!(rust, "{indent}}}")
+            }
+            Stmt::DoLoop(body) => {
+                writeln!(rust, "{indent}loop {{")?;
+                stmts(rust, body, depth + 1, signed_cells)?;
+                writeln!(
+                    rust,
+                    "{indent}    if mem[ptr] == 0 {{\n{indent}        break;\n{indent}    }}"
+                )?;
+                writeln!(rust, "{indent}}}")
+            }
+            Stmt::Output => writeln!(rust, "{indent}output.write_all(&[mem[ptr]])?;"),
+            Stmt::OutputConst(bytes) => writeln!(
+                rust,
+                "{indent}output.write_all(b\"{}\")?;",
+                bytes.escape_ascii()
+            ),
+            // a prompt must be visible before blocking on the read
+            Stmt::Input => writeln!(
+                rust,
+                "{indent}output.flush()?;\n{indent}mem[ptr] = read_byte(&mut input)?;"
+            ),
+            Stmt::DebugCell => writeln!(
+                rust,
+                "{indent}output.flush()?;\n{indent}eprintln!(\"{{}}\", mem[ptr]{num});"
+            ),
+            Stmt::OutputNum => writeln!(rust, "{indent}write!(output, \"{{}}\", mem[ptr]{num})?;"),
+            Stmt::Mod => writeln!(
+                rust,
+                "{indent}if mem[ptr + 1] != 0 {{\n{indent}    mem[ptr] %= mem[ptr + 1];\n{indent}}}"
+            ),
+        }?;
+    }
+    Ok(())
+}
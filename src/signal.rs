@@ -0,0 +1,37 @@
+use std::{
+    io::Write,
+    process::exit,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use eyre::Result;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Record SIGINT instead of dying, so the interpreters can flush output first
+pub fn install() {
+    #[cfg(feature = "sigint")]
+    {
+        extern "C" fn handler(_: libc::c_int) {
+            INTERRUPTED.store(true, Ordering::Relaxed);
+        }
+        unsafe {
+            libc::signal(libc::SIGINT, handler as *const () as libc::sighandler_t);
+        }
+    }
+}
+
+/// Always false without the `sigint` feature
+pub fn interrupted() -> bool {
+    cfg!(feature = "sigint") && INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Polled on loop back-edges
+pub fn check(output: &mut impl Write) -> Result<()> {
+    if interrupted() {
+        output.flush()?;
+        eprintln!("interrupted");
+        exit(130);
+    }
+    Ok(())
+}
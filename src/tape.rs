@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use eyre::{Result, eyre};
+
+use crate::{Args, io::IoCounts};
+
+/// Value shown for a cell by debug output, `--signed-cells` reads it as `i8`
+pub fn cell_num(cell: u8, signed: bool) -> i32 {
+    if signed {
+        cell as i8 as i32
+    } else {
+        cell as i32
+    }
+}
+
+/// Cells around the pointer, the current one in brackets
+pub fn tape_window(mem: &[u8], ptr: usize, signed: bool) -> String {
+    let start = ptr.saturating_sub(4);
+    let end = (ptr + 5).min(mem.len());
+    (start..end)
+        .map(|i| {
+            let cell = cell_num(mem[i], signed);
+            if i == ptr {
+                format!("[{cell}]")
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `--tape-dump-on-error`: the cells around the pointer a move failed from, and where in
+/// the program if the interpreter knows, on stderr ahead of the error itself
+pub fn dump_tape(mem: &[u8], ptr: usize, at: Option<String>, signed: bool) {
+    let at = at.map(|at| format!(" at {at}")).unwrap_or_default();
+    eprintln!("tape{at}, ptr {ptr}: {}", tape_window(mem, ptr, signed));
+}
+
+/// How the interpreters move the pointer
+#[derive(Debug, Clone, Copy)]
+pub struct PtrMoves {
+    /// `--wrap`: the tape is circular
+    wrap: bool,
+    halt_on_overflow: bool,
+}
+
+impl PtrMoves {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            wrap: args.wrap,
+            halt_on_overflow: args.halt_on_overflow,
+        }
+    }
+
+    /// `ptr + delta` checked against the tape, and against overflowing `usize` with
+    /// `--halt-on-overflow`. A wrapped pointer is off the tape too, just reported as that.
+    /// With `--wrap` it comes back at the other end instead
+    pub fn apply(self, ptr: usize, delta: isize, len: usize) -> Result<usize> {
+        if self.wrap {
+            return Ok((ptr + delta.rem_euclid(len as isize) as usize) % len);
+        }
+        let moved = if self.halt_on_overflow {
+            ptr.checked_add_signed(delta)
+                .ok_or_else(|| eyre!("pointer {ptr} overflowed moving by {delta}"))?
+        } else {
+            ptr.wrapping_add_signed(delta)
+        };
+        check_ptr(moved, len)
+    }
+}
+
+/// Checked once after every pointer move, so cell accesses can index directly
+pub fn check_ptr(ptr: usize, len: usize) -> Result<usize> {
+    if ptr < len {
+        Ok(ptr)
+    } else {
+        Err(eyre!("pointer {} exceeded tape {len}", ptr as isize))
+    }
+}
+
+/// Stops a loop that spins once input is at EOF. `,` then keeps reading 0, so if a pass
+/// through a loop ends with the same pointer and tape as the pass before, printing and
+/// reading nothing in between, every later pass is the same and the loop never exits.
+/// Only the 2^k-th pass is compared with the one before it, costing a tape copy per
+/// doubling, so a spin is caught by the time it has run as long as the loop did before
+#[derive(Debug, Default)]
+pub struct SpinGuard {
+    /// Passes after EOF and the last snapshot, by loop
+    loops: HashMap<usize, (u64, Option<Snapshot>)>,
+}
+
+#[derive(Debug)]
+struct Snapshot {
+    ptr: usize,
+    outputs: usize,
+    input_bytes: usize,
+    mem: Vec<u8>,
+}
+
+impl SpinGuard {
+    /// At the start of every pass but the first through the loop identified by `id`
+    pub fn spinning(&mut self, id: usize, mem: &[u8], ptr: usize, counts: &IoCounts) -> bool {
+        if counts.inputs == counts.input_bytes {
+            return false;
+        }
+        let (passes, before) = self.loops.entry(id).or_default();
+        *passes += 1;
+        if (*passes + 1).is_power_of_two() {
+            *before = Some(Snapshot {
+                ptr,
+                outputs: counts.outputs,
+                input_bytes: counts.input_bytes,
+                mem: mem.to_vec(),
+            });
+        } else if passes.is_power_of_two()
+            && let Some(before) = before.take()
+        {
+            return before.ptr == ptr
+                && before.outputs == counts.outputs
+                && before.input_bytes == counts.input_bytes
+                && before.mem == mem;
+        }
+        false
+    }
+}
+
+/// Printed when [`SpinGuard::spinning`] stops a run
+pub const SPIN_WARNING: &str =
+    "warning: stopped a loop that cannot exit, input is at EOF and the tape stopped changing";
@@ -0,0 +1,43 @@
+//! `--buffered-runtime` batches the LLVM executable's output into `write` calls and reads
+//! input in blocks, flushing what it holds before each read and before `main` returns.
+
+mod common;
+
+use common::{sbfnj, source};
+
+/// Echoes its input, then prints 10000 '@', more than one buffer
+fn echo() -> String {
+    format!(
+        ",[.,]{}>{}[>{}[<<{}>>-]<-]",
+        "+".repeat(64),
+        "+".repeat(100),
+        "+".repeat(10),
+        ".".repeat(10)
+    )
+}
+
+fn run(name: &str, args: &[&str], input: &[u8]) -> Vec<u8> {
+    let prog = source("buffered-runtime", name, echo());
+    let args = [&["--llvm", "--no-cache"], args].concat();
+    sbfnj(&args, prog, input).stdout
+}
+
+#[test]
+fn flushed_before_return() {
+    let ir = String::from_utf8(run("ir.b", &["--buffered-runtime", "--text"], b"")).unwrap();
+    assert!(ir.contains("call void @io.putc("), "{ir}");
+    assert!(ir.contains("call i8 @io.getc()"), "{ir}");
+    assert!(
+        ir.contains("  call void @io.flush()\n  ret i32 0\n"),
+        "{ir}"
+    );
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn same_output() {
+    let input = b"buffered\n".repeat(1000);
+    let buffered = run("buffered.b", &["--buffered-runtime"], &input);
+    assert_eq!(buffered.len(), input.len() + 10_000);
+    assert_eq!(buffered, run("libc.b", &[], &input));
+}
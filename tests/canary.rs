@@ -0,0 +1,44 @@
+//! `--canary` catches a write into the `--tape-guard` canaries when the loop doing it
+//! exits, before the rest of the program runs, and names that loop.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+/// Writes left of the tape, cats its input in loop 1, then prints 6 after the loop
+const OVERRUN: &str = "<+>,[.,]>+++[<++>-]<.";
+
+fn run(args: &[&str]) -> Output {
+    let prog = source("canary", &format!("overrun{}.b", args.join("")), OVERRUN);
+    let args = [&["--llvm", "--runtime", "freestanding"], args].concat();
+    common::run(&args, prog, b"xy")
+}
+
+#[test]
+fn requires_tape_guard() {
+    let output = run(&["--text", "--canary"]);
+    assert!(!output.status.success());
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn in_loop() {
+    let output = run(&["--tape-guard", "--canary"]);
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"xy");
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("pointer overran the tape guard in loop 1")
+    );
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn at_exit() {
+    let output = run(&["--tape-guard"]);
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"xy\x06");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pointer overran the tape guard"));
+}
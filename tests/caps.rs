@@ -0,0 +1,61 @@
+//! `--caps` reports the build's features without needing a program, as key=value lines
+//! or one JSON object with `--json`.
+
+use std::process::Command;
+
+fn caps(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_sbfnj"))
+        .arg("--caps")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn lines() {
+    let caps = caps(&[]);
+    let lines: Vec<_> = caps.lines().collect();
+    assert!(lines.contains(&"llvm=true"), "{caps}");
+    assert!(lines.contains(&"backends=o0,o1,o2,llvm"), "{caps}");
+    assert!(
+        lines.contains(&format!("raw_io={}", cfg!(feature = "raw-io")).as_str()),
+        "{caps}"
+    );
+    assert!(
+        lines.contains(&format!("sigint={}", cfg!(feature = "sigint")).as_str()),
+        "{caps}"
+    );
+    assert!(
+        lines.contains(&format!("serde={}", cfg!(feature = "serde")).as_str()),
+        "{caps}"
+    );
+    assert!(
+        lines.contains(&format!("gzip={}", cfg!(feature = "gzip")).as_str()),
+        "{caps}"
+    );
+    if cfg!(feature = "clang") {
+        assert!(lines.contains(&"clang=true"), "{caps}");
+    }
+}
+
+#[test]
+fn json() {
+    let caps = caps(&["--json"]);
+    assert!(caps.starts_with(r#"{"version":""#), "{caps}");
+    assert!(
+        caps.contains(r#""emit":["exe","asm","obj","json-ast","rust","ssa-ir","tokens"]"#),
+        "{caps}"
+    );
+    assert!(caps.trim_end().ends_with('}'), "{caps}");
+}
+
+#[test]
+fn json_needs_ir_stats_or_caps() {
+    let output = Command::new(env!("CARGO_BIN_EXE_sbfnj"))
+        .args(["--json", "tests/dbfi.b"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
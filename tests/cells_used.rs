@@ -0,0 +1,70 @@
+//! `--count-cells-used` bounds the tape cells a program can reach, and `--auto-mem` sizes
+//! the tape from that bound, falling back to `--max-mem` when there is none.
+
+mod common;
+
+use std::process::Output;
+
+use common::{sbfnj, source};
+
+fn run(name: &str, args: &[&str], src: &str) -> Output {
+    common::run(args, source("cells-used", name, src), b"")
+}
+
+fn cells_used(name: &str, src: &str) -> String {
+    let prog = source("cells-used", name, src);
+    let output = sbfnj(&["--o2", "--count-cells-used"], prog, b"");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/// Writes the cell 40000 cells right of the start, past the default tape
+fn far() -> String {
+    ">".repeat(39999) + "+."
+}
+
+#[test]
+fn finite() {
+    assert_eq!(
+        cells_used("finite.b", ",>>>+<<[-]>>>>."),
+        "cells used: 0..=5, 6 cells\n"
+    );
+    // a loop that returns the pointer to where it started is bounded by its body
+    assert_eq!(
+        cells_used("loop.b", ",[->>+<<]"),
+        "cells used: 0..=2, 3 cells\n"
+    );
+}
+
+#[test]
+fn unbounded() {
+    assert_eq!(
+        cells_used("unbounded.b", ",[>,]"),
+        "cells used: unbounded, a loop moves the pointer\n"
+    );
+}
+
+fn check(backend: &str) {
+    let output = run(&format!("far{backend}.b"), &[backend, "--auto-mem"], &far());
+    assert!(output.status.success(), "{backend}");
+    assert_eq!(output.stdout, [1], "{backend}");
+    // without a bound the tape keeps its default size
+    let output = run(
+        &format!("cat{backend}.b"),
+        &[backend, "--auto-mem"],
+        ",[>,]+.",
+    );
+    assert!(output.status.success(), "{backend}");
+    assert_eq!(output.stdout, [1], "{backend}");
+}
+
+#[test]
+fn o2() {
+    assert!(!run("far.b", &["--o2"], &far()).status.success());
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
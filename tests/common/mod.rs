@@ -0,0 +1,71 @@
+//! The fixture every integration test shares: write a program to the temp directory and
+//! run the `sbfnj` binary on it with some input.
+
+// each test crate builds this module and uses part of it
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    thread,
+};
+
+/// Writes `src` to `name` in `sbfnj-<test>` under the temp directory. Each test file gets
+/// a directory of its own, as the LLVM backend writes its `.ll` and executable next to
+/// the source
+pub fn source(test: &str, name: &str, src: impl AsRef<[u8]>) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sbfnj-{test}"));
+    fs::create_dir_all(&dir).unwrap();
+    let prog = dir.join(name);
+    fs::write(&prog, src).unwrap();
+    prog
+}
+
+pub fn command() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_sbfnj"))
+}
+
+/// Runs `command` with `input` on stdin and stdout and stderr captured. The input is
+/// written from a thread, so a program that prints only once it has read everything
+/// cannot fill the pipe and deadlock, and one that exits without reading is fine
+pub fn run_with(command: &mut Command, input: &[u8]) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    output
+}
+
+/// `sbfnj <args> <prog>` on `input`
+pub fn run(args: &[&str], prog: impl AsRef<Path>, input: &[u8]) -> Output {
+    run_with(command().args(args).arg(prog.as_ref()), input)
+}
+
+/// [`run`] that must exit successfully
+pub fn sbfnj(args: &[&str], prog: impl AsRef<Path>, input: &[u8]) -> Output {
+    let prog = prog.as_ref();
+    let output = run(args, prog, input);
+    assert!(
+        output.status.success(),
+        "sbfnj {args:?} {} failed: {}",
+        prog.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// Stdout of a successful run as text, such as an IR listing
+pub fn text(args: &[&str], prog: impl AsRef<Path>) -> String {
+    String::from_utf8(sbfnj(args, prog, b"").stdout).unwrap()
+}
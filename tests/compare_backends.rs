@@ -0,0 +1,37 @@
+//! `--compare-backends` runs the program on every backend with the same input and fails
+//! naming those whose output differs from o0's.
+
+mod common;
+
+use common::{run, sbfnj, source};
+
+#[test]
+fn agree() {
+    // a multiply loop and a scan for the optimizers to rewrite, then a cat
+    let prog = source(
+        "compare-backends",
+        "agree.b",
+        "+++[>++++<-]>.[>]+<[<]>>.,[.,]",
+    );
+    let output = sbfnj(&["--compare-backends"], prog, b"abc");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("o0: reference, 5 bytes"), "{stderr}");
+    for backend in ["o1", "o2"] {
+        assert!(stderr.contains(&format!("{backend}: agrees")), "{stderr}");
+    }
+    // clang may be missing, but the LLVM leg is never silently left out
+    assert!(
+        stderr.contains("llvm: agrees") || stderr.contains("llvm: skipped, clang not found"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn failing() {
+    let prog = source("compare-backends", "left.b", "<");
+    let output = run(&["--compare-backends"], prog, b"");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("o0: failed with"), "{stderr}");
+    assert!(stderr.contains("backends diverged: o0, o1, o2"), "{stderr}");
+}
@@ -0,0 +1,84 @@
+//! `--debug` steps the o0 interpreter by commands on stdin, and `back` undoes steps from
+//! its `--history` log, leaving the pointer, tape and input as they were before them.
+
+mod common;
+
+use common::{run, sbfnj, source};
+
+/// Copies cell 0 into cells 1 and 2, then prints cell 2
+const SRC: &str = "++[->+>+<<]>>.";
+
+/// Stdout and stderr of `--debug` on `src` with `commands` on stdin, and the state it
+/// printed after each command
+fn debug(args: &[&str], name: &str, src: &str, commands: &str) -> (Vec<u8>, String, Vec<String>) {
+    let prog = source("debug", name, src);
+    let args = [&["--debug"], args].concat();
+    let output = sbfnj(&args, prog, commands.as_bytes());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let states = stderr
+        .split("> ")
+        .filter_map(|printed| printed.lines().last())
+        .filter(|state| state.starts_with("pc "))
+        .map(str::to_owned)
+        .collect();
+    (output.stdout, stderr, states)
+}
+
+#[test]
+fn back_mid_loop() {
+    let (_, _, states) = debug(&[], "mid.b", SRC, "s 5\np\ns 7\nb 7\np\n");
+    assert_eq!(states[1], "pc 5 '+', ptr 1: 1 [0] 0 0 0 0");
+    assert_ne!(states[2], states[1]);
+    assert_eq!(states[3], states[1]);
+    assert_eq!(states[4], states[1]);
+}
+
+#[test]
+fn back_to_start() {
+    let (stdout, stderr, states) = debug(&[], "start.b", SRC, "p\nc\nb 100\np\n");
+    assert_eq!(states[1], "pc 14 end, ptr 2: 0 2 [2] 0 0 0 0");
+    // the output stays written
+    assert_eq!(stdout, [2]);
+    assert!(stderr.contains("no more history"), "{stderr}");
+    assert_eq!(states[2], states[0]);
+    assert_eq!(states[3], states[0]);
+}
+
+#[test]
+fn rereads_input() {
+    // `,` reads the byte after the line that stepped onto it, back gives it back
+    let prog = source("debug", "read.b", ",+");
+    let output = sbfnj(&["--debug", "--count-io"], prog, b"s\nAb\ns\np\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.ends_with(
+            "> pc 1 '+', ptr 0: [65] 0 0 0 0\n> \noutput: 0 bytes; input: 1 ops, 1 bytes\n"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn history() {
+    let (_, stderr, states) = debug(&["--history", "1"], "history.b", SRC, "s 2\nb\nb\n");
+    assert_eq!(states[1], "pc 1 '+', ptr 0: [1] 0 0 0 0");
+    assert!(
+        stderr.contains("no more history, --history keeps 1"),
+        "{stderr}"
+    );
+    assert_eq!(states[2], states[1]);
+}
+
+#[test]
+fn o0_only() {
+    let prog = source("debug", "o2.b", SRC);
+    let output = run(&["--o2", "--debug"], prog, b"");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn no_ext() {
+    let prog = source("debug", "ext.b", "+:");
+    let output = run(&["--debug", "--ext"], prog, b"");
+    assert!(!output.status.success());
+}
@@ -0,0 +1,48 @@
+//! Two `--deterministic` invocations print byte-identical output, IR and diagnostics.
+
+mod common;
+
+use common::{run, sbfnj, source};
+
+/// dbfi exercises every o2 pass and, outlined, the deduplication of identical loops
+const DBFI: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/dbfi.b");
+
+fn outputs(args: &[&str], prog: &str) -> (Vec<u8>, Vec<u8>) {
+    let output = sbfnj(&[&["--deterministic"], args].concat(), prog, b"");
+    (output.stdout, output.stderr)
+}
+
+fn check(args: &[&str], prog: &str) {
+    assert_eq!(outputs(args, prog), outputs(args, prog), "{args:?}");
+}
+
+#[test]
+fn ir() {
+    check(&["--o2", "--text"], DBFI);
+    check(&["--llvm", "--text"], DBFI);
+    check(&["--llvm", "--text", "--outline-loops"], DBFI);
+}
+
+#[test]
+fn timings() {
+    let prog = source(
+        "deterministic",
+        "hello.b",
+        "++++++++[>++++++++<-]>+.[-]++++++++++.",
+    );
+    let prog = prog.to_str().unwrap();
+    check(&["--parse-only"], prog);
+    check(&["--o2", "--runs", "3"], prog);
+}
+
+#[test]
+fn random_input() {
+    let prog = source("deterministic", "echo.b", ",.".repeat(64));
+    check(&["--o2", "--random-input"], prog.to_str().unwrap());
+}
+
+#[test]
+fn arch_native() {
+    let args = ["--deterministic", "--arch-native", "--llvm", "--text"];
+    assert!(!run(&args, DBFI, b"").status.success());
+}
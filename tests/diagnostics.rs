@@ -0,0 +1,15 @@
+//! Parse errors quote the source line with a caret under the offending byte.
+
+mod common;
+
+use common::{run, source};
+
+#[test]
+fn orphan_bracket_snippet() {
+    let prog = source("diagnostics", "orphan.b", "++[>+<-]\n+++]--");
+    let output = run(&["--o1"], prog, b"");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("2 | +++]--\n  |    ^\n"), "{stderr}");
+    assert!(stderr.contains("Orphan ']' at 2:4"), "{stderr}");
+}
@@ -0,0 +1,13 @@
+//! `--emit asm` has LLVM write the host's assembly for the program, with `--text` to
+//! stdout instead of next to the source.
+
+mod common;
+
+use common::{source, text};
+
+#[test]
+fn main_symbol() {
+    let prog = source("emit-asm", "echo.b", ",[.,]");
+    let asm = text(&["--llvm", "--emit", "asm", "--text"], prog);
+    assert!(asm.contains("main:"), "{asm}");
+}
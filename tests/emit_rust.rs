@@ -0,0 +1,77 @@
+//! `--emit rust` writes a Rust source whose `run` behaves like the program: compiled by
+//! rustc behind a small `main`, it prints what the o2 interpreter prints.
+
+mod common;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use common::{run_with, sbfnj, source};
+
+/// Compile `<name>.rs` from `--emit rust` into an executable calling its `run` on stdio
+fn build(dir: &Path, name: &str) -> PathBuf {
+    let main = dir.join(format!("{name}_main.rs"));
+    fs::write(
+        &main,
+        format!(
+            "#[path = \"{name}.b.rs\"]\nmod prog;\n\nfn main() {{\n    \
+             prog::run(std::io::stdin().lock(), std::io::stdout().lock()).unwrap();\n}}\n"
+        ),
+    )
+    .unwrap();
+    let exe = dir.join(format!("{name}_main"));
+    let status = Command::new(env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned()))
+        .args(["--edition", "2021", "-O", "-o"])
+        .arg(&exe)
+        .arg(&main)
+        .status()
+        .unwrap();
+    assert!(status.success(), "{name}");
+    exe
+}
+
+fn check(name: &str, src: &str, input: &[u8], args: &[&str]) {
+    let prog = source("emit-rust", &format!("{name}.b"), src);
+    let expected = sbfnj(&[&["--o2"], args].concat(), &prog, input).stdout;
+    sbfnj(&[&["--llvm", "--emit", "rust"], args].concat(), &prog, b"");
+    let output = run_with(
+        &mut Command::new(build(prog.parent().unwrap(), name)),
+        input,
+    );
+    assert!(output.status.success(), "{name}");
+    assert_eq!(output.stdout, expected, "{name}");
+}
+
+#[test]
+fn hello_world() {
+    let src = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+    check("hello", src, b"", &[]);
+}
+
+#[test]
+fn loops_and_input() {
+    // reverses its input, then doubles and triples a cell through multiply loops and
+    // prints it as a number
+    let src = ">,[>,]<[.<]++++++[->++>+++<<]>>:<[-<+>]<:";
+    check("reverse", src, b"sbfnj\n", &["--ext"]);
+}
+
+#[test]
+fn modulo() {
+    // 123 % 10, then 123 % 0 leaves the cell as is
+    check("modulo", ",>,<%:>[-]<%:", &[123, 10], &["--ext"]);
+}
+
+#[test]
+fn dbfi() {
+    let input = fs::read("tests/dbfi.in").unwrap();
+    check(
+        "dbfi",
+        &fs::read_to_string("tests/dbfi.b").unwrap(),
+        &input,
+        &[],
+    );
+}
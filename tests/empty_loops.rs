@@ -0,0 +1,17 @@
+//! An empty loop `[]` is warned about once, however often the backend compiles it.
+
+mod common;
+
+use common::{sbfnj, source};
+
+#[test]
+fn warned_once() {
+    let prog = source("empty-loops", "once.b", "+[-]\n>[]+.");
+    for args in [&[][..], &["--o1"], &["--o2"], &["--fix"]] {
+        let stderr = String::from_utf8(sbfnj(args, &prog, b"").stderr).unwrap();
+        assert_eq!(
+            stderr, "warning: empty loop at 2:2 never exits if its cell is nonzero\n",
+            "{args:?}"
+        );
+    }
+}
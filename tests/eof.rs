@@ -0,0 +1,62 @@
+//! `,` at EOF stores 0 on every backend, so a plain `,[.,]` cat stops after its input.
+//! A 0xFF byte is data and must not be mistaken for EOF, which is -1 from `getchar`.
+
+mod common;
+
+use std::{
+    io::Write,
+    process::Stdio,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{command, source};
+
+const CAT: &str = ",[.,]";
+
+const INPUT: &[u8] = b"a\xffb";
+
+fn check(backend: &str) {
+    let prog = source("eof", &format!("cat{backend}.b"), CAT);
+    let mut child = command()
+        .arg(backend)
+        .arg(&prog)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(INPUT).unwrap();
+    // a cat that misses EOF spins forever, fail instead of hanging the test run
+    let deadline = Instant::now() + Duration::from_secs(60);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("cat on {backend} did not stop at EOF");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, INPUT, "{backend}");
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
@@ -0,0 +1,60 @@
+//! `--expect FILE` compares the output with FILE instead of printing it, exiting 0 when
+//! they match and reporting the first byte that differs otherwise.
+
+mod common;
+
+use std::{path::PathBuf, process::Output};
+
+use common::source;
+
+/// Echoes three bytes of input, each one up by one
+const SHIFT: &str = ",+.,+.,+.";
+
+fn run(backend: &str, name: &str, expected: &[u8]) -> (Output, PathBuf) {
+    let prog = source("expect", &format!("{name}{backend}.b"), SHIFT);
+    let expect = source("expect", &format!("{name}{backend}.out"), expected);
+    let args = [backend, "--expect", expect.to_str().unwrap()];
+    (common::run(&args, prog, b"abc"), expect)
+}
+
+fn check(backend: &str) {
+    let (output, _) = run(backend, "match", b"bcd");
+    assert!(output.status.success(), "{backend}");
+    assert_eq!(output.stdout, b"", "{backend}");
+    for (name, expected, error) in [
+        (
+            "differs",
+            &b"bxd"[..],
+            "differs from {} at byte 1, expected 0x78, got 0x63",
+        ),
+        ("short", b"bc", "output goes on past the 2 bytes of {}"),
+        ("long", b"bcde", "output ended at byte 3, {} has 4"),
+    ] {
+        let (output, path) = run(backend, name, expected);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let error = error.replace("{}", &path.display().to_string());
+        assert_eq!(output.status.code(), Some(1), "{name} on {backend}");
+        assert!(stderr.contains(&error), "{name} on {backend}: {stderr}");
+    }
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
@@ -0,0 +1,98 @@
+//! `--ext` adds `:`, printing the cell as a decimal number to stdout, `$`, printing it to
+//! stderr, and `%`, taking the cell modulo the next one. Numbers count byte by byte toward
+//! `--limit-output`.
+
+mod common;
+
+use common::{sbfnj, source};
+
+/// Prints the input byte as a number between two copies of itself on stderr
+const SRC: &str = ",$:$";
+
+fn check(backend: &str) {
+    let prog = source("ext", &format!("num{backend}.b"), SRC);
+    let output = sbfnj(&[backend, "--ext"], prog, &[123]);
+    assert_eq!(output.stdout, b"123", "{backend}");
+    assert_eq!(output.stderr, b"123\n123\n", "{backend}");
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[test]
+fn limit_output() {
+    for backend in ["--o0", "--o1", "--o2"] {
+        let prog = source("ext", &format!("limit{backend}.b"), ",:+:");
+        let args = [backend, "--ext", "--limit-output", "4"];
+        let output = sbfnj(&args, prog, &[123]);
+        assert_eq!(output.stdout, b"1231", "{backend}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("output limit of 4 bytes reached"),
+            "{backend}: {stderr}"
+        );
+    }
+}
+
+#[test]
+fn count_io() {
+    for backend in ["--o0", "--o1", "--o2"] {
+        let prog = source("ext", &format!("count{backend}.b"), ",:");
+        let output = sbfnj(&[backend, "--ext", "--count-io"], prog, &[123]);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("output: 3 bytes; input: 1 ops, 1 bytes"),
+            "{backend}: {stderr}"
+        );
+    }
+}
+
+/// The first input byte modulo the 0 next to it, then modulo the second input byte
+const MOD: &str = ",%:>,<%:";
+
+fn check_mod(backend: &str) {
+    let prog = source("ext", &format!("mod{backend}.b"), MOD);
+    let output = sbfnj(&[backend, "--ext"], prog, &[123, 10]);
+    assert_eq!(output.stdout, b"1233", "{backend}");
+}
+
+#[test]
+fn modulo() {
+    for backend in ["--o0", "--o1", "--o2"] {
+        check_mod(backend);
+    }
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn modulo_llvm() {
+    check_mod("--llvm");
+}
+
+#[test]
+fn modulo_off_tape() {
+    for backend in ["--o0", "--o1", "--o2"] {
+        let prog = source("ext", &format!("edge{backend}.b"), ">%");
+        let output = common::run(&[backend, "--ext", "--max-mem", "2"], prog, b"");
+        assert!(!output.status.success(), "{backend}");
+    }
+}
+
+#[test]
+fn without_ext() {
+    let prog = source("ext", "off.b", ",>,<%.");
+    let output = sbfnj(&["--o2"], prog, &[123, 10]);
+    assert_eq!(output.stdout, [123]);
+}
@@ -0,0 +1,63 @@
+//! `--fix` prints the source without what provably does nothing, leaving comments and
+//! everything else as written, and the result runs the same as the original.
+
+mod common;
+
+use std::fs;
+
+use common::{sbfnj, source};
+
+fn run(name: &str, args: &[&str], src: &[u8], input: &[u8]) -> Vec<u8> {
+    sbfnj(args, source("fix", &format!("{name}.b"), src), input).stdout
+}
+
+fn fix(name: &str, src: &str) -> String {
+    String::from_utf8(run(name, &["--fix"], src.as_bytes(), b"")).unwrap()
+}
+
+#[test]
+fn redundant_clears() {
+    assert_eq!(fix("clears", ",[-][-].[+]"), ",[-].");
+}
+
+#[test]
+fn cancelling_values() {
+    assert_eq!(fix("values", ",++-+-.--++"), ",+.");
+    // cells wrap, so 255 increments are one decrement
+    assert_eq!(fix("wrap", &format!(",{}.", "+".repeat(255))), ",-.");
+}
+
+#[test]
+fn cancelling_moves() {
+    assert_eq!(fix("moves", ",>><<<>.>"), ",.>");
+}
+
+#[test]
+fn unreachable_loops() {
+    // a leading comment loop never runs on the zeroed tape, nor a loop after a loop
+    assert_eq!(
+        fix("comment", "[a comment, with commas.]+[>,.<-][->+<]."),
+        "+[>,.<-]."
+    );
+    // taking a loop out leaves `><` to cancel
+    assert_eq!(fix("between", "+>[<->-]<."), "+.");
+}
+
+#[test]
+fn unchanged() {
+    let src = "read a byte , and echo it back . then loop [ , . ]";
+    assert_eq!(fix("prose", src), src);
+    // a prior loop forgets the tape, so this clear may not be redundant
+    assert_eq!(fix("unknown", ",[>+<-]>[-]"), ",[>+<-]>[-]");
+}
+
+#[test]
+fn same_output() {
+    let src = fs::read("tests/dbfi.b").unwrap();
+    let fixed = run("dbfi", &["--fix"], &src, b"");
+    let input = fs::read("tests/dbfi.in").unwrap();
+    assert_eq!(
+        run("original", &["--o2"], &src, &input),
+        run("fixed", &["--o2"], &fixed, &input)
+    );
+}
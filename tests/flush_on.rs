@@ -0,0 +1,92 @@
+//! `--flush-on newline` gets a prompt ending in a newline to the reader before the
+//! program blocks on input, even with stdout on a pipe. The prompt is read before any
+//! input is written, so without the flush the test times out instead of hanging.
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    process::Stdio,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use common::{command, sbfnj, source};
+
+/// Prints "?\n", then echoes one byte of input
+const PROMPT: &str = "++++++++[>++++++++<-]>-.[-]++++++++++.,.";
+
+fn prompted(backend: &str, flush_on: &str) -> Vec<u8> {
+    let prog = source("flush-on", &format!("prompt{backend}-{flush_on}.b"), PROMPT);
+    let mut child = command()
+        .args([backend, "--flush-on", flush_on])
+        .arg(&prog)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut byte = [0];
+        while stdout.read_exact(&mut byte).is_ok() {
+            if tx.send(byte[0]).is_err() {
+                break;
+            }
+        }
+    });
+    let mut output = Vec::new();
+    while output != b"?\n" {
+        // compiling for --llvm takes a moment
+        match rx.recv_timeout(Duration::from_secs(30)) {
+            Ok(byte) => output.push(byte),
+            Err(_) => {
+                let _ = child.kill();
+                panic!("{backend}: no prompt before input, got {output:?}");
+            }
+        }
+    }
+    child.stdin.take().unwrap().write_all(b"x").unwrap();
+    assert!(child.wait().unwrap().success(), "{backend}");
+    output.extend(rx.iter());
+    output
+}
+
+fn check(backend: &str) {
+    assert_eq!(prompted(backend, "newline"), b"?\nx", "{backend}");
+    assert_eq!(prompted(backend, "byte"), b"?\nx", "{backend}");
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
+
+#[test]
+fn never() {
+    // held back until the end, but all of it arrives
+    let prog = source(
+        "flush-on",
+        "never.b",
+        format!("{PROMPT}{}", ".".repeat(100_000)),
+    );
+    let output = sbfnj(&["--o2", "--flush-on", "never"], prog, b"");
+    assert_eq!(output.stdout.len(), 2 + 1 + 100_000);
+}
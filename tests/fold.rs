@@ -0,0 +1,20 @@
+//! The arithmetic in a loop that I/O keeps from being rewritten whole is still folded, with
+//! the I/O left where it was.
+
+mod common;
+
+use common::{source, text};
+
+#[test]
+fn around_output() {
+    // the leading `,` keeps the loop from being dropped as never entered
+    let prog = source("fold", "out.b", ",[.+>-<+]");
+    assert_eq!(
+        text(&["--o2", "--text", "--opt-passes", "scan"], &prog),
+        "in\nloop {\n  out\n  add val, 1\n  add ptr, 1\n  add val, -1\n  add ptr, -1\n  add val, 1\n}\n"
+    );
+    assert_eq!(
+        text(&["--o2", "--text", "--opt-passes", "fold"], prog),
+        "in\nloop {\n  out\n  add val, 2\n  add ptr, 1\n  add val, -1\n  add ptr, -1\n}\n"
+    );
+}
@@ -0,0 +1,56 @@
+//! `--from-stmt` parses o2 IR as `--o2 --text` prints it, so the listing of an optimized
+//! program reads back to the same statements and runs the same.
+
+mod common;
+
+use std::fs;
+
+use common::{sbfnj, source, text};
+
+/// Every statement kind, in the layout `--text` prints
+const EVERY_STMT: &str = "\
+outconst 72, 105
+in
+add ptr, 1
+add val, -3
+set val, 0
+addmul 1, 2
+addat -1, 4
+setat 2, 7
+mod
+scan -1
+loop {
+  out
+  in
+  dbg
+  outnum
+}
+strided 2 {
+  add val, 1
+  addat 1, 1
+}
+if {
+  set val, 0
+}
+do {
+  add val, -1
+}
+";
+
+#[test]
+fn every_stmt() {
+    let prog = source("from-stmt", "every.o2", EVERY_STMT);
+    assert_eq!(text(&["--o2", "--from-stmt", "--text"], prog), EVERY_STMT);
+}
+
+#[test]
+fn round_trip() {
+    let listing = text(&["--o2", "--text"], "tests/dbfi.b");
+    let prog = source("from-stmt", "dbfi.o2", &listing);
+    assert_eq!(text(&["--o2", "--from-stmt", "--text"], &prog), listing);
+    let input = fs::read("tests/dbfi.in").unwrap();
+    assert_eq!(
+        sbfnj(&["--o2", "--from-stmt"], &prog, &input).stdout,
+        sbfnj(&["--o2"], "tests/dbfi.b", &input).stdout
+    );
+}
@@ -0,0 +1,54 @@
+//! `--grow` reallocs the LLVM tape when the pointer walks past its right end, keeping the
+//! cells already written, so a 16-cell tape holds a program that needs 40000.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+/// Stores its input from cell 1 on, then prints it back reversed
+const REVERSE: &str = ">,[>,]<[.<]";
+
+fn run(name: &str, args: &[&str], src: &str, input: &[u8]) -> Output {
+    let args = [&["--llvm", "--grow"], args].concat();
+    common::run(&args, source("grow", name, src), input)
+}
+
+#[test]
+fn needs_libc() {
+    let output = run(
+        "freestanding.b",
+        &["--text", "--runtime", "freestanding"],
+        REVERSE,
+        b"",
+    );
+    assert!(!output.status.success());
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn walks_far_right() {
+    let input: Vec<u8> = (0..40000).map(|i| b'a' + (i % 26) as u8).collect();
+    for (name, args) in [
+        ("reverse.b", &["--max-mem", "16"][..]),
+        ("outlined.b", &["--max-mem", "16", "--outline-loops"][..]),
+    ] {
+        let output = run(name, args, REVERSE, &input);
+        assert!(output.status.success(), "{name}");
+        let mut expected = input.clone();
+        expected.reverse();
+        assert!(output.stdout == expected, "{name}");
+    }
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn left_edge() {
+    let output = run("left.b", &[], "<+", b"");
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("pointer -1 exceeded tape, it cannot grow there")
+    );
+}
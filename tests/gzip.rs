@@ -0,0 +1,80 @@
+//! Sources compressed with gzip are decompressed before parsing, told apart from plain
+//! ones by their magic bytes. Without the `gzip` feature they are rejected.
+
+mod common;
+
+use std::process::{Command, Output};
+
+use common::{run_with, source};
+
+const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+fn gzip(src: &[u8]) -> Vec<u8> {
+    let output = run_with(Command::new("gzip").arg("-c"), src);
+    assert!(output.status.success());
+    output.stdout
+}
+
+fn run(name: &str, args: &[&str], contents: &[u8]) -> Output {
+    common::run(args, source("gzip", name, contents), b"")
+}
+
+#[cfg(feature = "gzip")]
+fn check(backend: &str) {
+    let output = run(
+        &format!("hello{backend}.b.gz"),
+        &[backend],
+        &gzip(HELLO.as_bytes()),
+    );
+    assert!(output.status.success(), "{backend}");
+    assert_eq!(output.stdout, b"Hello World!\n", "{backend}");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(all(feature = "gzip", feature = "clang"))]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn by_magic() {
+    // the name does not matter either way
+    let output = run("compressed.b", &["--o1"], &gzip(HELLO.as_bytes()));
+    assert_eq!(output.stdout, b"Hello World!\n");
+    let output = run("plain.b.gz", &["--o1"], HELLO.as_bytes());
+    assert_eq!(output.stdout, b"Hello World!\n");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn corrupt() {
+    let mut contents = gzip(HELLO.as_bytes());
+    contents.truncate(12);
+    let output = run("corrupt.b.gz", &["--o0"], &contents);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot decompress"));
+}
+
+#[cfg(not(feature = "gzip"))]
+#[test]
+fn needs_feature() {
+    let output = run("hello.b.gz", &["--o0"], &gzip(HELLO.as_bytes()));
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("gzip feature"));
+    // plain sources are read as before
+    let output = run("plain.b.gz", &["--o0"], HELLO.as_bytes());
+    assert_eq!(output.stdout, b"Hello World!\n");
+}
@@ -0,0 +1,46 @@
+//! Every byte value survives `--hex-input` and `--hex-output` through a cat that counts
+//! its 256 bytes instead of stopping at a zero.
+
+mod common;
+
+use common::{sbfnj, source};
+
+const CAT: &str = "->,.<[>,.<-]";
+
+fn hex() -> String {
+    (0..=255u8).map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn check(backend: &str) {
+    let prog = source("hex-io", &format!("cat{backend}.b"), CAT);
+    // whitespace between pairs is skipped
+    let input = hex().replace("ff", "\nff");
+    let args = [backend, "--hex-input", "--hex-output"];
+    let output = sbfnj(&args, prog, input.as_bytes());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        hex(),
+        "{backend}"
+    );
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
@@ -0,0 +1,28 @@
+//! Loops like `[>+<[-]]` that zero their guard on the way out run at most once, o2
+//! turns them into `if` blocks.
+
+mod common;
+
+use common::{sbfnj, source};
+
+/// Adds one to the next cell if the input is nonzero, for two inputs
+const SRC: &str = ",[>+<[-]]>.<,[>+<[-]]>.";
+
+fn run(name: &str, args: &[&str], input: &str) -> Vec<u8> {
+    sbfnj(args, source("if", name, SRC), input.as_bytes()).stdout
+}
+
+#[test]
+fn text() {
+    let text = String::from_utf8(run("text.b", &["--o2", "--text"], "")).unwrap();
+    assert_eq!(text.matches("if {").count(), 2, "{text}");
+    assert!(!text.contains("loop {"), "{text}");
+}
+
+#[test]
+fn runs_at_most_once() {
+    for input in ["\0\0", "a\0", "ab"] {
+        let expected = run("o0.b", &["--o0"], input);
+        assert_eq!(run("o2.b", &["--o2"], input), expected, "{input:?}");
+    }
+}
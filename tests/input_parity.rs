@@ -0,0 +1,41 @@
+//! `,` stores the byte it reads as is on every backend: 0x00, the sign bit 0x80 and 0xFF
+//! come back unchanged and step to their neighbours as unsigned bytes do.
+
+mod common;
+
+use common::{sbfnj, source};
+
+/// Echoes each of three bytes, then prints it plus one
+const SRC: &str = ",.+.,.+.,.+.";
+
+const INPUT: &[u8] = &[0x00, 0x80, 0xff];
+
+fn run(backend: &str) -> Vec<u8> {
+    let prog = source("input-parity", &format!("bytes{backend}.b"), SRC);
+    sbfnj(&[backend], prog, INPUT).stdout
+}
+
+fn check(backend: &str) {
+    assert_eq!(run(backend), run("--o0"), "{backend}");
+}
+
+#[test]
+fn o0() {
+    assert_eq!(run("--o0"), [0x00, 0x01, 0x80, 0x81, 0xff, 0x00]);
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
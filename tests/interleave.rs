@@ -0,0 +1,65 @@
+//! `--interleave-check` runs every program before and after the o2 passes on the same
+//! random input, so these pass only while no pass reorders, drops or invents I/O.
+
+mod common;
+
+use std::fs;
+
+use common::{run, source};
+
+/// Programs mixing output, input and the `--ext` commands around the loops each pass
+/// rewrites
+fn cases() -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "hello",
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.".to_owned(),
+        ),
+        // a `?` prompt before every read
+        (
+            "prompt",
+            "+++[>+++[>+++++++<-]>.[-]<<-]>,.[-]<,[>.<-]".to_owned(),
+        ),
+        ("cat", ",[.,]".to_owned()),
+        ("mul", ",[->+++<]>.,[>+>+<<-]>>.<.".to_owned()),
+        ("scan", ">+>+>+>,[<]>.>.,".to_owned()),
+        ("strided", "+>>+>>+<<<<.[+>+>],.<<.".to_owned()),
+        ("if", ",[>++.<[-]],[.[-]]".to_owned()),
+        ("ext", ",$+:>,[<$>-]:".to_owned()),
+        ("dbfi", fs::read_to_string("tests/dbfi.b").unwrap()),
+    ]
+}
+
+fn check(flags: &[&str]) {
+    let args = [&["--o2", "--text", "--ext", "--interleave-check"], flags].concat();
+    for (name, src) in cases() {
+        let output = run(&args, source("interleave", &format!("{name}.b"), src), b"");
+        assert!(
+            output.status.success(),
+            "{name} with {flags:?}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn default_passes() {
+    check(&[]);
+}
+
+#[test]
+fn unrolled() {
+    check(&["--unroll", "4"]);
+}
+
+#[test]
+fn other_seeds() {
+    for seed in ["--random-input=1", "--random-input=2"] {
+        check(&[seed]);
+    }
+}
+
+#[test]
+fn unknown_tape() {
+    check(&["--lib"]);
+}
@@ -0,0 +1,33 @@
+//! `--ir-stats` reports the optimized o2 statements' counts and estimated cost, which the
+//! passes are meant to lower.
+
+mod common;
+
+use common::{sbfnj, source};
+
+/// The estimated cost of `src` after the `--opt-passes` given
+fn cost(name: &str, passes: &str, src: &str) -> u64 {
+    let prog = source("ir-stats", &format!("{name}.b"), src);
+    let args = ["--o2", "--text", "--ir-stats", "--opt-passes", passes];
+    let stderr = String::from_utf8(sbfnj(&args, prog, b"").stderr).unwrap();
+    let (_, cost) = stderr.split_once("estimated cost: ").unwrap();
+    cost.trim_end().parse().unwrap()
+}
+
+#[test]
+fn clear_loop() {
+    // `scan` leaves a clear loop alone
+    let src = ",[-].";
+    assert!(cost("set", "set", src) < cost("loop", "scan", src));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json() {
+    let prog = source("ir-stats", "json.b", "+[>,.<-]");
+    let output = sbfnj(&["--o2", "--ir-stats", "--json"], prog, b"");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"stmts\":7,\"loops\":1,\"max_depth\":1,\"cost\":97}\n"
+    );
+}
@@ -0,0 +1,57 @@
+//! `--emit json-ast` prints the optimized o2 statements as one JSON object, loops nesting
+//! their bodies, for tools that read the optimizer's output. Without the `serde` feature
+//! it is rejected.
+
+mod common;
+
+use common::source;
+#[cfg(feature = "serde")]
+use common::text;
+
+#[cfg(feature = "serde")]
+fn json_ast(name: &str, args: &[&str], src: &str) -> String {
+    let args = [&["--emit", "json-ast"], args].concat();
+    text(&args, source("json-ast", &format!("{name}.b"), src))
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn nested() {
+    assert_eq!(
+        json_ast("nested", &["--o2", "--text"], ",[->++<]>[.,]"),
+        concat!(
+            r#"{"schema":"sbfnj-o2-ast/1","stmts":["Input",{"AddMul":[1,2]},{"Set":0},"#,
+            r#"{"PtrInc":1},{"Loop":["Output","Input"]}]}"#,
+            "\n"
+        )
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn output_const() {
+    let json = json_ast("hello", &["--o2", "--text"], "++++++++[>++++++++<-]>+.+.");
+    assert!(
+        json.contains(r#""stmts":[{"OutputConst":[65,66]}]"#),
+        "{json}"
+    );
+}
+
+#[cfg(all(feature = "serde", feature = "clang"))]
+#[test]
+fn llvm() {
+    // the LLVM backend prints the same statements without compiling them
+    let src = ",[->++<]>[.,]";
+    assert_eq!(
+        json_ast("llvm", &["--llvm"], src),
+        json_ast("o2", &["--o2", "--text"], src)
+    );
+}
+
+#[cfg(not(feature = "serde"))]
+#[test]
+fn needs_feature() {
+    let prog = source("json-ast", "plain.b", "+.");
+    let output = common::run(&["--o2", "--text", "--emit", "json-ast"], prog, b"");
+    assert!(!output.status.success());
+}
@@ -0,0 +1,38 @@
+//! o2 refuses programs past `--max-stmts` or `--max-depth` with an error naming the
+//! limit, instead of running out of memory or stack.
+
+mod common;
+
+use common::{run, source};
+
+fn stderr(name: &str, src: &str, args: &[&str]) -> String {
+    let args = [&["--o2"], args].concat();
+    let output = run(&args, source("limits", &format!("{name}.b"), src), b"");
+    assert!(!output.status.success());
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn max_stmts() {
+    let stderr = stderr("stmts", "+>+>+>+", &["--max-stmts", "6"]);
+    assert!(
+        stderr.contains("7 statements, more than --max-stmts 6"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn max_depth() {
+    let stderr = stderr("depth", "+[[[-]]]", &["--max-depth", "2"]);
+    assert!(
+        stderr.contains("3 deep, more than --max-depth 2"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn default_depth() {
+    let src = format!("+{}-{}", "[".repeat(100_000), "]".repeat(100_000));
+    let stderr = stderr("default-depth", &src, &[]);
+    assert!(stderr.contains("more than --max-depth 1000"), "{stderr}");
+}
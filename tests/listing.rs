@@ -0,0 +1,34 @@
+//! `--indent` and `--no-line-numbers` reformat the o1 listing, which keeps its two-space,
+//! numbered layout by default.
+
+mod common;
+
+use common::{source, text};
+
+const SRC: &str = "+[>[-]<-].";
+
+fn listing(args: &[&str]) -> String {
+    let args = [&["--o1", "--text"], args].concat();
+    text(&args, source("listing", "listing.b", SRC))
+}
+
+#[test]
+fn default() {
+    assert_eq!(
+        listing(&[]),
+        " 0  add val, 1\n 1  jz 7\n 2    add ptr, 1\n 3    set 0\n 4    add ptr, -1\n 5    add val, -1\n 6  jnz 2\n 7  out\n"
+    );
+}
+
+#[test]
+fn indent_without_numbers() {
+    assert_eq!(
+        listing(&["--indent", "4", "--no-line-numbers"]),
+        "add val, 1\njz 7\n    add ptr, 1\n    set 0\n    add ptr, -1\n    add val, -1\njnz 2\nout\n"
+    );
+}
+
+#[test]
+fn no_indent() {
+    assert!(listing(&["--indent", "0"]).contains("\n 2  add ptr, 1\n"));
+}
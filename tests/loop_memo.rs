@@ -0,0 +1,44 @@
+//! The o2 interpreter reuses what a pure loop nesting control flow left the last time it
+//! was entered with the same cells, which must print the same as rerunning it.
+
+mod common;
+
+use common::{sbfnj, source};
+
+fn run(name: &str, src: &str, args: &[&str]) -> Vec<u8> {
+    let args = [&["--o2"], args].concat();
+    let prog = source("loop-memo", &format!("{name}.b"), src);
+    // the outer loop runs as many times as this byte says
+    sbfnj(&args, prog, &[4]).stdout
+}
+
+fn check(name: &str, src: &str, expected: &[u8]) {
+    assert_eq!(run(name, src, &[]), expected, "{name}");
+    assert_eq!(run(name, src, &["--no-loop-memo"]), expected, "{name}");
+}
+
+#[test]
+fn same_entry() {
+    // 3 * 5 * 3 added to a cell the outer loop clears after printing it
+    check(
+        "same",
+        ",[>+++[>+++++[>+++[>+<-]<-]<-]>>>.[-]<<<<-]",
+        &[45; 4],
+    );
+}
+
+#[test]
+fn changing_entry() {
+    // the sum stays inside the memoized loop's window, so no entry repeats
+    check(
+        "changing",
+        ",[>+++[>+++++[>+++[>+<-]<-]<-]>>>.<<<<-]",
+        &[45, 90, 135, 180],
+    );
+}
+
+#[test]
+fn impure() {
+    // output in the nested loop, which must happen on every entry
+    check("impure", ",[>++[>+++[>.<-]<-]<-]", &[0; 24]);
+}
@@ -0,0 +1,29 @@
+//! `--mmio` makes the last cell of the o0 tape an I/O port: moving onto it reads a byte
+//! into it, and `+` or `-` on it writes its new value.
+
+mod common;
+
+use common::{run, sbfnj, source};
+
+#[test]
+fn port() {
+    // cell 3 of 4 is the port, it reads each time the pointer arrives
+    let prog = source("mmio", "port.b", ">>>+-<.>.");
+    let output = sbfnj(&["--mmio", "--max-mem", "4"], prog, b"AZ");
+    assert_eq!(output.stdout, b"BA\0Z");
+}
+
+#[test]
+fn other_cells() {
+    // nothing happens off the port
+    let prog = source("mmio", "plain.b", ">>++.");
+    let output = sbfnj(&["--mmio", "--max-mem", "4"], prog, b"A");
+    assert_eq!(output.stdout, [2]);
+}
+
+#[test]
+fn o0_only() {
+    let prog = source("mmio", "o2.b", ">>>+");
+    let output = run(&["--o2", "--mmio", "--max-mem", "4"], prog, b"");
+    assert!(!output.status.success());
+}
@@ -0,0 +1,45 @@
+//! A loop moving its guard to a cell on the left lowers like one moving it right, to an
+//! `AddMul` at a negative offset and the guard clear.
+
+mod common;
+
+use common::source;
+
+/// Runs `src` with itself as input
+fn run(name: &str, args: &[&str], src: &str) -> Vec<u8> {
+    let args = [&["--o2"], args].concat();
+    common::sbfnj(&args, source("move-left", name, src), src.as_bytes()).stdout
+}
+
+fn text(name: &str, src: &str) -> String {
+    // on a fresh tape the whole program would run at compile time
+    String::from_utf8(run(name, &["--text", "--lib"], src)).unwrap()
+}
+
+#[test]
+fn one_left() {
+    assert_eq!(text("one.b", "[<+>-]"), "addmul -1, 1\nset val, 0\n");
+    assert_eq!(
+        text("counting-up.b", "[<+>+]"),
+        "addmul -1, -1\nset val, 0\n"
+    );
+}
+
+#[test]
+fn two_left() {
+    assert_eq!(text("two.b", "[<<+>>-]"), "addmul -2, 1\nset val, 0\n");
+}
+
+#[test]
+fn both_sides() {
+    assert_eq!(
+        text("both.b", "[<<+>>>++<-]"),
+        "addmul -2, 1\naddmul 1, 2\nset val, 0\n"
+    );
+}
+
+#[test]
+fn runs() {
+    // reads its own first byte, '>', and moves it back to cell 0 to print it
+    assert_eq!(run("run.b", &[], ">,[<+>-]<."), b">");
+}
@@ -0,0 +1,51 @@
+//! A multiply loop lowers to `AddMul`s sorted by offset followed by the guard clear,
+//! whatever order the body visits the cells in, so codegen is reproducible.
+
+mod common;
+
+use common::source;
+
+fn text() -> String {
+    let prog = source("mul-order", "mul.b", ">+[->>>+++>+<<<+<<++>]");
+    // on a fresh tape the whole program would run at compile time
+    common::text(&["--o2", "--text", "--lib"], prog)
+}
+
+#[test]
+fn sorted_by_offset() {
+    let text = text();
+    let body: Vec<_> = text.lines().skip(2).collect();
+    assert_eq!(
+        body,
+        [
+            "addmul -1, 2",
+            "addmul 1, 1",
+            "addmul 3, 3",
+            "addmul 4, 1",
+            "set val, 0"
+        ],
+        "{text}"
+    );
+    assert_eq!(text, self::text());
+}
+
+#[test]
+fn one_guard_load() {
+    let prog = source("mul-order", "guard.b", "[->+>++>+++<<<]");
+    let ir = common::text(&["--llvm", "--text", "--lib"], prog);
+    // the guard once and each target once, never one cell twice
+    let loaded: Vec<_> = ir
+        .lines()
+        .filter_map(|line| line.split_once(" = load i8, ptr "))
+        .map(|(_, from)| from)
+        .collect();
+    assert_eq!(loaded.len(), 4, "{ir}");
+    assert!(
+        loaded
+            .iter()
+            .enumerate()
+            .all(|(i, from)| !loaded[..i].contains(from)),
+        "{ir}"
+    );
+    assert_eq!(ir.matches(" = mul i8 ").count(), 3, "{ir}");
+}
@@ -0,0 +1,74 @@
+//! `--multi` links every input into one LLVM module, a function per program named after
+//! its file plus a `main` that runs the one its first argument names.
+
+mod common;
+
+use std::{path::PathBuf, process::Output};
+
+use common::{command, run_with, source};
+
+/// Writes the programs, which the LLVM backend builds next to the first one
+fn sources(test: &str, programs: &[(&str, &str)]) -> Vec<PathBuf> {
+    programs
+        .iter()
+        .map(|(name, src)| source(&format!("multi-{test}"), name, src))
+        .collect()
+}
+
+fn sbfnj(args: &[&str], inputs: &[PathBuf]) -> Output {
+    run_with(
+        command()
+            .args(["--llvm", "--multi"])
+            .args(args)
+            .args(inputs),
+        b"",
+    )
+}
+
+const PROGRAMS: [(&str, &str); 2] = [
+    ("hello.b", "++++++++[>+++++++++<-]>."),
+    ("echo-input.b", ",[.,]"),
+];
+
+#[test]
+fn one_function_each() {
+    let output = sbfnj(&["--text"], &sources("text", &PROGRAMS));
+    assert!(output.status.success());
+    let ir = String::from_utf8(output.stdout).unwrap();
+    for define in ["@bf_hello()", "@bf_echo_input()", "@main(i32"] {
+        assert!(ir.contains(&format!("define i32 {define}")), "{define}");
+    }
+}
+
+#[test]
+fn distinct_names() {
+    let output = sbfnj(
+        &["--text"],
+        &sources("names", &[("a-b.b", "+"), ("a_b.b", "-")]),
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("would both be named bf_a_b"));
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn dispatches_on_argv() {
+    use std::process::Command;
+
+    let inputs = sources("exe", &PROGRAMS);
+    assert!(sbfnj(&[], &inputs).status.success());
+    let exe = inputs[0].with_added_extension("out");
+    let run = |args: &[&str], input: &[u8]| run_with(Command::new(&exe).args(args), input);
+
+    let output = run(&["hello"], b"");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"H");
+    let output = run(&["echo-input"], b"abc");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"abc");
+    for args in [&[][..], &["missing"][..]] {
+        let output = run(args, b"");
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("one of: hello, echo-input"));
+    }
+}
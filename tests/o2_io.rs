@@ -0,0 +1,38 @@
+//! The o2 interpreter reads `,` and writes `.` through whatever it is handed: the REPL
+//! shares stdin between its lines and the program, --runs restarts a random stream that
+//! --repeat reads on from.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+fn sbfnj(name: &str, args: &[&str], src: &str, input: &[u8]) -> Output {
+    common::sbfnj(args, source("o2-io", &format!("{name}.b"), src), input)
+}
+
+#[test]
+fn repl() {
+    // the program's reads take the line after it, the REPL the one after that
+    let output = sbfnj("repl", &["--repl"], "", b"+++[>,.<-]\nabc\n>.\n");
+    assert_eq!(output.stdout, b"abcc");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("ptr 1: 0 [99]"), "{stderr}");
+}
+
+#[test]
+fn random_runs() {
+    let args = ["--o2", "--deterministic", "--random-input=5"];
+    let runs = sbfnj("runs", &[&args[..], &["--runs", "3"]].concat(), ",.,.", b"");
+    assert_eq!(runs.stdout.len(), 6);
+    assert!(runs.stdout.chunks(2).all(|run| run == &runs.stdout[..2]));
+    let repeat = sbfnj(
+        "repeat",
+        &[&args[..], &["--repeat", "3"]].concat(),
+        ",.,.",
+        b"",
+    );
+    assert_eq!(repeat.stdout[..2], runs.stdout[..2]);
+    assert_ne!(repeat.stdout[2..4], runs.stdout[..2]);
+}
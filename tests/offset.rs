@@ -0,0 +1,14 @@
+//! Arithmetic between pointer moves that cancel out is addressed relative to the pointer,
+//! so both moves disappear.
+
+mod common;
+
+use common::{source, text};
+
+#[test]
+fn pointer_moves_cancel() {
+    let prog = source("offset", "offset.b", ">+++>>[-]<<<");
+    // on a fresh tape the whole program would run at compile time
+    let text = text(&["--o2", "--text", "--lib"], prog);
+    assert_eq!(text, "addat 1, 3\nsetat 3, 0\n");
+}
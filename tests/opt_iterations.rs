@@ -0,0 +1,31 @@
+//! Unrolling runs after folding, so only a second round of the o2 pipeline folds the
+//! unrolled copies into their neighbours.
+
+mod common;
+
+use common::{source, text};
+
+fn text_lines(iterations: &str) -> usize {
+    let prog = source(
+        "opt-iterations",
+        &format!("unroll-{iterations}.b"),
+        "++[>.<-]",
+    );
+    let args = [
+        "--o2",
+        "--text",
+        "--unroll",
+        "2",
+        "--opt-iterations",
+        iterations,
+        // without the output pass, which would run the whole program
+        "--opt-passes",
+        "set,mul,scan,strided,dead,fold,offset,unroll,if",
+    ];
+    text(&args, prog).lines().count()
+}
+
+#[test]
+fn second_iteration_shrinks() {
+    assert!(text_lines("2") < text_lines("1"));
+}
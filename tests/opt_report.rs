@@ -0,0 +1,68 @@
+//! `--opt-report` compares the o2 statements before and after optimizing and names the
+//! passes that changed them.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+/// Prints H, clears the cell and echoes its input
+const SRC: &str = "++++++++[>+++++++++<-]>.[-]>>,[.,]";
+
+fn sbfnj(name: &str, args: &[&str]) -> Output {
+    common::run(args, source("opt-report", &format!("{name}.b"), SRC), b"")
+}
+
+#[test]
+fn table() {
+    let output = sbfnj("table", &["--o2", "--opt-report"]);
+    assert!(output.status.success());
+    // the report goes to stderr, the program still runs
+    assert_eq!(output.stdout, b"H");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("stmts             15         6     60.0%"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("passes fired: set x1, mul x1, output x1"),
+        "{stderr}"
+    );
+
+    let output = sbfnj("none", &["--o2", "--opt-report", "--opt-passes", "dead"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("passes fired: none"), "{stderr}");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json() {
+    let output = sbfnj("json", &["--o2", "--opt-report", "--json"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        concat!(
+            r#"{"before":{"stmts":15,"loops":3,"max_depth":1,"cost":168},"#,
+            r#""after":{"stmts":6,"loops":1,"max_depth":1,"cost":52},"#,
+            r#""reduction":{"stmts":60.0,"loops":66.7,"max_depth":0.0,"cost":69.0},"#,
+            r#""fired":{"set":1,"mul":1,"output":1}}"#,
+            "\n"
+        )
+    );
+}
+
+#[cfg(not(feature = "serde"))]
+#[test]
+fn needs_feature() {
+    let output = sbfnj("plain", &["--o2", "--opt-report", "--json"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("the serde feature"));
+}
+
+#[test]
+fn needs_o2() {
+    let output = sbfnj("o1", &["--o1", "--opt-report"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--opt-report needs --o2"));
+}
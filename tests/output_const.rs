@@ -0,0 +1,93 @@
+//! The `output` pass runs the input-free start of a program at compile time, so the
+//! canonical Hello World lowers to a single `outconst` and one write.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+fn run(name: &str, args: &[&str], src: &str, input: &[u8]) -> Output {
+    common::run(args, source("output-const", name, src), input)
+}
+
+fn text(name: &str, src: &str) -> String {
+    common::text(&["--o2", "--text"], source("output-const", name, src))
+}
+
+#[test]
+fn hello_world() {
+    assert_eq!(
+        text("text.b", HELLO),
+        "outconst 72, 101, 108, 108, 111, 32, 87, 111, 114, 108, 100, 33, 10\n"
+    );
+}
+
+#[test]
+fn stops_at_input() {
+    // the tape the prefix leaves behind is rebuilt before the read
+    assert_eq!(
+        text("input.b", "+++[>+++++<-]>.,."),
+        "outconst 15\nsetat 1, 15\nadd ptr, 1\nin\nout\n"
+    );
+}
+
+#[test]
+fn limit_output() {
+    let output = run("limit.b", &["--o2", "--limit-output", "5"], HELLO, b"");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Hello");
+}
+
+/// Hello World as a banner, then echoes its input
+fn banner() -> String {
+    format!("{HELLO},[.,]")
+}
+
+#[test]
+fn banner_text() {
+    // only the echo loop is left to interpret
+    assert!(
+        text("banner.b", &banner())
+            .starts_with("outconst 72, 101, 108, 108, 111, 32, 87, 111, 114, 108, 100, 33, 10\n")
+    );
+    assert!(text("banner.b", &banner()).ends_with("in\nloop {\n  out\n  in\n}\n"));
+}
+
+fn check(backend: &str) {
+    let output = run(&format!("run{backend}.b"), &[backend], HELLO, b"");
+    assert!(output.status.success(), "{backend}");
+    assert_eq!(output.stdout, b"Hello World!\n", "{backend}");
+
+    let output = run(
+        &format!("banner{backend}.b"),
+        &[backend],
+        &banner(),
+        b"echo",
+    );
+    assert!(output.status.success(), "{backend}");
+    assert_eq!(output.stdout, b"Hello World!\necho", "{backend}");
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
@@ -0,0 +1,79 @@
+//! `--halt-on-overflow` reports a pointer move that overflows the pointer type itself,
+//! rather than the wrapped pointer it lands on.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+/// A move of `i32::MAX` then one more, past what the LLVM pointer holds
+const NEAR_MAX: &str = "add ptr, 2147483647\nadd ptr, 1\nout\n";
+
+fn run(name: &str, args: &[&str], src: &str) -> Output {
+    common::run(args, source("overflow", name, src), b"")
+}
+
+fn failure(name: &str, args: &[&str], src: &str) -> String {
+    let output = run(name, args, src);
+    assert!(!output.status.success(), "{name}");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn interpreters() {
+    for backend in ["--o0", "--o1", "--o2"] {
+        let stderr = failure("left.b", &[backend, "--halt-on-overflow"], "<");
+        assert!(
+            stderr.contains("pointer 0 overflowed moving by -1"),
+            "{backend}: {stderr}"
+        );
+        // wrapping by default, which only leaves the tape
+        let stderr = failure("left.b", &[backend], "<");
+        assert!(
+            stderr.contains("pointer -1 exceeded tape"),
+            "{backend}: {stderr}"
+        );
+    }
+    // a usize cannot overflow from a move off a 30000 cell tape
+    let stderr = failure(
+        "near_max.o2",
+        &["--o2", "--from-stmt", "--halt-on-overflow"],
+        NEAR_MAX,
+    );
+    assert!(
+        stderr.contains("pointer 2147483647 exceeded tape"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn freestanding() {
+    let args = ["--llvm", "--runtime", "freestanding", "--halt-on-overflow"];
+    let stderr = failure("freestanding.b", &args, "<");
+    assert!(
+        stderr.contains("--halt-on-overflow needs --runtime libc"),
+        "{stderr}"
+    );
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    let args = ["--llvm", "--from-stmt", "--halt-on-overflow"];
+    let stderr = failure("near_max.o2", &args, NEAR_MAX);
+    assert!(
+        stderr.contains("pointer 2147483647 overflowed moving by 1"),
+        "{stderr}"
+    );
+    // the grown tape sees the wrapped pointer otherwise
+    let stderr = failure(
+        "near_max_grow.o2",
+        &["--llvm", "--from-stmt", "--grow"],
+        NEAR_MAX,
+    );
+    assert!(
+        stderr.contains("pointer -2147483648 exceeded tape"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,48 @@
+//! The o1 peephole pass turns a clear loop into one `SetZero`, printed as `set 0`, and
+//! I/O between pointer moves that cancel into one offset-addressed `outat` or `inat`.
+
+mod common;
+
+use common::source;
+
+/// Runs `src` with itself as input
+fn run(name: &str, args: &[&str], src: &str) -> Vec<u8> {
+    let args = [&["--o1"], args].concat();
+    common::sbfnj(&args, source("peephole", name, src), src.as_bytes()).stdout
+}
+
+fn text(name: &str, src: &str) -> String {
+    String::from_utf8(run(name, &["--text"], src)).unwrap()
+}
+
+#[test]
+fn clear_loop() {
+    assert_eq!(text("clear.b", "[-]"), " 0  set 0\n");
+}
+
+#[test]
+fn offset_io() {
+    assert_eq!(text("out.b", ">.<"), " 0  outat 1\n");
+    assert_eq!(text("in.b", "<<,>>"), " 0  inat -2\n");
+    // the moves don't cancel, so they stay
+    assert_eq!(
+        text("uneven.b", ">.<<"),
+        " 0  add ptr, 1\n 1  out\n 2  add ptr, -2\n"
+    );
+}
+
+#[test]
+fn loop_targets() {
+    assert_eq!(
+        text("loop.b", "+[>.<-]"),
+        " 0  add val, 1\n 1  jz 5\n 2    outat 1\n 3    add val, -1\n 4  jnz 2\n"
+    );
+}
+
+#[test]
+fn runs() {
+    // the pointer stays on cell 0 across the output, which the last '.' prints
+    assert_eq!(run("run.b", &[], "+>++.<."), [2, 1]);
+    // reads the source's first byte into cell 1 and prints it from there
+    assert_eq!(run("read.b", &[], ">,<+>.<-"), b">");
+}
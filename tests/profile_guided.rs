@@ -0,0 +1,72 @@
+//! `--profile-guided` runs the program on the o1 interpreter first and only unrolls the
+//! loops that ran hot there.
+
+mod common;
+
+use common::source;
+
+/// An inner loop run once per input count, then the same loop once at the end
+const SRC: &str = ",[>[-]+++[>.+<-]<-]>>>>[-]+++[>.+<-]";
+
+fn sbfnj(name: &str, args: &[&str], input: &[u8]) -> Vec<u8> {
+    let args = [&["--o2", "--unroll", "8"], args].concat();
+    common::sbfnj(&args, source("profile-guided", name, SRC), input).stdout
+}
+
+/// Loops left in the --text listing, a rotated one counts too
+fn loops(text: &[u8]) -> usize {
+    let text = String::from_utf8(text.to_vec()).unwrap();
+    text.lines()
+        .filter(|line| line.ends_with("loop {") || line.ends_with("do {"))
+        .count()
+}
+
+#[test]
+fn hot_only() {
+    // both are unrolled without a profile, leaving only the outer loop
+    assert_eq!(loops(&sbfnj("plain.b", &["--text"], b"")), 1);
+
+    let input = source("profile-guided", "input", [100]);
+    let input = input.to_str().unwrap();
+    let args = ["--text", "--profile-guided", "--profile-input", input];
+    let text = String::from_utf8(sbfnj("hot.b", &args, b"")).unwrap();
+    // the inner loop ran 300 times and is unrolled, the last one ran 3 times and is not
+    let (outer, tail) = text.split_once("\n}\n").unwrap();
+    assert_eq!(loops(outer.as_bytes()), 1, "{text}");
+    assert_eq!(loops(tail.as_bytes()), 1, "{text}");
+
+    // at EOF the outer loop never runs, so the last loop is the hot one
+    let text = String::from_utf8(sbfnj("eof.b", &["--text", "--profile-guided"], b"")).unwrap();
+    let (outer, tail) = text.split_once("\n}\n").unwrap();
+    assert_eq!(loops(outer.as_bytes()), 2, "{text}");
+    assert_eq!(loops(tail.as_bytes()), 0, "{text}");
+}
+
+#[test]
+fn same_output() {
+    let expected = sbfnj("run.b", &[], &[2]);
+    assert_eq!(expected, [0, 1, 2, 3, 4, 5, 0, 1, 2]);
+    assert_eq!(sbfnj("run_pgo.b", &["--profile-guided"], &[2]), expected);
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn cache_key() {
+    // the same path with other contents profiles other loops as hot
+    let prog = source("profile-guided", "cached.b", SRC);
+    let cache = prog.with_added_extension("sbfnj-cache");
+    let input = source("profile-guided", "cached.in", [100]);
+    let args = [
+        "--llvm",
+        "--unroll",
+        "8",
+        "--profile-guided",
+        "--profile-input",
+        input.to_str().unwrap(),
+    ];
+    common::sbfnj(&args, &prog, b"");
+    let hot = std::fs::read(&cache).unwrap();
+    source("profile-guided", "cached.in", [0]);
+    common::sbfnj(&args, &prog, b"");
+    assert_ne!(std::fs::read(&cache).unwrap(), hot);
+}
@@ -0,0 +1,45 @@
+//! `--raw-io` writes interpreter output with `write(2)` directly, the bytes must match the
+//! buffered stdout. Without the `raw-io` feature the flag is rejected.
+
+mod common;
+
+use common::source;
+
+/// Prints every byte value counting up from 0, stopping when the cell wraps
+const SRC: &str = ".+[.+]";
+
+fn run(args: &[&str]) -> std::process::Output {
+    common::run(args, source("raw-io", "count.b", SRC), b"")
+}
+
+#[cfg(feature = "raw-io")]
+fn check(backend: &str) {
+    let output = run(&[backend, "--raw-io"]);
+    assert!(output.status.success(), "{backend}");
+    assert_eq!(output.stdout, (0..=255).collect::<Vec<u8>>(), "{backend}");
+}
+
+#[cfg(feature = "raw-io")]
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[cfg(feature = "raw-io")]
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[cfg(feature = "raw-io")]
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(not(feature = "raw-io"))]
+#[test]
+fn needs_feature() {
+    let output = run(&["--o2", "--raw-io"]);
+    assert!(!output.status.success());
+}
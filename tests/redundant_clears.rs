@@ -0,0 +1,44 @@
+//! `--warn-redundant-clears` points at clear loops like `[-]` entered on a cell that is
+//! already known to be zero, such as one right after another.
+
+mod common;
+
+use common::{sbfnj, source};
+
+fn warnings(name: &str, src: &str) -> String {
+    let prog = source("redundant-clears", &format!("{name}.b"), src);
+    let output = sbfnj(&["--o2", "--text", "--warn-redundant-clears"], prog, b"");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+const WARNING: &str = "is redundant, the cell is already zero";
+
+#[test]
+fn double() {
+    let stderr = warnings("double", ",[-][-]");
+    assert_eq!(stderr, format!("warning: clear at 1:5 {WARNING}\n"));
+    // the tape starts zeroed
+    assert!(warnings("start", "\n[+]").contains(&format!("clear at 2:1 {WARNING}")));
+}
+
+#[test]
+fn needed() {
+    assert_eq!(warnings("input", ",[-]"), "");
+    // the loop leaves the cell to its right unknown
+    assert_eq!(warnings("other", ",[>+<-]>[-]"), "");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn cached() {
+    // a cache hit still runs the lints
+    let prog = source("redundant-clears", "cached.b", ",[-][-]");
+    for _ in 0..2 {
+        let output = sbfnj(&["--llvm", "--warn-redundant-clears"], &prog, b"\x01");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!("clear at 1:5 {WARNING}")),
+            "{stderr}"
+        );
+    }
+}
@@ -0,0 +1,34 @@
+//! `--repeat` reruns a program on one input stream, clearing the tape between runs
+//! unless `--keep-tape` is given.
+
+mod common;
+
+use common::{sbfnj, source};
+
+fn run(name: &str, src: &str, args: &[&str], input: &[u8]) -> Vec<u8> {
+    let args = [&["--o2"], args].concat();
+    sbfnj(&args, source("repeat", &format!("{name}.b"), src), input).stdout
+}
+
+#[test]
+fn one_byte_per_run() {
+    assert_eq!(run("cat", ",.", &["--repeat", "3"], b"abcd"), b"abc");
+}
+
+#[test]
+fn keep_tape() {
+    assert_eq!(run("clear", "+.", &["--repeat", "3"], b""), [1, 1, 1]);
+    assert_eq!(
+        run("keep", "+.", &["--repeat", "3", "--keep-tape"], b""),
+        [1, 2, 3]
+    );
+}
+
+#[test]
+fn zero() {
+    for flag in ["--runs", "--repeat"] {
+        let prog = source("repeat", "zero.b", "+.");
+        let output = common::run(&["--o2", flag, "0"], prog, b"");
+        assert!(!output.status.success(), "{flag}");
+    }
+}
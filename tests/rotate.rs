@@ -0,0 +1,58 @@
+//! A loop entered on a cell known to be nonzero is rotated into a `do` loop that only
+//! checks its guard after each iteration, which must run the same as the plain loop.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+/// Reads and echoes five bytes, the guard is set to 5 right before the loop
+const SRC: &str = "+++++[>,.<-]>.";
+
+fn sbfnj(name: &str, args: &[&str], src: &str, input: &str) -> Output {
+    common::sbfnj(args, source("rotate", name, src), input.as_bytes())
+}
+
+fn text(name: &str, args: &[&str], src: &str) -> String {
+    let args = [&["--o2", "--text"], args].concat();
+    String::from_utf8(sbfnj(name, &args, src, "").stdout).unwrap()
+}
+
+#[test]
+fn rotated() {
+    assert!(text("text.b", &[], SRC).contains("do {"));
+    // the input may be zero
+    assert!(!text("input.b", &[], ",[>,.<-]").contains("do {"));
+    // --lib starts on an unknown tape, unless the guard is set before the loop
+    assert!(!text("lib.b", &["--lib"], SRC).contains("do {"));
+    assert!(text("lib_set.b", &["--lib"], &format!("[-]{SRC}")).contains("do {"));
+}
+
+#[test]
+fn from_stmt() {
+    let stmts = text("stmts.b", &[], SRC);
+    let output = sbfnj("stmts.o2", &["--o2", "--from-stmt"], &stmts, "abcde");
+    assert_eq!(output.stdout, b"abcdee");
+}
+
+fn check(backend: &str) {
+    let args = [backend];
+    let name = format!("run{backend}.b");
+    assert_eq!(
+        sbfnj(&name, &args, SRC, "abcde").stdout,
+        b"abcdee",
+        "{backend}"
+    );
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
@@ -0,0 +1,40 @@
+//! Run Daniel B. Cristofani's dbfi, a Brainfuck interpreter written in Brainfuck, on
+//! every backend. `dbfi.in` is a hello world followed by a cat, then `!` and the input
+//! of the inner program.
+
+mod common;
+
+use std::path::Path;
+
+use common::sbfnj;
+
+const DBFI: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/dbfi.b");
+const INPUT: &[u8] = include_bytes!("dbfi.in");
+const EXPECTED: &[u8] = b"Hello World!\nsbfnj";
+
+fn run(backend: &str, prog: &Path) -> Vec<u8> {
+    sbfnj(&[backend], prog, INPUT).stdout
+}
+
+#[test]
+fn o0() {
+    assert_eq!(run("--o0", Path::new(DBFI)), EXPECTED);
+}
+
+#[test]
+fn o1() {
+    assert_eq!(run("--o1", Path::new(DBFI)), EXPECTED);
+}
+
+#[test]
+fn o2() {
+    assert_eq!(run("--o2", Path::new(DBFI)), EXPECTED);
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    // built from a copy, so the .ll and executable stay out of the source tree
+    let prog = common::source("self-hosting", "dbfi.b", std::fs::read(DBFI).unwrap());
+    assert_eq!(run("--llvm", &prog), EXPECTED);
+}
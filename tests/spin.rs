@@ -0,0 +1,45 @@
+//! Once input is at EOF, a loop whose passes leave the tape, the pointer and the output
+//! unchanged can never exit, so the interpreters stop it with a warning instead of
+//! spinning forever.
+
+mod common;
+
+use common::{sbfnj, source};
+
+const WARNING: &str = "stopped a loop that cannot exit";
+
+fn run(backend: &str, name: &str, src: &str, extra: &[&str]) -> (Vec<u8>, String) {
+    let args = [&[backend], extra].concat();
+    let output = sbfnj(&args, source("spin", &format!("{name}.b"), src), b"");
+    (output.stdout, String::from_utf8(output.stderr).unwrap())
+}
+
+fn check(backend: &str) {
+    // a read every pass, but EOF only ever gives 0 and `+` makes it 1
+    let (stdout, stderr) = run(backend, "reread", ",+[,+]", &[]);
+    assert_eq!(stdout, b"");
+    assert!(stderr.contains(WARNING), "{backend}: {stderr}");
+
+    let (_, stderr) = run(backend, "exits", ",[.]>+++[<+>-]<.", &[]);
+    assert!(!stderr.contains(WARNING), "{backend}: {stderr}");
+
+    // printing counts as progress, only the output limit stops this one
+    let (stdout, stderr) = run(backend, "prints", ",+[.]", &["--limit-output", "5"]);
+    assert_eq!(stdout, [1; 5]);
+    assert!(!stderr.contains(WARNING), "{backend}: {stderr}");
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
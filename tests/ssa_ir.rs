@@ -0,0 +1,94 @@
+//! `--emit ssa-ir` lists the pointer and cell values the LLVM backend threads through the
+//! program, a new `%ptr` for every pointer update and a phi where control flow joins.
+
+mod common;
+
+use std::fs;
+
+use common::source;
+
+fn sbfnj(name: &str, args: &[&str], src: &str) -> String {
+    let args = [&["--llvm", "--emit", "ssa-ir"], args].concat();
+    common::text(&args, source("ssa-ir", name, src))
+}
+
+const LOOP: &str = "\
+main:
+  %ptr0 = 0
+  %v1 = in
+  store [%ptr0], %v1
+loop1:
+  %ptr1 = phi %ptr0, %ptr1
+  %v2 = load [%ptr1]
+  br %v2, loop1.body, loop1.end
+loop1.body:
+  %v3 = load [%ptr1]
+  out %v3
+  %v4 = load [%ptr1]
+  %v5 = add %v4, -1
+  store [%ptr1], %v5
+  br loop1
+loop1.end:
+  %ptr2 = add %ptr1, 1
+  %v6 = load [%ptr2]
+  %v7 = add %v6, 1
+  store [%ptr2], %v7
+  ret 0
+";
+
+#[test]
+fn text() {
+    assert_eq!(sbfnj("loop.b", &["--text"], ",[.-]>+"), LOOP);
+    let lib = sbfnj("lib.b", &["--text", "--lib"], ",[.-]>+");
+    assert!(
+        lib.starts_with("bf_run:\n") && lib.ends_with("  ret %ptr2\n"),
+        "{lib}"
+    );
+}
+
+#[test]
+fn rotated() {
+    let ssa = sbfnj("do.b", &["--text"], "+++++[>,.<-]");
+    // the pointer after the loop is the one the test at the bottom saw
+    assert!(ssa.contains("do1:\n  %ptr1 = phi %ptr0, %ptr3\n"), "{ssa}");
+    assert!(
+        ssa.contains("  br %v7, do1, do1.end\ndo1.end:\n  ret 0\n"),
+        "{ssa}"
+    );
+}
+
+#[test]
+fn outlined() {
+    let ssa = sbfnj("outlined.b", &["--text", "--outline-loops"], ",[.-]>+");
+    assert!(ssa.contains("  %ptr1 = call loop.0(%ptr0)\n"), "{ssa}");
+    assert!(ssa.contains("\nloop.0(%ptr0):\nloop1:\n"), "{ssa}");
+}
+
+#[test]
+fn file() {
+    assert_eq!(sbfnj("file.b", &[], ",[.-]>+"), "");
+    let path = std::env::temp_dir().join("sbfnj-ssa-ir").join("file.b.ssa");
+    assert_eq!(fs::read_to_string(path).unwrap(), LOOP);
+}
+
+#[test]
+fn add_muls_share_guard() {
+    let ssa = sbfnj("mul.b", &["--text", "--lib"], "[->+>++>+++<<<]");
+    // the guard is loaded once for the three multiplies
+    assert_eq!(ssa.matches(" = load [%ptr0]\n").count(), 1, "{ssa}");
+    assert_eq!(ssa.matches(" = mul ").count(), 3, "{ssa}");
+}
+
+#[test]
+fn modulo() {
+    let ssa = sbfnj("mod.b", &["--text", "--ext"], ",%.");
+    assert!(
+        ssa.contains(concat!(
+            "  %v2 = load [%ptr0]\n",
+            "  %v3 = load [%ptr0 + 1]\n",
+            "  %v4 = mod %v2, %v3\n",
+            "  store [%ptr0], %v4\n",
+        )),
+        "{ssa}"
+    );
+}
@@ -0,0 +1,68 @@
+//! `--max-steps` with `--save-state` checkpoints the o0 interpreter, and `--load-state`
+//! resumes it so the two runs together print what one uninterrupted run does.
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    process::Output,
+};
+
+use common::source;
+
+/// Prints ABC, echoes its input, then a C again
+const SRC: &str = "++++++++[>++++++++<-]>+.+.+.>,[.,]<.";
+
+const INPUT: &str = "hello\n";
+
+fn dir() -> PathBuf {
+    std::env::temp_dir().join("sbfnj-state")
+}
+
+fn sbfnj(prog: &Path, args: &[&str]) -> Output {
+    common::run(args, prog, INPUT.as_bytes())
+}
+
+fn program(name: &str, src: &str) -> PathBuf {
+    source("state", name, src)
+}
+
+#[test]
+fn resume() {
+    let prog = program("resume.b", SRC);
+    let whole = sbfnj(&prog, &[]).stdout;
+    assert_eq!(whole, b"ABChello\nC");
+    // in the multiply loop, before the first output and partway through the input
+    for steps in [10, 60, 130] {
+        let state = dir().join(format!("resume-{steps}.state"));
+        let state = state.to_str().unwrap();
+        let first = sbfnj(
+            &prog,
+            &["--max-steps", &steps.to_string(), "--save-state", state],
+        );
+        assert!(first.status.success());
+        let stderr = String::from_utf8(first.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!("stopped after {steps} steps")),
+            "{stderr}"
+        );
+        let rest = sbfnj(&prog, &["--load-state", state]);
+        assert!(rest.status.success());
+        assert_eq!([first.stdout, rest.stdout].concat(), whole, "{steps}");
+    }
+}
+
+#[test]
+fn other_program() {
+    let state = dir().join("other.state");
+    let state = state.to_str().unwrap();
+    let prog = program("other.b", SRC);
+    assert!(
+        sbfnj(&prog, &["--max-steps", "5", "--save-state", state])
+            .status
+            .success()
+    );
+    let output = sbfnj(&program("other2.b", "+."), &["--load-state", state]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("saved from another program"));
+}
@@ -0,0 +1,72 @@
+//! Pure loops that move the pointer by a fixed stride while adding constants, like
+//! `[+>+>]`, become `strided` loops in o2 and run the same on every backend.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+/// Marks every other cell, bumps each mark and its right neighbour in one strided loop,
+/// then prints the first eight cells: 2 1 2 1 2 1 2 1
+const SRC: &str = "+>>+>>+>>+<<<<<<[+>+>]<<<<<<<<.>.>.>.>.>.>.>.";
+
+fn sbfnj(name: &str, args: &[&str], src: &str) -> Output {
+    common::run(args, source("strided", name, src), b"")
+}
+
+fn check(backend: &str) {
+    let output = sbfnj(&format!("run{backend}.b"), &[backend], SRC);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [2, 1, 2, 1, 2, 1, 2, 1], "{backend}");
+}
+
+#[test]
+fn text() {
+    // on a fresh tape the whole program would run at compile time
+    let output = sbfnj("text.b", &["--o2", "--text", "--lib"], SRC);
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        text.contains("strided 2 {\n  add val, 1\n  addat 1, 1\n}"),
+        "{text}"
+    );
+    assert!(!text.contains("loop {"), "{text}");
+}
+
+#[test]
+fn io_stays_a_loop() {
+    let output = sbfnj("io.b", &["--o2", "--text"], ",[.>+>]");
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(!text.contains("strided"), "{text}");
+}
+
+#[test]
+fn from_stmt() {
+    let text = sbfnj("text.b", &["--o2", "--text"], SRC).stdout;
+    let text = String::from_utf8(text).unwrap();
+    let output = sbfnj("from.stmt", &["--o2", "--from-stmt"], &text);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [2, 1, 2, 1, 2, 1, 2, 1]);
+    let output = sbfnj(
+        "bad.stmt",
+        &["--o2", "--from-stmt"],
+        "strided 1 {\n  out\n}\n",
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
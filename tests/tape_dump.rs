@@ -0,0 +1,66 @@
+//! `--tape-dump-on-error` prints the cells around the pointer when a move leaves the tape,
+//! with where the program was in the backends that track it.
+
+mod common;
+
+use common::{run, source};
+
+const OFF_THE_END: &str = "+>++>+++>";
+
+fn stderr(backend: &str, args: &[&str]) -> String {
+    let prog = source("tape-dump", &format!("off{backend}.b"), OFF_THE_END);
+    let args = [&[backend, "--max-mem", "3"], args].concat();
+    let output = run(&args, prog, b"");
+    assert!(!output.status.success(), "{backend}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("pointer 3 exceeded tape 3"),
+        "{backend}: {stderr}"
+    );
+    stderr
+}
+
+#[test]
+fn o0() {
+    let stderr = stderr("--o0", &["--tape-dump-on-error"]);
+    assert!(
+        stderr.contains("tape at source byte 8, ptr 2: 1 2 [3]\n"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn o1() {
+    let stderr = stderr("--o1", &["--tape-dump-on-error"]);
+    assert!(
+        stderr.contains("tape at o1 line 5, ptr 2: 1 2 [3]\n"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn o2() {
+    // the adds before the move are offset-addressed, so the pointer fails from cell 0
+    let stderr = stderr("--o2", &["--tape-dump-on-error"]);
+    assert!(stderr.contains("tape, ptr 0: [1] 2 3\n"), "{stderr}");
+}
+
+#[test]
+fn off_by_default() {
+    for backend in ["--o0", "--o1", "--o2"] {
+        let stderr = stderr(backend, &[]);
+        assert!(!stderr.contains("ptr "), "{backend}: {stderr}");
+    }
+}
+
+#[test]
+fn modulo() {
+    // `%` on the last cell reads past the end without moving the pointer
+    let prog = source("tape-dump", "mod.b", "++>+++%");
+    let args = ["--o0", "--max-mem", "2", "--ext", "--tape-dump-on-error"];
+    let stderr = String::from_utf8(run(&args, prog, b"").stderr).unwrap();
+    assert!(
+        stderr.contains("tape at source byte 6, ptr 1: 2 [3]\n"),
+        "{stderr}"
+    );
+}
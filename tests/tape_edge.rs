@@ -0,0 +1,89 @@
+//! Moving off either end of the tape: `--safe` stops every backend with the same error,
+//! `--wrap` makes the tape circular so the move comes back at the other end.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+fn sbfnj(backend: &str, name: &str, args: &[&str], src: &str) -> Output {
+    let prog = source("tape-edge", &format!("{name}{backend}.b"), src);
+    common::run(&[&[backend], args].concat(), prog, b"")
+}
+
+fn check(backend: &str) {
+    let safe = ["--safe", "--max-mem", "4"];
+    for (name, src, error) in [
+        ("left", "+<.", "pointer -1 exceeded tape 4"),
+        ("right", ">>>+>.", "pointer 4 exceeded tape 4"),
+        ("scan", ">+>+>+[>].", "pointer 4 exceeded tape 4"),
+    ] {
+        let output = sbfnj(backend, name, &safe, src);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(!output.status.success(), "{name} on {backend}");
+        assert!(stderr.contains(error), "{name} on {backend}: {stderr}");
+    }
+    let output = sbfnj(backend, "inside", &safe, ">>>+.<<<.");
+    assert!(output.status.success(), "{backend}");
+    assert_eq!(output.stdout, [1, 0], "{backend}");
+
+    let wrap = ["--wrap", "--max-mem", "4"];
+    for (name, src, expected) in [
+        // the last cell is one to the left of the first
+        ("left", "<+++.>>>>.", &[3, 3][..]),
+        ("right", ">>>>++.<.", &[2, 0]),
+        ("far", "+<<<<<<<<.", &[1]),
+        // scans run round the tape to the empty cell
+        ("scan", ">+>+>++[>]<.>>+[<]>.", &[2, 2]),
+    ] {
+        let output = sbfnj(backend, name, &wrap, src);
+        assert!(output.status.success(), "{name} on {backend}");
+        assert_eq!(output.stdout, expected, "{name} on {backend}");
+    }
+    // `%` on the last cell divides by the first
+    let output = sbfnj(
+        backend,
+        "modulo",
+        &[&wrap[..], &["--ext"]].concat(),
+        "+++>>>+++++++%.",
+    );
+    assert_eq!(output.stdout, [1], "{backend}");
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
+
+#[test]
+fn rejected() {
+    let output = sbfnj("--llvm", "rust", &["--wrap", "--emit", "rust"], "<.");
+    assert!(!output.status.success());
+    let output = sbfnj("--o0", "grow", &["--wrap", "--grow"], "<.");
+    assert!(!output.status.success());
+    // a tape needs a cell for the pointer to start on
+    let output = sbfnj("--o0", "empty", &["--max-mem", "0"], ".");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("invalid value '0' for '--max-mem"),
+        "{stderr}"
+    );
+}
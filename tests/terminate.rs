@@ -0,0 +1,29 @@
+//! o2 warns about loops that provably never exit once entered, like `+[]`, but still
+//! compiles them, and says nothing about loops that do exit.
+
+mod common;
+
+use common::{sbfnj, source};
+
+fn warnings(name: &str, src: &str) -> String {
+    let prog = source("terminate", &format!("{name}.b"), src);
+    String::from_utf8(sbfnj(&["--o2", "--text"], prog, b"").stderr).unwrap()
+}
+
+const WARNING: &str = "never exits, program may not terminate";
+
+#[test]
+fn infinite() {
+    assert!(warnings("empty", "+[]").contains(&format!("loop at 1:2 {WARNING}")));
+    // an odd guard stepped by 2 skips zero forever
+    assert!(warnings("odd", "+\n++[>+<++]").contains(&format!("loop at 2:3 {WARNING}")));
+}
+
+#[test]
+fn terminating() {
+    // wraps to zero after 255 iterations
+    assert_eq!(warnings("wrap", "+[+]"), "");
+    assert_eq!(warnings("even", "++[++]"), "");
+    // the guard is read from input, so it may be zero
+    assert_eq!(warnings("input", ",[>+<]"), "");
+}
@@ -0,0 +1,44 @@
+//! `--emit tokens` lists every command byte with its offset in the source, before the
+//! parser coalesces runs, skipping comments and whitespace.
+
+mod common;
+
+use std::process::Output;
+
+use common::source;
+
+const SRC: &str = "++ comment\n>[-]\n$.%";
+
+fn sbfnj(name: &str, args: &[&str]) -> Output {
+    let args = [&["--emit", "tokens"], args].concat();
+    common::run(&args, source("tokens", &format!("{name}.b"), SRC), b"")
+}
+
+#[test]
+fn offsets() {
+    let output = sbfnj("plain", &[]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0 +\n1 +\n11 >\n12 [\n13 -\n14 ]\n17 .\n"
+    );
+    // the same whatever backend is picked, nothing runs
+    assert_eq!(sbfnj("o2", &["--o2"]).stdout, sbfnj("plain", &[]).stdout);
+}
+
+#[test]
+fn ext() {
+    let output = sbfnj("ext", &["--ext"]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .ends_with("14 ]\n16 $\n17 .\n18 %\n")
+    );
+}
+
+#[test]
+fn strict() {
+    let output = sbfnj("strict", &["--strict"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Stray 'c' at 1:4"));
+}
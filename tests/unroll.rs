@@ -0,0 +1,35 @@
+//! `--unroll N` replaces a loop whose guard is known on entry and runs at most N times with
+//! that many copies of its body, then clears the guard.
+
+mod common;
+
+use common::{source, text};
+
+fn unrolled(name: &str, unroll: &str, src: &str) -> String {
+    let prog = source("unroll", &format!("{name}.b"), src);
+    // alone, so neither the other loop passes nor the output pass get to the loop first
+    let args = [
+        "--o2",
+        "--text",
+        "--unroll",
+        unroll,
+        "--opt-passes",
+        "unroll",
+    ];
+    text(&args, prog)
+}
+
+#[test]
+fn three_copies() {
+    let body = "add ptr, 1\nadd val, 1\nadd ptr, -1\nadd val, -1\n";
+    assert_eq!(
+        unrolled("three", "4", "+++[>+<-]"),
+        format!("add val, 3\n{}set val, 0\n", body.repeat(3))
+    );
+}
+
+#[test]
+fn over_limit() {
+    // three trips are more than --unroll 2 allows
+    assert!(unrolled("over", "2", "+++[>+<-]").contains("loop {"));
+}
@@ -0,0 +1,88 @@
+//! 8-bit wrapping arithmetic must agree across backends, in particular for deltas and
+//! factors outside `i8` that the optimizer folds into single constants.
+
+mod common;
+
+use common::{sbfnj, source};
+
+/// Expected output of each program: 300 `+`, 300 `-`, a 5 * 55 multiply loop, a
+/// multiply by -3 and a clear followed by 300 `+`
+fn cases() -> Vec<(&'static str, String, u8)> {
+    vec![
+        ("inc", "+".repeat(300) + ".", 44),
+        ("dec", "-".repeat(300) + ".", 212),
+        ("mul", format!("+++++[>{}<-]>.", "+".repeat(55)), 19),
+        ("neg", "+++++++[>---<-]>.".to_owned(), 235),
+        ("set", format!("+[-]{}.", "+".repeat(300)), 44),
+    ]
+}
+
+fn run(args: &[&str], name: &str, src: &str) -> Vec<u8> {
+    let prog = source("wrapping", &format!("{name}{}.b", args.concat()), src);
+    sbfnj(args, prog, b"").stdout
+}
+
+fn check(backend: &str) {
+    for (name, src, expected) in cases() {
+        assert_eq!(
+            run(&[backend], name, &src),
+            [expected],
+            "{name} on {backend}"
+        );
+    }
+}
+
+#[test]
+fn o0() {
+    check("--o0");
+}
+
+#[test]
+fn o1() {
+    check("--o1");
+}
+
+#[test]
+fn o2() {
+    check("--o2");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn llvm() {
+    check("--llvm");
+}
+
+/// `.` writes the raw byte even when cells are shown as signed
+#[cfg(feature = "clang")]
+#[test]
+fn llvm_signed_cells() {
+    for (name, src, expected) in cases() {
+        let output = run(&["--llvm", "--signed-cells"], name, &src);
+        assert_eq!(output, [expected], "{name}");
+    }
+}
+
+/// 257 `+` after a read, so the run stays one `ValInc(257)` up to the backends
+fn coalesced(args: &[&str], name: &str) -> Vec<u8> {
+    let src = format!(",{}.", "+".repeat(257));
+    let prog = source("wrapping", &format!("{name}.b"), &src);
+    // the program reads the ',' it starts with
+    sbfnj(args, prog, src.as_bytes()).stdout
+}
+
+#[test]
+fn coalesced_257() {
+    let text = String::from_utf8(coalesced(&["--o2", "--text"], "text-257")).unwrap();
+    assert_eq!(text, "in\nadd val, 257\nout\n");
+    assert_eq!(coalesced(&["--o2"], "o2-257"), b"-");
+    let ir = String::from_utf8(coalesced(&["--llvm", "--text"], "ir-257")).unwrap();
+    assert!(ir.contains(" = add i8 %") && ir.contains(", 1\n"), "{ir}");
+    assert!(!ir.contains(", 257") && !ir.contains(", -255"), "{ir}");
+}
+
+#[cfg(feature = "clang")]
+#[test]
+fn coalesced_257_llvm() {
+    assert_eq!(coalesced(&["--llvm", "--no-cache"], "llvm-257"), b"-");
+}
@@ -0,0 +1,46 @@
+//! `--xref` follows the o1 listing with every loop's `[` and `]` lines and its depth.
+
+mod common;
+
+use common::{source, text};
+
+fn xref(name: &str, src: &str, args: &[&str]) -> Vec<String> {
+    let args = [&["--o1", "--text", "--xref"], args].concat();
+    let text = text(&args, source("xref", name, src));
+    let (_, footer) = text.split_once("loops:\n").unwrap();
+    footer.lines().map(|line| line.trim().to_owned()).collect()
+}
+
+#[test]
+fn pairs() {
+    // the clear loop becomes `set 0` and is not listed
+    let src = "+[>,[.,]<[-]-]>>[.]";
+    assert_eq!(
+        xref("pairs.b", src, &[]),
+        [
+            "[ @1  -> ] @11 depth 1",
+            "[ @4  -> ] @7  depth 2",
+            "[ @13 -> ] @15 depth 1"
+        ]
+    );
+    assert_eq!(xref("hex.b", src, &["--hex"])[2], "[ @d  -> ] @f  depth 1");
+}
+
+#[test]
+fn deep() {
+    let n = 1000;
+    let src = format!("+{}.{}", "[".repeat(n), "]".repeat(n));
+    let footer = xref("deep.b", &src, &[]);
+    assert_eq!(footer.len(), n);
+    for (i, line) in footer.iter().enumerate() {
+        let (start, end) = (i + 1, 2 * n + 1 - i);
+        let expected = format!("[ @{start:<4} -> ] @{end:<4} depth {}", i + 1);
+        assert_eq!(line.trim_end(), expected.trim_end(), "loop {i}");
+    }
+}
+
+#[test]
+fn requires_text() {
+    let output = common::run(&["--o1", "--xref"], "-", b"");
+    assert!(!output.status.success());
+}